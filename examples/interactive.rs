@@ -8,7 +8,10 @@
 //!   /rx1r, /rx2r         Set RX reverse stereo
 //!   /aux <port> <value>  Set AUX output (e.g. /aux 1 4)
 //!   /qaux <port>         Query AUX port value
+//!   /qtx                 Query TX focus
+//!   /qrx                 Query RX routing
 //!   /name                Query device name
+//!   /version             Query firmware version
 //!   /raw <cmd>           Send raw command string
 //!   /info                Print device info and capabilities
 //!   /help                Print command list
@@ -43,6 +46,9 @@ async fn main() -> otrsp::Result<()> {
     if let Some(p) = &device.info().port {
         eprintln!("Port: {p}");
     }
+    if let Some(baud) = device.info().baud_rate {
+        eprintln!("Baud: {baud}");
+    }
     eprintln!();
     eprintln!("Type /help for command list, /quit to exit.");
     eprintln!();
@@ -94,7 +100,10 @@ async fn main() -> otrsp::Result<()> {
                 eprintln!("  /rx1r, /rx2r         Set RX reverse stereo");
                 eprintln!("  /aux <port> <value>  Set AUX output (e.g. /aux 1 4)");
                 eprintln!("  /qaux <port>         Query AUX port value");
+                eprintln!("  /qtx                 Query TX focus");
+                eprintln!("  /qrx                 Query RX routing");
                 eprintln!("  /name                Query device name");
+                eprintln!("  /version             Query firmware version");
                 eprintln!("  /raw <cmd>           Send raw command string");
                 eprintln!("  /info                Print device info and capabilities");
                 eprintln!("  /help                Print command list");
@@ -153,10 +162,22 @@ async fn main() -> otrsp::Result<()> {
                     Err(_) => eprintln!("Usage: /qaux <port> (e.g. /qaux 1)"),
                 }
             }
+            "/qtx" => match device.query_tx().await {
+                Ok(radio) => eprintln!("TX: {radio:?}"),
+                Err(e) => eprintln!("Error: {e}"),
+            },
+            "/qrx" => match device.query_rx().await {
+                Ok((radio, mode)) => eprintln!("RX: {radio:?} {mode:?}"),
+                Err(e) => eprintln!("Error: {e}"),
+            },
             "/name" => match device.device_name().await {
                 Ok(name) => eprintln!("Device name: {name}"),
                 Err(e) => eprintln!("Error: {e}"),
             },
+            "/version" => match device.device_version().await {
+                Ok(version) => eprintln!("Firmware: {version}"),
+                Err(e) => eprintln!("Error: {e}"),
+            },
             "/raw" => {
                 let raw_cmd = line.strip_prefix("/raw").unwrap().trim();
                 if raw_cmd.is_empty() {
@@ -175,9 +196,14 @@ async fn main() -> otrsp::Result<()> {
                 if let Some(p) = &info.port {
                     eprintln!("Port: {p}");
                 }
+                if let Some(fw) = &info.firmware {
+                    eprintln!("Firmware: {fw}");
+                }
                 eprintln!("Stereo: {}", caps.stereo);
                 eprintln!("Reverse stereo: {}", caps.reverse_stereo);
                 eprintln!("AUX ports: {}", caps.aux_ports);
+                eprintln!("AUX value max: {}", caps.aux_value_max);
+                eprintln!("Mixed RX: {}", caps.mixed);
             }
             "/quit" | "/exit" | "/q" => {
                 break;
@@ -0,0 +1,187 @@
+//! Dueling-CQ / 2BSIQ alternation: move TX focus between radios on a cadence.
+//!
+//! Calling CQ on both radios means flipping TX focus after every call. An
+//! [`Alternator`] does the flipping on a fixed cadence and reports each flip
+//! as an [`AlternatorEvent`], so a practice tool or keyer only has to follow
+//! along. It can be paused while working a caller, and a manual selection
+//! restarts the cadence from the chosen radio.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::follower::sleep_until_some;
+use crate::switch::So2rSwitch;
+use crate::types::Radio;
+
+/// What an [`Alternator`] did, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AlternatorEvent {
+    /// TX focus moved to `radio` on the cadence.
+    Alternated { radio: Radio },
+    /// TX focus moved to `radio` by [`Alternator::select()`].
+    Selected { radio: Radio },
+    /// Alternation stopped; TX focus stays where it is.
+    Paused,
+    /// Alternation resumed; the next flip is a full cadence away.
+    Resumed,
+    /// Moving TX focus to `radio` failed. Alternation carries on.
+    Failed { radio: Radio, error: String },
+}
+
+/// Configures and spawns an [`Alternator`].
+pub struct AlternatorBuilder {
+    cadence: Duration,
+    start: Radio,
+    paused: bool,
+}
+
+impl AlternatorBuilder {
+    /// Radio given TX focus first (default: Radio 1).
+    pub fn start(mut self, radio: Radio) -> Self {
+        self.start = radio;
+        self
+    }
+
+    /// Start paused, waiting for [`Alternator::resume()`] (default: false).
+    pub fn paused(mut self, paused: bool) -> Self {
+        self.paused = paused;
+        self
+    }
+
+    /// Spawn the alternator task driving `switch`.
+    ///
+    /// Unless paused, TX focus moves to the start radio at once.
+    pub fn spawn<S>(self, switch: Arc<S>) -> Alternator
+    where
+        S: So2rSwitch + ?Sized + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(16);
+        tokio::spawn(alternate(switch, rx, events.clone(), self));
+        Alternator { tx, events }
+    }
+}
+
+enum Control {
+    Pause,
+    Resume,
+    Select(Radio),
+}
+
+/// Handle to a running alternator task.
+///
+/// Clones control the same task, which stops once every handle is dropped.
+#[derive(Clone)]
+pub struct Alternator {
+    tx: mpsc::UnboundedSender<Control>,
+    events: broadcast::Sender<AlternatorEvent>,
+}
+
+impl Alternator {
+    /// Start configuring an alternator that flips TX focus every `cadence`.
+    pub fn builder(cadence: Duration) -> AlternatorBuilder {
+        AlternatorBuilder {
+            cadence,
+            start: Radio::Radio1,
+            paused: false,
+        }
+    }
+
+    /// Stop alternating, leaving TX focus where it is.
+    pub fn pause(&self) {
+        let _ = self.tx.send(Control::Pause);
+    }
+
+    /// Resume alternating after [`pause()`](Self::pause).
+    pub fn resume(&self) {
+        let _ = self.tx.send(Control::Resume);
+    }
+
+    /// Give `radio` TX focus now and restart the cadence from there.
+    ///
+    /// A paused alternator stays paused.
+    pub fn select(&self, radio: Radio) {
+        let _ = self.tx.send(Control::Select(radio));
+    }
+
+    /// Subscribe to alternation events.
+    pub fn subscribe(&self) -> broadcast::Receiver<AlternatorEvent> {
+        self.events.subscribe()
+    }
+}
+
+async fn alternate<S>(
+    switch: Arc<S>,
+    mut rx: mpsc::UnboundedReceiver<Control>,
+    events: broadcast::Sender<AlternatorEvent>,
+    config: AlternatorBuilder,
+) where
+    S: So2rSwitch + ?Sized,
+{
+    let AlternatorBuilder {
+        cadence,
+        start: mut radio,
+        mut paused,
+    } = config;
+    let mut next = Instant::now();
+
+    loop {
+        tokio::select! {
+            control = rx.recv() => {
+                let Some(control) = control else { break };
+                match control {
+                    Control::Pause if !paused => {
+                        paused = true;
+                        let _ = events.send(AlternatorEvent::Paused);
+                    }
+                    Control::Resume if paused => {
+                        paused = false;
+                        next = Instant::now() + cadence;
+                        let _ = events.send(AlternatorEvent::Resumed);
+                    }
+                    Control::Pause | Control::Resume => {}
+                    Control::Select(selected) => {
+                        debug!(radio = ?selected, "alternator override");
+                        let event = AlternatorEvent::Selected { radio: selected };
+                        focus(&*switch, selected, event, &events).await;
+                        radio = selected.other();
+                        next = Instant::now() + cadence;
+                    }
+                }
+            }
+            _ = sleep_until_some((!paused).then_some(next)) => {
+                focus(&*switch, radio, AlternatorEvent::Alternated { radio }, &events).await;
+                radio = radio.other();
+                // Keep the cadence steady, without bursts after a slow switch.
+                next = (next + cadence).max(Instant::now());
+            }
+        }
+    }
+}
+
+/// Move TX focus to `radio`, reporting `event` or the failure.
+async fn focus<S>(
+    switch: &S,
+    radio: Radio,
+    event: AlternatorEvent,
+    events: &broadcast::Sender<AlternatorEvent>,
+) where
+    S: So2rSwitch + ?Sized,
+{
+    let event = match switch.set_tx(radio).await {
+        Ok(()) => event,
+        Err(e) => {
+            warn!(?radio, "alternator TX switch failed: {e}");
+            AlternatorEvent::Failed {
+                radio,
+                error: e.to_string(),
+            }
+        }
+    };
+    let _ = events.send(event);
+}
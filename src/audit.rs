@@ -0,0 +1,164 @@
+//! Append-only JSONL journal of switch events.
+//!
+//! Each line is one JSON object with a `ts` field (milliseconds since the
+//! Unix epoch) and an `event` field, plus event-specific fields:
+//!
+//! ```text
+//! {"ts":1760601600000,"event":"open","port":"/dev/ttyUSB0"}
+//! {"ts":1760601600000,"event":"connected"}
+//! {"ts":1760601600120,"event":"tx","radio":2,"origin":"host"}
+//! {"ts":1760601600125,"event":"rx","radio":1,"mode":"stereo","origin":"host"}
+//! {"ts":1760601600300,"event":"aux","port":1,"value":4,"origin":"device"}
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::error::Result;
+use crate::event::{Origin, SwitchEvent};
+use crate::types::{Radio, RxMode};
+
+/// Open (or create) the journal at `path` for appending.
+pub fn open(path: &Path) -> Result<File> {
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
+
+/// Append every event from `events` to `file` until the channel closes.
+///
+/// An `open` record naming `port` is written first. With `connected` set,
+/// a `connected` record follows, as the journal starts on a live link and
+/// no `Connected` event is broadcast for it. If the writer falls behind,
+/// a `lagged` record notes how many events were lost. The writer runs on
+/// the blocking pool, so a slow disk never stalls the runtime.
+pub fn spawn(
+    mut file: File,
+    port: &str,
+    connected: bool,
+    mut events: broadcast::Receiver<SwitchEvent>,
+) -> JoinHandle<()> {
+    let opened = format!(r#""event":"open","port":{}"#, json_string(port));
+    tokio::task::spawn_blocking(move || {
+        append(&mut file, &opened);
+        if connected {
+            append(&mut file, &event_fields(&SwitchEvent::Connected));
+        }
+        loop {
+            match events.blocking_recv() {
+                Ok(event) => {
+                    append(&mut file, &event_fields(&event));
+                }
+                Err(RecvError::Lagged(n)) => {
+                    append(&mut file, &format!(r#""event":"lagged","missed":{n}"#));
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+/// Current time in milliseconds since the Unix epoch.
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn append(file: &mut File, fields: &str) {
+    let ts = now_ms();
+    let line = format!("{{\"ts\":{ts},{fields}}}\n");
+    if let Err(e) = file.write_all(line.as_bytes()) {
+        warn!("audit log write failed: {e}");
+    }
+}
+
+/// JSON fields (without braces) describing `event`.
+pub(crate) fn event_fields(event: &SwitchEvent) -> String {
+    match event {
+        SwitchEvent::TxChanged { radio, origin } => format!(
+            r#""event":"tx","radio":{},"origin":"{}""#,
+            radio_number(*radio),
+            origin_name(*origin)
+        ),
+        SwitchEvent::RxChanged {
+            radio,
+            mode,
+            origin,
+        } => format!(
+            r#""event":"rx","radio":{},"mode":"{}","origin":"{}""#,
+            radio_number(*radio),
+            mode_name(*mode),
+            origin_name(*origin)
+        ),
+        SwitchEvent::AuxChanged {
+            port,
+            value,
+            origin,
+        } => format!(
+            r#""event":"aux","port":{port},"value":{value},"origin":"{}""#,
+            origin_name(*origin)
+        ),
+        SwitchEvent::KeyingChanged { radio, origin } => format!(
+            r#""event":"keying","radio":{},"origin":"{}""#,
+            radio_number(*radio),
+            origin_name(*origin)
+        ),
+        SwitchEvent::Connected => r#""event":"connected""#.to_string(),
+        SwitchEvent::Disconnected => r#""event":"disconnected""#.to_string(),
+        SwitchEvent::DeviceReset => r#""event":"device_reset""#.to_string(),
+        SwitchEvent::FootswitchChanged { pressed } => {
+            format!(r#""event":"footswitch","pressed":{pressed}"#)
+        }
+        SwitchEvent::PttChanged { radio } => {
+            format!(r#""event":"ptt","radio":{}"#, radio.map_or(0, radio_number))
+        }
+        SwitchEvent::FailedOver => r#""event":"failed_over""#.to_string(),
+        SwitchEvent::Reconnecting => r#""event":"reconnecting""#.to_string(),
+        SwitchEvent::Reconnected => r#""event":"reconnected""#.to_string(),
+        SwitchEvent::IoTaskFailed => r#""event":"io_task_failed""#.to_string(),
+    }
+}
+
+pub(crate) fn radio_number(radio: Radio) -> u8 {
+    match radio {
+        Radio::Radio1 => 1,
+        Radio::Radio2 => 2,
+    }
+}
+
+pub(crate) fn origin_name(origin: Origin) -> &'static str {
+    match origin {
+        Origin::Host => "host",
+        Origin::Device => "device",
+    }
+}
+
+pub(crate) fn mode_name(mode: RxMode) -> &'static str {
+    match mode {
+        RxMode::Mono => "mono",
+        RxMode::Stereo => "stereo",
+        RxMode::ReverseStereo => "reverse_stereo",
+        RxMode::Mixed => "mixed",
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,146 @@
+//! Frequency to band mapping.
+//!
+//! A [`BandPlan`] turns a rig frequency into a [`Band`], using the IARU
+//! region allocations plus any custom ranges registered on top. Combined with
+//! a [`BcdMap`] it yields the AUX value for a frequency, ready to drive a
+//! [`BandFollower`](crate::follower::BandFollower):
+//!
+//! ```
+//! use otrsp::bandplan::{BandPlan, Region};
+//! use otrsp::follower::BandFollower;
+//! use otrsp::protocol::BcdMap;
+//!
+//! let plan = BandPlan::iaru(Region::Iaru2);
+//! let builder = BandFollower::builder(plan.aux_map(BcdMap::yaesu()));
+//! # let _ = builder;
+//! ```
+
+use std::ops::RangeInclusive;
+
+use crate::protocol::BcdMap;
+use crate::types::Band;
+
+/// IARU region whose allocations a [`BandPlan`] follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// Europe, Africa, Middle East, northern Asia.
+    Iaru1,
+    /// The Americas.
+    Iaru2,
+    /// Asia-Pacific.
+    Iaru3,
+}
+
+/// Frequency ranges (Hz, inclusive) assigned to bands.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BandPlan {
+    ranges: Vec<(RangeInclusive<u64>, Band)>,
+}
+
+impl BandPlan {
+    /// A plan with no ranges; every frequency is out of band.
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// The amateur allocations of an IARU region.
+    pub fn iaru(region: Region) -> Self {
+        let (m160, m80, m60, m40, m6) = match region {
+            Region::Iaru1 => (
+                1_810_000,
+                3_800_000,
+                5_351_500..=5_366_500,
+                7_200_000,
+                52_000_000,
+            ),
+            Region::Iaru2 => (
+                1_800_000,
+                4_000_000,
+                5_330_500..=5_406_500,
+                7_300_000,
+                54_000_000,
+            ),
+            Region::Iaru3 => (
+                1_800_000,
+                3_900_000,
+                5_351_500..=5_366_500,
+                7_200_000,
+                54_000_000,
+            ),
+        };
+        let ranges = vec![
+            (m160..=2_000_000, Band::M160),
+            (3_500_000..=m80, Band::M80),
+            (m60, Band::M60),
+            (7_000_000..=m40, Band::M40),
+            (10_100_000..=10_150_000, Band::M30),
+            (14_000_000..=14_350_000, Band::M20),
+            (18_068_000..=18_168_000, Band::M17),
+            (21_000_000..=21_450_000, Band::M15),
+            (24_890_000..=24_990_000, Band::M12),
+            (28_000_000..=29_700_000, Band::M10),
+            (50_000_000..=m6, Band::M6),
+        ];
+        Self { ranges }
+    }
+
+    /// Register a custom range for `band`, e.g. a national allocation.
+    ///
+    /// Ranges registered later take precedence where they overlap.
+    pub fn with_range(mut self, band: Band, low_hz: u64, high_hz: u64) -> Self {
+        self.ranges.push((low_hz..=high_hz, band));
+        self
+    }
+
+    /// Band containing `freq_hz`, if any.
+    pub fn band(&self, freq_hz: u64) -> Option<Band> {
+        self.ranges
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&freq_hz))
+            .map(|&(_, band)| band)
+    }
+
+    /// Frequency to AUX value mapping through `bcd`, for
+    /// [`BandFollower::builder()`](crate::follower::BandFollower::builder).
+    pub fn aux_map(self, bcd: BcdMap) -> impl Fn(u64) -> Option<u8> + Send + Sync + 'static {
+        move |freq_hz| self.band(freq_hz).map(|band| bcd.code(band))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_edges() {
+        let r1 = BandPlan::iaru(Region::Iaru1);
+        let r2 = BandPlan::iaru(Region::Iaru2);
+        assert_eq!(r1.band(14_025_000), Some(Band::M20));
+        assert_eq!(r1.band(7_250_000), None);
+        assert_eq!(r2.band(7_250_000), Some(Band::M40));
+        assert_eq!(r1.band(1_805_000), None);
+        assert_eq!(r2.band(1_805_000), Some(Band::M160));
+        assert_eq!(r2.band(3_950_000), Some(Band::M80));
+        assert_eq!(BandPlan::iaru(Region::Iaru3).band(3_950_000), None);
+        assert_eq!(r2.band(53_000_000), Some(Band::M6));
+        assert_eq!(r1.band(11_000_000), None);
+    }
+
+    #[test]
+    fn test_custom_range_takes_precedence() {
+        let plan = BandPlan::iaru(Region::Iaru1).with_range(Band::M40, 7_000_000, 7_300_000);
+        assert_eq!(plan.band(7_250_000), Some(Band::M40));
+        let plan = BandPlan::empty().with_range(Band::M20, 14_000_000, 14_350_000);
+        assert_eq!(plan.band(14_000_000), Some(Band::M20));
+        assert_eq!(plan.band(7_000_000), None);
+    }
+
+    #[test]
+    fn test_aux_map() {
+        let map = BandPlan::iaru(Region::Iaru2).aux_map(BcdMap::yaesu());
+        assert_eq!(map(14_074_000), Some(5));
+        assert_eq!(map(28_400_000), Some(9));
+        assert_eq!(map(12_000_000), None);
+    }
+}
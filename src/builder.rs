@@ -1,15 +1,36 @@
 //! OtrspBuilder: configure and connect to an OTRSP device.
 
-use tokio::io::{AsyncRead, AsyncWrite};
-use tokio::sync::broadcast;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU32;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, mpsc};
+use tokio_serial::{ClearBuffer, SerialPort};
 use tracing::{debug, info, warn};
 
+use crate::audit;
 use crate::device::OtrspDevice;
-use crate::error::Result;
+use crate::discovery::{self, DetectEvent, PortFilter};
+use crate::error::{Error, Result};
 use crate::event::SwitchEvent;
-use crate::io::spawn_io_task;
-use crate::switch::{SwitchCapabilities, SwitchInfo};
-use crate::transport;
+use crate::io::{IoConfig, IoHandle, Restart, read_line, spawn_io_task};
+use crate::latch::{FootswitchLatch, TransmitLatch};
+use crate::protocol::{self, BcdMap, DeviceIdentity, ParseMode};
+use crate::queue::QueuePolicy;
+use crate::reconnect::ReconnectingPort;
+use crate::rfc2217::{Rfc2217Connector, Rfc2217Settings};
+use crate::state::{self, SwitchState};
+use crate::stats::StatsCounters;
+use crate::switch::{ProtocolFeatures, SwitchCapabilities, SwitchInfo};
+use crate::tap::{TapPort, WireDirection, WireTap};
+use crate::transcript::Transcript;
+use crate::transport::{
+    self, BoxedTransport, ConfigureSerial, Connector, DtrControl, DtrPort, PortInfo, PortLock,
+    SerialConnector, SerialPortBuilder, TcpConnector,
+};
+use crate::types::{Radio, RxFollow};
 
 /// Builder for creating an OTRSP device connection.
 ///
@@ -26,7 +47,85 @@ use crate::transport;
 /// ```
 pub struct OtrspBuilder {
     port_path: String,
+    link: Link,
     query_name: bool,
+    name_retries: u32,
+    settle_delay: Duration,
+    query_version: bool,
+    io_config: IoConfig,
+    negotiate: bool,
+    capabilities: SwitchCapabilities,
+    footswitch_latch: bool,
+    transmit_latch: bool,
+    configure_serial: Option<ConfigureSerial>,
+    exclusive: bool,
+    skip_redundant: bool,
+    best_effort_rx: bool,
+    rx_follow: Option<RxFollow>,
+    ptt_lead: Duration,
+    ptt_tail: Duration,
+    emit_connected: bool,
+    lock_dir: Option<PathBuf>,
+    auto_baud: bool,
+    reset_after_timeouts: u32,
+    offline_queue: bool,
+    bcd_map: BcdMap,
+    band_decoders: [Option<(u8, BcdMap)>; 2],
+    audit_path: Option<PathBuf>,
+    transcript_capacity: usize,
+    transcript_path: Option<PathBuf>,
+    port_filter: PortFilter,
+    detect_name: Option<String>,
+    detect_progress: Option<mpsc::UnboundedSender<DetectEvent>>,
+    wire_tap: Option<WireTap>,
+    reconnect: bool,
+    reconnect_delay: Duration,
+    reconnect_attempts: Option<u32>,
+    dtr_pulse: Duration,
+    bootloader_delay: Duration,
+    /// DTR line of the serial port opened by the build, if any.
+    dtr: Option<DtrControl>,
+    event_tx: broadcast::Sender<SwitchEvent>,
+}
+
+/// How [`OtrspBuilder::build()`] reaches the device.
+enum Link {
+    Serial,
+    Tcp,
+    Connector(Arc<dyn Connector>),
+    Rfc2217 {
+        host: String,
+        port: u16,
+        settings: Rfc2217Settings,
+    },
+    #[cfg(feature = "tls")]
+    Tls {
+        host: String,
+        port: u16,
+        config: Option<Arc<transport::rustls::ClientConfig>>,
+    },
+}
+
+impl Link {
+    /// The connector for a network or custom link (`None` for serial).
+    fn connector(&self, addr: &str) -> Option<Arc<dyn Connector>> {
+        match self {
+            Link::Serial => None,
+            Link::Tcp => Some(Arc::new(TcpConnector::with_addr(addr))),
+            Link::Connector(connector) => Some(connector.clone()),
+            Link::Rfc2217 {
+                host,
+                port,
+                settings,
+            } => Some(Arc::new(Rfc2217Connector::new(host, *port, *settings))),
+            #[cfg(feature = "tls")]
+            Link::Tls { host, port, config } => Some(Arc::new(transport::TlsConnector::new(
+                host,
+                *port,
+                config.clone(),
+            ))),
+        }
+    }
 }
 
 impl OtrspBuilder {
@@ -34,20 +133,834 @@ impl OtrspBuilder {
     pub fn new(port: &str) -> Self {
         Self {
             port_path: port.to_string(),
+            link: Link::Serial,
             query_name: true,
+            name_retries: 0,
+            settle_delay: Duration::ZERO,
+            query_version: false,
+            io_config: IoConfig::default(),
+            negotiate: false,
+            capabilities: SwitchCapabilities::default(),
+            footswitch_latch: false,
+            transmit_latch: false,
+            configure_serial: None,
+            exclusive: true,
+            skip_redundant: false,
+            best_effort_rx: false,
+            rx_follow: None,
+            ptt_lead: Duration::ZERO,
+            ptt_tail: Duration::ZERO,
+            emit_connected: true,
+            lock_dir: None,
+            auto_baud: false,
+            reset_after_timeouts: 0,
+            offline_queue: false,
+            bcd_map: BcdMap::default(),
+            band_decoders: [None, None],
+            audit_path: None,
+            transcript_capacity: 0,
+            transcript_path: None,
+            port_filter: PortFilter::new(),
+            detect_name: None,
+            detect_progress: None,
+            wire_tap: None,
+            reconnect: false,
+            reconnect_delay: Duration::from_secs(1),
+            reconnect_attempts: None,
+            dtr_pulse: Duration::from_millis(100),
+            bootloader_delay: Duration::from_secs(2),
+            dtr: None,
+            event_tx: broadcast::channel(64).0,
+        }
+    }
+
+    /// Create a new builder for a switch paired over Bluetooth serial (SPP).
+    ///
+    /// Operating systems expose an SPP link as a serial device, so `path` is
+    /// what the OS created when pairing: `/dev/rfcomm0` on Linux (after
+    /// `rfcomm bind 0 <address>`), `/dev/cu.<name>` on macOS, or the outgoing
+    /// `COMn` port on Windows. [`list_ports()`](transport::list_ports) flags
+    /// such ports with [`PortInfo::is_bluetooth()`].
+    ///
+    /// Bluetooth links drop when the radio fades or the box sleeps, so
+    /// [`reconnect()`](Self::reconnect) starts out enabled. The baud rate is
+    /// ignored by SPP.
+    pub fn new_rfcomm(path: &str) -> Self {
+        Self {
+            reconnect: true,
+            ..Self::new(path)
+        }
+    }
+
+    /// Create a new builder for a device reachable over TCP (ser2net, Wi-Fi
+    /// serial bridges).
+    ///
+    /// [`build()`](Self::build) connects instead of opening a serial port;
+    /// serial-only options such as [`auto_baud()`](Self::auto_baud) are
+    /// ignored.
+    pub fn new_tcp(host: &str, port: u16) -> Self {
+        Self {
+            link: Link::Tcp,
+            ..Self::new(&transport::tcp_addr(host, port))
+        }
+    }
+
+    /// Create a new builder that opens the link through `connector`.
+    ///
+    /// `name` stands in for the port path in [`SwitchInfo::port`] and port
+    /// locking. Serial-only options are ignored.
+    pub fn from_connector(name: &str, connector: impl Connector + 'static) -> Self {
+        Self {
+            link: Link::Connector(Arc::new(connector)),
+            ..Self::new(name)
+        }
+    }
+
+    /// Create a new builder for a device behind an RFC 2217 (Telnet COM port
+    /// control) server, such as ser2net in `telnet` mode.
+    ///
+    /// Unlike [`new_tcp()`](Self::new_tcp), the far-end serial port is set
+    /// to 9600 8N1 with DTR and RTS low on connect; see
+    /// [`rfc2217_settings()`](Self::rfc2217_settings).
+    pub fn new_rfc2217(host: &str, port: u16) -> Self {
+        Self {
+            link: Link::Rfc2217 {
+                host: host.to_string(),
+                port,
+                settings: Rfc2217Settings::default(),
+            },
+            ..Self::new(&transport::tcp_addr(host, port))
+        }
+    }
+
+    /// Far-end baud rate and modem lines for a
+    /// [`new_rfc2217()`](Self::new_rfc2217) builder (default: 9600 baud,
+    /// DTR and RTS low).
+    ///
+    /// Ignored for other builders.
+    pub fn rfc2217_settings(mut self, rfc2217: Rfc2217Settings) -> Self {
+        if let Link::Rfc2217 { settings, .. } = &mut self.link {
+            *settings = rfc2217;
+        }
+        self
+    }
+
+    /// Create a new builder for a device reachable over TLS, e.g. a remote
+    /// station behind a TLS-terminating proxy.
+    ///
+    /// The server certificate must be valid for `host`. Trusts the Mozilla
+    /// root set unless [`tls_config()`](Self::tls_config) supplies other roots.
+    #[cfg(feature = "tls")]
+    pub fn new_tls(host: &str, port: u16) -> Self {
+        Self {
+            link: Link::Tls {
+                host: host.to_string(),
+                port,
+                config: None,
+            },
+            ..Self::new(&transport::tcp_addr(host, port))
         }
     }
 
+    /// TLS client settings for a [`new_tls()`](Self::new_tls) builder, e.g.
+    /// to trust a private CA (default: Mozilla roots, no client certificate).
+    ///
+    /// Ignored for serial and plain TCP builders.
+    #[cfg(feature = "tls")]
+    pub fn tls_config(mut self, tls: Arc<transport::rustls::ClientConfig>) -> Self {
+        if let Link::Tls { config, .. } = &mut self.link {
+            *config = Some(tls);
+        }
+        self
+    }
+
     /// Whether to query the device name during build (default: true).
     pub fn query_name(mut self, enabled: bool) -> Self {
         self.query_name = enabled;
         self
     }
 
-    /// Build the OTRSP connection using a real serial port.
-    pub async fn build(self) -> Result<OtrspDevice> {
-        let port = transport::open_serial(&self.port_path)?;
-        self.build_with_port(port).await
+    /// Retry an unanswered `?NAME` query up to `retries` more times (default: 0).
+    ///
+    /// Each attempt waits up to the [`query_timeout()`](Self::query_timeout);
+    /// the name falls back to `"Unknown"` once all attempts have timed out.
+    pub fn name_retries(mut self, retries: u32) -> Self {
+        self.name_retries = retries;
+        self
+    }
+
+    /// Wait after opening the port before sending anything (default: 0).
+    ///
+    /// Arduino-based devices reset when the port opens and ignore commands
+    /// until their bootloader hands over, typically 1-2 seconds.
+    pub fn settle_delay(mut self, delay: Duration) -> Self {
+        self.settle_delay = delay;
+        self
+    }
+
+    /// DTR pulse length and bootloader wait used by
+    /// [`OtrspDevice::reset_hardware()`](crate::OtrspDevice::reset_hardware)
+    /// (default: 100 ms pulse, 2 s wait).
+    pub fn dtr_reset(mut self, pulse: Duration, bootloader_delay: Duration) -> Self {
+        self.dtr_pulse = pulse;
+        self.bootloader_delay = bootloader_delay;
+        self
+    }
+
+    /// Whether the device echoes every command back before answering (default: false).
+    ///
+    /// When enabled, echoed copies of sent commands are skipped so that
+    /// queries such as `?NAME` and `?AUX` return the real response.
+    pub fn echo_mode(mut self, enabled: bool) -> Self {
+        self.io_config.echo = enabled;
+        self
+    }
+
+    /// Skip unexpected lines ahead of a query answer (default: 0, off).
+    ///
+    /// For chatty devices that print a banner or debug output before
+    /// answering: a standard query (`?NAME`, `?AUXp`, ...) passes over up to
+    /// `max_lines` lines not starting with the expected prefix, for at most
+    /// `budget` of the [`query_timeout()`](Self::query_timeout). The line after that is
+    /// returned as the answer whatever it holds.
+    pub fn skip_unexpected_lines(mut self, max_lines: usize, budget: Duration) -> Self {
+        self.io_config.skip_lines = max_lines;
+        self.io_config.skip_budget = budget;
+        self
+    }
+
+    /// How long a query waits for its answer (default: 1s).
+    ///
+    /// Raise it for high-latency links, such as a remote station reached
+    /// over RFC 2217 or a VPN; lower it on a local port so a silent device
+    /// is noticed sooner. Also bounds each `?NAME` probe made by
+    /// [`auto_baud()`](Self::auto_baud) and [`detect()`](Self::detect).
+    pub fn query_timeout(mut self, timeout: Duration) -> Self {
+        self.io_config.query_timeout = timeout;
+        self
+    }
+
+    /// Retry writes that fail with a transient error (default: 1 attempt, no retry).
+    ///
+    /// A busy USB serial adapter may refuse a write with `EAGAIN` or `EINTR`.
+    /// Each command is tried up to `attempts` times, `delay` apart, before
+    /// the error reaches the caller. Only would-block, interrupted and
+    /// timed-out errors are retried; a vanished device still fails at once.
+    /// A retry resumes after the bytes already written, and callers wait
+    /// out the whole retry budget before timing out.
+    pub fn write_retry(mut self, attempts: u32, delay: Duration) -> Self {
+        self.io_config.write_attempts = attempts;
+        self.io_config.write_retry_delay = delay;
+        self
+    }
+
+    /// Whether to accept sloppy responses (default: false).
+    ///
+    /// Lowercase prefixes and stray whitespace (`aux1 4 `) are normalized
+    /// before parsing; see [`ParseMode::Permissive`](crate::protocol::ParseMode::Permissive).
+    pub fn permissive(mut self, enabled: bool) -> Self {
+        self.set_parse_mode(ParseMode::Permissive, enabled);
+        self
+    }
+
+    /// Whether to reject responses that deviate from the OTRSP grammar (default: false).
+    ///
+    /// Meant for validating new firmware: a query answer with the wrong
+    /// terminator, lowercase prefix or junk after the value fails with a
+    /// detailed [`Error::Protocol`]. See [`protocol::validate_response()`].
+    /// Overrides [`permissive()`](Self::permissive) and vice versa.
+    pub fn strict(mut self, enabled: bool) -> Self {
+        self.set_parse_mode(ParseMode::Strict, enabled);
+        self
+    }
+
+    /// Switch to `mode`, or back to standard parsing if `mode` is being disabled.
+    fn set_parse_mode(&mut self, mode: ParseMode, enabled: bool) {
+        if enabled {
+            self.io_config.parse_mode = mode;
+        } else if self.io_config.parse_mode == mode {
+            self.io_config.parse_mode = ParseMode::Standard;
+        }
+    }
+
+    /// Whether to query the firmware version during build (default: false).
+    ///
+    /// Fills [`SwitchInfo::firmware`] when the device answers `?VERSION`.
+    /// Off by default because devices without the query leave it unanswered,
+    /// adding a read timeout to every connect.
+    pub fn query_version(mut self, enabled: bool) -> Self {
+        self.query_version = enabled;
+        self
+    }
+
+    /// Whether to probe for protocol extensions after connecting (default: false).
+    ///
+    /// Negotiation issues `?EVENT` and `?PTT` probes and records the result in
+    /// [`OtrspDevice::features()`](crate::OtrspDevice::features). Each probe the
+    /// device leaves unanswered costs one [`query_timeout()`](Self::query_timeout)
+    /// during build.
+    pub fn negotiate(mut self, enabled: bool) -> Self {
+        self.negotiate = enabled;
+        self
+    }
+
+    /// Declare the device's capabilities (default: [`SwitchCapabilities::default()`]).
+    ///
+    /// OTRSP has no capability query, so non-standard features such as mixed
+    /// RX audio must be declared here.
+    pub fn capabilities(mut self, capabilities: SwitchCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Enable the footswitch latch (default: false).
+    ///
+    /// Driven by [`OtrspDevice::footswitch_press()`](crate::OtrspDevice::footswitch_press),
+    /// or by the device's own footswitch reports once events are enabled.
+    pub fn footswitch_latch(mut self, enabled: bool) -> Self {
+        self.footswitch_latch = enabled;
+        self
+    }
+
+    /// Enable the transmit latch (default: false).
+    ///
+    /// See [`OtrspDevice::set_transmitting()`](crate::OtrspDevice::set_transmitting).
+    pub fn transmit_latch(mut self, enabled: bool) -> Self {
+        self.transmit_latch = enabled;
+        self
+    }
+
+    /// Skip TX/RX/AUX commands whose target state already holds (default: false).
+    ///
+    /// When enabled, `set_tx`, `set_rx` and `set_aux` compare against the
+    /// cached [`SwitchState`](crate::SwitchState) and return `Ok(())` without
+    /// writing or emitting an event if nothing would change. Useful when a
+    /// logger re-asserts state on every keystroke.
+    pub fn skip_redundant(mut self, enabled: bool) -> Self {
+        self.skip_redundant = enabled;
+        self
+    }
+
+    /// Downgrade unsupported RX modes to mono instead of failing (default: false).
+    ///
+    /// By default `set_rx()` returns [`Error::Unsupported`](crate::Error::Unsupported)
+    /// for a mode the [capabilities](Self::capabilities) rule out.
+    pub fn best_effort_rx(mut self, enabled: bool) -> Self {
+        self.best_effort_rx = enabled;
+        self
+    }
+
+    /// Have every `set_tx()` also route RX audio to the new radio (default: off).
+    ///
+    /// Saves a second awaited call for the usual pattern of listening to the
+    /// radio you transmit on. [`swap()`](crate::So2rSwitch::swap) keeps
+    /// mirroring the RX mode as before. TX focus moved by PTT, whether a
+    /// `$PTT` report from the device or
+    /// [`report_ptt()`](crate::OtrspDevice::report_ptt), moves RX too,
+    /// unless the [`transmit_latch()`](Self::transmit_latch) is handling PTT.
+    pub fn rx_follows_tx(mut self, follow: RxFollow) -> Self {
+        self.rx_follow = Some(follow);
+        self
+    }
+
+    /// Sequencing delays around TX focus changes (default: none).
+    ///
+    /// When `set_tx()` changes radio it first waits until `tail` has elapsed
+    /// since the last [`report_unkey()`](crate::OtrspDevice::report_unkey),
+    /// then sends the command and waits `lead` for the relays to settle
+    /// before returning, so the caller can assert PTT as soon as it returns.
+    pub fn ptt_timing(mut self, lead: Duration, tail: Duration) -> Self {
+        self.ptt_lead = lead;
+        self.ptt_tail = tail;
+        self
+    }
+
+    /// Whether to emit the initial `Connected` event (default: true).
+    ///
+    /// No one can subscribe before `build()` returns, so instead of being
+    /// broadcast into the void, the event starts every
+    /// [`subscribe_connection()`](crate::So2rSwitch::subscribe_connection)
+    /// receiver, and every [`subscribe_filtered()`](crate::So2rSwitch::subscribe_filtered)
+    /// one whose filter takes it, while the link is up. Plain
+    /// [`subscribe()`](crate::So2rSwitch::subscribe) receivers only see
+    /// changes; the current link status is in
+    /// [`watch_state()`](crate::OtrspDevice::watch_state). The
+    /// [`audit_log()`](Self::audit_log) records it after its `open` line.
+    pub fn emit_connected(mut self, enabled: bool) -> Self {
+        self.emit_connected = enabled;
+        self
+    }
+
+    /// Take an advisory lock on the port, with lock files in `dir` (default: no lock).
+    ///
+    /// The lock is acquired before the port is touched and held until the
+    /// device is dropped; building fails with
+    /// [`Error::Transport`](crate::Error::Transport) if another process holds
+    /// it. See [`PortLock`] for the file naming convention.
+    pub fn advisory_lock(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.lock_dir = Some(dir.into());
+        self
+    }
+
+    /// Detect device reboots after this many consecutive unanswered queries (default: 0, off).
+    ///
+    /// A box that browns out stops answering, then comes back with default
+    /// routing. When a query is answered after at least `timeouts` in a row
+    /// went unanswered, the device is re-identified and the cached state is
+    /// restored, see [`OtrspDevice::reinitialize()`](crate::OtrspDevice::reinitialize).
+    pub fn detect_resets(mut self, timeouts: u32) -> Self {
+        self.reset_after_timeouts = timeouts;
+        self
+    }
+
+    /// Accept TX/RX/AUX commands while the link is down and replay them later (default: false).
+    ///
+    /// Instead of failing with a connection error during a brief USB glitch,
+    /// state commands are recorded and succeed. When the link works again
+    /// the net result is sent as a state diff, see
+    /// [`OtrspDevice::replay_offline()`](crate::OtrspDevice::replay_offline).
+    /// With [`reconnect()`](Self::reconnect), commands that time out while
+    /// the link is down are recorded too, and the backlog goes out as soon
+    /// as the link is back.
+    pub fn offline_queue(mut self, enabled: bool) -> Self {
+        self.offline_queue = enabled;
+        self
+    }
+
+    /// Number of commands that may wait for the IO task (default: 32).
+    ///
+    /// See [`queue_policy()`](Self::queue_policy) for what happens when a
+    /// burst fills the queue.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.io_config.capacity = capacity;
+        self
+    }
+
+    /// What a command does when the request queue is full (default: [`QueuePolicy::Block`]).
+    pub fn queue_policy(mut self, policy: QueuePolicy) -> Self {
+        self.io_config.policy = policy;
+        self
+    }
+
+    /// Drop a queued RX routing update once it has waited this long and a
+    /// newer one is queued behind it (default: never).
+    ///
+    /// When the queue backs up, the switch then jumps straight to the latest
+    /// routing instead of replaying each step, which is heard as stutter.
+    pub fn routing_deadline(mut self, deadline: Duration) -> Self {
+        self.io_config.routing_deadline = Some(deadline);
+        self
+    }
+
+    /// Band to AUX value table used by [`OtrspDevice::set_band()`](crate::OtrspDevice::set_band)
+    /// (default: the Yaesu BCD codes).
+    pub fn bcd_map(mut self, map: BcdMap) -> Self {
+        self.bcd_map = map;
+        self
+    }
+
+    /// Drive `radio`'s band decoder from AUX `port` with its own code table
+    /// (default: Radio 1 on port 1, Radio 2 on port 2, both using
+    /// [`bcd_map`](Self::bcd_map)).
+    ///
+    /// For stations whose decoders hang off other ports, or whose two
+    /// radios feed decoders expecting different codes.
+    pub fn band_decoder(mut self, radio: Radio, port: u8, map: BcdMap) -> Self {
+        let index = match radio {
+            Radio::Radio1 => 0,
+            Radio::Radio2 => 1,
+        };
+        self.band_decoders[index] = Some((port, map));
+        self
+    }
+
+    /// Append every TX/RX/AUX change and connection event to a JSONL journal.
+    ///
+    /// The file is created if missing and never truncated, so one journal can
+    /// cover a whole contest weekend. See [`audit`](crate::audit) for the format.
+    pub fn audit_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit_path = Some(path.into());
+        self
+    }
+
+    /// Keep the last `capacity` command/response exchanges in memory (default: 0, off).
+    ///
+    /// Read them back with [`OtrspDevice::transcript()`](crate::OtrspDevice::transcript).
+    pub fn transcript(mut self, capacity: usize) -> Self {
+        self.transcript_capacity = capacity;
+        self
+    }
+
+    /// Append every command/response exchange to a JSONL file.
+    ///
+    /// The file is created if missing and never truncated. Works with or
+    /// without an in-memory [`transcript()`](Self::transcript); see
+    /// [`transcript`](crate::transcript) for the format.
+    pub fn transcript_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.transcript_path = Some(path.into());
+        self
+    }
+
+    /// Call `tap` with every chunk of raw bytes written to or read from the
+    /// device, before echo filtering or parsing.
+    ///
+    /// Runs on the IO task, so it should return quickly.
+    ///
+    /// ```no_run
+    /// # use otrsp::OtrspBuilder;
+    /// # async fn example() -> otrsp::Result<()> {
+    /// let device = OtrspBuilder::new("/dev/ttyUSB0")
+    ///     .on_wire(|dir, bytes| eprintln!("{dir} {}", bytes.escape_ascii()))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_wire<F>(mut self, tap: F) -> Self
+    where
+        F: Fn(WireDirection, &[u8]) + Send + Sync + 'static,
+    {
+        self.wire_tap = Some(Arc::new(tap));
+        self
+    }
+
+    /// Customize the serial port settings before the port is opened.
+    ///
+    /// The closure receives the default OTRSP settings (9600 8N1, no flow
+    /// control) and can set any other tokio-serial option. Used by
+    /// [`build()`](Self::build) and [`detect()`](Self::detect), and again
+    /// each time [`reconnect()`](Self::reconnect) reopens the port.
+    ///
+    /// ```no_run
+    /// # use otrsp::OtrspBuilder;
+    /// # async fn example() -> otrsp::Result<()> {
+    /// let device = OtrspBuilder::new("/dev/ttyUSB0")
+    ///     .configure_serial(|serial| serial.dtr_on_open(false))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn configure_serial<F>(mut self, configure: F) -> Self
+    where
+        F: Fn(SerialPortBuilder) -> SerialPortBuilder + Send + Sync + 'static,
+    {
+        self.configure_serial = Some(Arc::new(configure));
+        self
+    }
+
+    /// Reopen the link and carry on after an IO error (default: false).
+    ///
+    /// The port is wrapped in a [`ReconnectingPort`], which retries every
+    /// [`reconnect_delay()`](Self::reconnect_delay) and emits
+    /// [`SwitchEvent::Reconnecting`] and [`SwitchEvent::Reconnected`]. Commands
+    /// sent while the link is down time out. Should the IO task itself fail,
+    /// it is restarted on a freshly opened link. Serial ports are reopened
+    /// with the [`configure_serial()`](Self::configure_serial) hook, at the
+    /// baud rate found by [`build()`](Self::build). Not used by
+    /// [`build_with_port()`](Self::build_with_port).
+    pub fn reconnect(mut self, enabled: bool) -> Self {
+        self.reconnect = enabled;
+        self
+    }
+
+    /// Probe the link with `?NAME` after `interval` without traffic (default: off).
+    ///
+    /// OTRSP commands get no answer, so a device that hangs or loses power
+    /// behind a USB hub or network bridge goes unnoticed until the next
+    /// query. A keepalive left unanswered for the [`query_timeout()`](Self::query_timeout)
+    /// emits [`SwitchEvent::Disconnected`], or with
+    /// [`reconnect()`](Self::reconnect) on, drops the link and reopens it.
+    /// `Duration::ZERO` turns the keepalive off.
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.io_config.keepalive = (!interval.is_zero()).then_some(interval);
+        self
+    }
+
+    /// Time between reconnection attempts (default: 1s).
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+        self
+    }
+
+    /// Give up reconnecting after this many failed attempts in a row
+    /// (default: never).
+    ///
+    /// Once given up, commands fail at once instead of waiting for the
+    /// link. See [`ReconnectingPort::max_attempts()`].
+    pub fn reconnect_attempts(mut self, attempts: u32) -> Self {
+        self.reconnect_attempts = Some(attempts);
+        self
+    }
+
+    /// Whether to open the serial port for exclusive use (default: true).
+    ///
+    /// On Unix the port is locked with `TIOCEXCL` and `flock`, so a logger or
+    /// second SO2R program that already holds it makes [`build()`](Self::build)
+    /// fail with [`Error::Transport`] saying the port is in use, rather than
+    /// both programs reading half of each other's answers. Windows always
+    /// opens COM ports without sharing.
+    pub fn exclusive(mut self, enabled: bool) -> Self {
+        self.exclusive = enabled;
+        self
+    }
+
+    /// Probe common baud rates until the device answers `?NAME` (default: false).
+    ///
+    /// [`build()`](Self::build) opens the port once (after any
+    /// [`configure_serial`](Self::configure_serial) hook), waits out the
+    /// [`settle_delay()`](Self::settle_delay), then switches it through each of
+    /// [`COMMON_BAUD_RATES`](transport::COMMON_BAUD_RATES) in place and keeps
+    /// the first rate that yields a `NAME` response. The port is not reopened
+    /// between rates, so an Arduino-based box is reset only once. The rate is
+    /// recorded in [`SwitchInfo::baud_rate`]. Each silent rate costs one
+    /// [`query_timeout()`](Self::query_timeout).
+    pub fn auto_baud(mut self, enabled: bool) -> Self {
+        self.auto_baud = enabled;
+        self
+    }
+
+    /// Which serial ports [`discover()`](Self::discover) returns (default:
+    /// every port).
+    ///
+    /// Use [`PortFilter::known_adapters()`] or list the VID/PID pairs of the
+    /// adapters your station uses.
+    pub fn port_filter(mut self, filter: PortFilter) -> Self {
+        self.port_filter = filter;
+        self
+    }
+
+    /// The serial ports passing [`port_filter()`](Self::port_filter), best
+    /// candidates first.
+    pub fn discover(&self) -> Result<Vec<PortInfo>> {
+        discovery::discover(&self.port_filter)
+    }
+
+    /// Make [`detect()`](Self::detect) pass over devices whose name does not
+    /// contain `name`, ignoring case (default: take the first responder).
+    pub fn detect_name(mut self, name: &str) -> Self {
+        self.detect_name = Some(name.to_string());
+        self
+    }
+
+    /// Report [`detect()`](Self::detect) progress on `progress` (default:
+    /// no reports).
+    pub fn detect_progress(mut self, progress: mpsc::UnboundedSender<DetectEvent>) -> Self {
+        self.detect_progress = Some(progress);
+        self
+    }
+
+    /// Build the OTRSP connection using a real serial port, a network
+    /// connection for builders made with [`new_tcp()`](Self::new_tcp),
+    /// [`new_rfc2217()`](Self::new_rfc2217) or `new_tls()`, or the connector
+    /// given to [`from_connector()`](Self::from_connector).
+    pub async fn build(mut self) -> Result<OtrspDevice> {
+        let lock = self.acquire_lock()?;
+        if let Some(connector) = self.link.connector(&self.port_path) {
+            let baud_rate = match &self.link {
+                Link::Rfc2217 { settings, .. } => Some(settings.baud_rate),
+                _ => None,
+            };
+            let stream = connector.connect().await?;
+            return self.finish_link(connector, stream, lock, baud_rate).await;
+        }
+        let path = self.port_path.clone();
+        let configure = |serial| self.configure_port(serial);
+
+        let mut port = transport::open_serial_with(&path, configure)?;
+        if self.auto_baud {
+            if !self.settle_delay.is_zero() {
+                debug!(delay = ?self.settle_delay, "waiting for device to settle");
+                tokio::time::sleep(self.settle_delay).await;
+                // Settled once; the port stays open from here on.
+                self.settle_delay = Duration::ZERO;
+            }
+            let rate = probe_baud(
+                &mut port,
+                &transport::COMMON_BAUD_RATES,
+                self.io_config.query_timeout,
+                |port, rate| {
+                    port.set_baud_rate(rate)
+                        .and_then(|()| port.clear(ClearBuffer::Input))
+                        .map_err(|e| Error::Transport(format!("{path}: set baud rate {rate}: {e}")))
+                },
+            )
+            .await?;
+            info!(rate, "baud rate detected");
+        }
+        self.finish_serial(port, lock).await
+    }
+
+    /// Finish on the serial port opened at the builder's port path, reopened
+    /// through a [`SerialConnector`] if [`reconnect()`](Self::reconnect) is on.
+    async fn finish_serial(
+        mut self,
+        port: tokio_serial::SerialStream,
+        lock: Option<PortLock>,
+    ) -> Result<OtrspDevice> {
+        let baud_rate = port.baud_rate().ok();
+        let port = self.with_dtr(port);
+        if self.reconnect {
+            let dtr = self.dtr.clone().unwrap_or_default();
+            let mut connector = SerialConnector::new(&self.port_path)
+                .exclusive(self.exclusive)
+                .dtr_control(dtr);
+            if let Some(configure) = &self.configure_serial {
+                connector = connector.configure(configure.clone());
+            }
+            if let Some(rate) = baud_rate {
+                connector = connector.baud_rate(rate);
+            }
+            return self
+                .finish_link(Arc::new(connector), Box::new(port), lock, baud_rate)
+                .await;
+        }
+        self.finish(port, lock, baud_rate, None).await
+    }
+
+    /// Finish on a link opened through `connector`, wrapped in a
+    /// [`ReconnectingPort`] if [`reconnect()`](Self::reconnect) is on.
+    async fn finish_link(
+        mut self,
+        connector: Arc<dyn Connector>,
+        stream: BoxedTransport,
+        lock: Option<PortLock>,
+        baud_rate: Option<u32>,
+    ) -> Result<OtrspDevice> {
+        if !self.reconnect {
+            return self.finish(stream, lock, baud_rate, None).await;
+        }
+        let mut port = ReconnectingPort::new(connector.clone(), stream)
+            .with_events(self.event_tx.clone())
+            .retry_delay(self.reconnect_delay);
+        if let Some(attempts) = self.reconnect_attempts {
+            port = port.max_attempts(attempts);
+        }
+        self.io_config.link_reset = Some(port.reset_handle());
+        self.finish(port, lock, baud_rate, Some(connector)).await
+    }
+
+    /// Reopen the link through `connector` for a restarted IO task, wrapped
+    /// as [`finish_link()`](Self::finish_link) does.
+    fn restart(&self, connector: Arc<dyn Connector>, stats: Arc<StatsCounters>) -> Restart {
+        let events = self.event_tx.clone();
+        let delay = self.reconnect_delay;
+        let attempts = self.reconnect_attempts;
+        let tap = self.wire_tap.clone();
+        Arc::new(move || {
+            let (connector, events, tap, stats) = (
+                connector.clone(),
+                events.clone(),
+                tap.clone(),
+                stats.clone(),
+            );
+            Box::pin(async move {
+                let mut port = ReconnectingPort::connect(connector)
+                    .await?
+                    .with_events(events)
+                    .retry_delay(delay);
+                if let Some(attempts) = attempts {
+                    port = port.max_attempts(attempts);
+                }
+                let reset = port.reset_handle();
+                let port: BoxedTransport = Box::new(TapPort::new(port, tap, stats));
+                Ok((port, Some(reset)))
+            })
+        })
+    }
+
+    /// Find the switch by probing serial ports, then build on the first one
+    /// that answers.
+    ///
+    /// Scans the ports from [`discover()`](Self::discover) in order. Each is
+    /// opened as by [`build()`](Self::build), given the
+    /// [`settle_delay()`](Self::settle_delay), and sent `?NAME`; silent ports
+    /// cost one [`query_timeout()`](Self::query_timeout). A device whose name does not match
+    /// [`detect_name()`](Self::detect_name) is passed over. The builder's
+    /// port path is replaced by the port found, which is then kept open
+    /// without a second settle delay and, with
+    /// [`reconnect()`](Self::reconnect) on, reopened on link loss just like
+    /// a port opened by [`build()`](Self::build).
+    pub async fn detect(mut self) -> Result<OtrspDevice> {
+        let candidates = self.discover()?;
+        self.report(DetectEvent::Scanning {
+            ports: candidates.iter().map(|p| p.name.clone()).collect(),
+        });
+        for candidate in &candidates {
+            let path = candidate.name.clone();
+            self.report(DetectEvent::Probing { port: path.clone() });
+            match self.probe_port(&path).await {
+                Ok((port, lock, name)) => {
+                    info!(port = %path, name = %name, "OTRSP device detected");
+                    self.report(DetectEvent::Found {
+                        port: path.clone(),
+                        name,
+                    });
+                    self.port_path = path;
+                    // probe_port() already waited for the device to settle.
+                    self.settle_delay = Duration::ZERO;
+                    return self.finish_serial(port, lock).await;
+                }
+                Err(e) => {
+                    debug!(port = %path, "skipping port: {e}");
+                    self.report(DetectEvent::Skipped {
+                        port: path,
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+        Err(Error::Transport(format!(
+            "no OTRSP device found on {} candidate ports",
+            candidates.len()
+        )))
+    }
+
+    /// Open `path` and check the device on it is the one wanted.
+    async fn probe_port(
+        &self,
+        path: &str,
+    ) -> Result<(tokio_serial::SerialStream, Option<PortLock>, String)> {
+        let lock = match &self.lock_dir {
+            Some(dir) => Some(PortLock::acquire(dir, path)?),
+            None => None,
+        };
+        let mut port = transport::open_serial_with(path, |serial| self.configure_port(serial))?;
+        if !self.settle_delay.is_zero() {
+            tokio::time::sleep(self.settle_delay).await;
+        }
+        let name = probe_name(&mut port, self.io_config.query_timeout).await?;
+        match &self.detect_name {
+            Some(wanted) if !name.to_lowercase().contains(&wanted.to_lowercase()) => Err(
+                Error::Transport(format!("found {name:?}, looking for {wanted:?}")),
+            ),
+            _ => Ok((port, lock, name)),
+        }
+    }
+
+    /// Apply [`exclusive()`](Self::exclusive), then the
+    /// [`configure_serial()`](Self::configure_serial) hook.
+    fn configure_port(&self, serial: SerialPortBuilder) -> SerialPortBuilder {
+        #[cfg(unix)]
+        let serial = serial.exclusive(self.exclusive);
+        match &self.configure_serial {
+            Some(configure) => configure(serial),
+            None => serial,
+        }
+    }
+
+    /// Give `port`'s DTR line to the device built on it, for
+    /// [`OtrspDevice::reset_hardware()`](crate::OtrspDevice::reset_hardware).
+    fn with_dtr(&mut self, port: tokio_serial::SerialStream) -> DtrPort {
+        let dtr = DtrControl::default();
+        self.dtr = Some(dtr.clone());
+        DtrPort::new(port, dtr)
+    }
+
+    fn report(&self, event: DetectEvent) {
+        if let Some(progress) = &self.detect_progress {
+            let _ = progress.send(event);
+        }
     }
 
     /// Build using a pre-opened port (for testing with MockPort).
@@ -55,42 +968,317 @@ impl OtrspBuilder {
     where
         P: AsyncRead + AsyncWrite + Send + Unpin + 'static,
     {
+        let lock = self.acquire_lock()?;
+        self.finish(port, lock, None, None).await
+    }
+
+    /// Take the advisory port lock, if configured.
+    fn acquire_lock(&self) -> Result<Option<PortLock>> {
+        self.lock_dir
+            .as_deref()
+            .map(|dir| PortLock::acquire(dir, &self.port_path))
+            .transpose()
+    }
+
+    /// Spawn the IO task on an open port and identify the device.
+    ///
+    /// With a `connector`, a failed IO task is restarted on a new
+    /// [`ReconnectingPort`] from it.
+    async fn finish<P>(
+        self,
+        port: P,
+        lock: Option<PortLock>,
+        baud_rate: Option<u32>,
+        connector: Option<Arc<dyn Connector>>,
+    ) -> Result<OtrspDevice>
+    where
+        P: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let audit_file = self.audit_path.as_deref().map(audit::open).transpose()?;
+        let transcript_file = self
+            .transcript_path
+            .as_deref()
+            .map(audit::open)
+            .transpose()?;
+        let mut io_config = self.io_config.clone();
+        io_config.stats = Arc::new(StatsCounters::default());
+        io_config.metrics = Arc::default();
+        io_config.latest_update = Arc::default();
+        io_config.footswitch_latch = self
+            .footswitch_latch
+            .then(|| Arc::new(Mutex::new(FootswitchLatch::default())));
+        let footswitch_latch = io_config.footswitch_latch.clone();
+        let offline = Arc::new(Mutex::new(SwitchState::default()));
+        let extensions = io_config.extensions.clone();
+        io_config.offline = self.offline_queue.then(|| offline.clone());
+        io_config.transmit_latch = self
+            .transmit_latch
+            .then(|| Arc::new(Mutex::new(TransmitLatch::default())));
+        let transmit_latch = io_config.transmit_latch.clone();
+        io_config.rx_follow = self.rx_follow;
+        if self.transcript_capacity > 0 || transcript_file.is_some() {
+            io_config.transcript = Some(Transcript::new(self.transcript_capacity, transcript_file));
+        }
+
         // Spawn IO task first — single owner of the port from the start.
-        let (event_tx, _) = broadcast::channel::<SwitchEvent>(64);
-        let _ = event_tx.send(SwitchEvent::Connected);
+        let event_tx = self.event_tx.clone();
+        if let Some(file) = audit_file {
+            audit::spawn(
+                file,
+                &self.port_path,
+                self.emit_connected,
+                event_tx.subscribe(),
+            );
+        }
+
+        let state = Arc::new(Mutex::new(SwitchState {
+            connected: true,
+            ..Default::default()
+        }));
+        let last_unkey = Arc::new(Mutex::new(None));
+        let restart = connector.map(|connector| self.restart(connector, io_config.stats.clone()));
+        let io = spawn_io_task(
+            TapPort::new(port, self.wire_tap.clone(), io_config.stats.clone()),
+            event_tx.clone(),
+            state.clone(),
+            last_unkey.clone(),
+            io_config,
+            restart,
+        );
 
-        let io = spawn_io_task(port, event_tx.clone());
+        if !self.settle_delay.is_zero() {
+            debug!(delay = ?self.settle_delay, "waiting for device to settle");
+            tokio::time::sleep(self.settle_delay).await;
+        }
 
         // Optionally query the device name through the IO task.
-        let name = if self.query_name {
+        let queried_name = if self.query_name {
             debug!("querying device name");
-            match io.command_read(b"?NAME\r".to_vec()).await {
+            let mut attempt = 0;
+            loop {
+                match io.command_read(protocol::encode_query_name()).await {
+                    Ok(response) => {
+                        let name = crate::protocol::parse_name_response(&response);
+                        info!(name = %name, "OTRSP device identified");
+                        break Some(name);
+                    }
+                    Err(Error::Timeout) if attempt < self.name_retries => {
+                        attempt += 1;
+                        debug!(attempt, "no answer to ?NAME, retrying");
+                    }
+                    Err(e) => {
+                        warn!("failed to query device name: {e}");
+                        break None;
+                    }
+                }
+            }
+        } else {
+            None
+        };
+        let identity = queried_name.as_deref().map(protocol::parse_identity);
+        if let Some(DeviceIdentity {
+            vendor: Some(vendor),
+            model: Some(model),
+            ..
+        }) = &identity
+        {
+            debug!(%vendor, %model, "recognized device family");
+        }
+        let name = queried_name
+            .clone()
+            .unwrap_or_else(|| "Unknown".to_string());
+
+        let firmware = if self.query_version {
+            match io.command_read(protocol::encode_query_version()).await {
                 Ok(response) => {
-                    let name = crate::protocol::parse_name_response(response.as_bytes());
-                    info!(name = %name, "OTRSP device identified");
-                    name
+                    let version = protocol::parse_version_response(&response);
+                    info!(version = %version, "device firmware");
+                    Some(version)
                 }
                 Err(e) => {
-                    warn!("failed to query device name: {e}");
-                    "Unknown".to_string()
+                    debug!("device did not report a firmware version: {e}");
+                    None
                 }
             }
         } else {
-            "Unknown".to_string()
+            None
+        };
+
+        let features = if self.negotiate {
+            negotiate_features(&io).await
+        } else {
+            ProtocolFeatures::default()
         };
+        if features.events || features.ptt {
+            io.set_listening(true).await?;
+        }
+        state.lock().unwrap().name = queried_name;
 
+        let state_watch = state::spawn_watch(state.clone(), event_tx.subscribe());
         Ok(OtrspDevice {
             io,
             info: SwitchInfo {
                 name,
                 port: Some(self.port_path),
+                baud_rate,
+                firmware,
+                identity,
             },
-            capabilities: SwitchCapabilities {
-                stereo: true,
-                reverse_stereo: true,
-                aux_ports: 2,
-            },
+            capabilities: self.capabilities,
+            features,
+            state,
+            state_watch,
+            skip_redundant: self.skip_redundant,
+            best_effort_rx: self.best_effort_rx,
+            rx_follow: self.rx_follow,
+            ptt_lead: self.ptt_lead,
+            ptt_tail: self.ptt_tail,
+            last_unkey,
+            latch: footswitch_latch,
+            transmit_latch,
             event_tx,
+            emit_connected: self.emit_connected,
+            reset_after_timeouts: self.reset_after_timeouts,
+            unanswered: AtomicU32::new(0),
+            offline_queue: self.offline_queue,
+            band_decoders: [(0, 1), (1, 2)].map(|(index, port)| {
+                self.band_decoders[index]
+                    .clone()
+                    .unwrap_or_else(|| (port, self.bcd_map.clone()))
+            }),
+            extensions,
+            offline,
+            dtr: self.dtr,
+            dtr_pulse: self.dtr_pulse,
+            bootloader_delay: self.bootloader_delay,
+            _lock: lock,
         })
     }
 }
+
+/// Switch the open port to each rate in turn until the device answers
+/// `?NAME`, returning the working rate.
+///
+/// `set_rate` changes the rate in place, so the port is not reopened. A rate
+/// that stays silent for `timeout` or answers with anything but a `NAME`
+/// line moves on to the next.
+async fn probe_baud<P, F>(
+    port: &mut P,
+    rates: &[u32],
+    timeout: Duration,
+    mut set_rate: F,
+) -> Result<u32>
+where
+    P: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut(&mut P, u32) -> Result<()>,
+{
+    for &rate in rates {
+        debug!(rate, "probing baud rate");
+        set_rate(port, rate)?;
+        match probe_name(port, timeout).await {
+            Ok(_) => return Ok(rate),
+            Err(e) => debug!(rate, "{e}"),
+        }
+    }
+    Err(Error::Transport(format!(
+        "no ?NAME response at any baud rate ({rates:?})"
+    )))
+}
+
+/// Send `?NAME` on a freshly opened port and return the device name.
+async fn probe_name<P>(port: &mut P, timeout: Duration) -> Result<String>
+where
+    P: AsyncRead + AsyncWrite + Unpin,
+{
+    port.write_all(&protocol::encode_query_name()).await?;
+    match tokio::time::timeout(timeout, read_line(port)).await {
+        Ok(Ok(line)) if protocol::is_probe_answer(&line, "NAME") => {
+            Ok(protocol::parse_name_response(&line))
+        }
+        Ok(Ok(line)) => Err(Error::Protocol(format!(
+            "unexpected probe response: {}",
+            line.escape_ascii()
+        ))),
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Err(Error::Timeout),
+    }
+}
+
+/// Probe the device for optional protocol extensions.
+async fn negotiate_features(io: &IoHandle) -> ProtocolFeatures {
+    debug!("negotiating protocol extensions");
+    let events = probe(io, protocol::encode_query_event(), "EVENT").await;
+    let ptt = probe(io, protocol::encode_query_ptt(), "PTT").await;
+    let features = ProtocolFeatures {
+        events,
+        ptt,
+        ..ProtocolFeatures::default()
+    };
+    info!(?features, "protocol extensions negotiated");
+    features
+}
+
+/// Issue a probe query, returning whether the device answered it.
+async fn probe(io: &IoHandle, query: Vec<u8>, prefix: &str) -> bool {
+    match io.command_read(query).await {
+        Ok(response) => protocol::is_probe_answer(&response, prefix),
+        Err(e) => {
+            debug!("probe for {prefix} failed: {e}");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockPort;
+
+    #[tokio::test]
+    async fn probe_baud_stops_at_first_answer() {
+        let mut port = MockPort::new();
+        let mut rates = Vec::new();
+        let rate = probe_baud(
+            &mut port,
+            &[9600, 19200, 38400],
+            Duration::from_millis(50),
+            |port, rate| {
+                rates.push(rate);
+                // Only the second rate gets an answer.
+                if rate == 19200 {
+                    port.queue_read(b"NAMESO2RDUINO\r");
+                }
+                Ok(())
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(rate, 19200);
+        assert_eq!(rates, [9600, 19200]);
+        assert_eq!(&port.written_data()[..], b"?NAME\r?NAME\r");
+    }
+
+    #[tokio::test]
+    async fn probe_baud_fails_when_nothing_answers() {
+        let mut garbage = MockPort::new();
+        garbage.queue_read(b"\x7f\x00\r");
+
+        let result = probe_baud(&mut garbage, &[9600], Duration::from_secs(1), |_, _| Ok(())).await;
+        assert!(matches!(result, Err(Error::Transport(_))));
+    }
+
+    #[tokio::test]
+    async fn probe_name_returns_device_name() {
+        let mut port = MockPort::new();
+        port.queue_read(b"NAMESO2RDUINO\r");
+        assert_eq!(
+            probe_name(&mut port, Duration::from_secs(1)).await.unwrap(),
+            "SO2RDUINO"
+        );
+
+        port.queue_read(b"TX1\r");
+        let result = probe_name(&mut port, Duration::from_secs(1)).await;
+        assert!(matches!(result, Err(Error::Protocol(_))));
+    }
+}
@@ -0,0 +1,131 @@
+//! tokio-util codec for OTRSP framing.
+//!
+//! [`OtrspCodec`] splits the byte stream into CR/LF-terminated lines and
+//! classifies them with [`parse_response`], so a port can be driven through
+//! `tokio_util::codec::Framed` directly:
+//!
+//! ```ignore
+//! let mut framed = Framed::new(port, OtrspCodec::new());
+//! framed.send(Command::QueryName).await?;
+//! let name = framed.next().await;
+//! ```
+
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::error::{Error, Result};
+use crate::protocol::{Command, Response, parse_response};
+
+/// Longest line accepted before the decoder gives up on finding a terminator.
+pub const MAX_LINE_LEN: usize = 256;
+
+/// Encodes [`Command`]s and decodes device lines into [`Response`]s.
+///
+/// Empty lines (the LF of a CRLF pair) are skipped. A line with a known
+/// prefix but a malformed body is returned as [`Response::Unknown`] rather
+/// than an error, since a decode error ends a `Framed` stream.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OtrspCodec;
+
+impl OtrspCodec {
+    /// Create a codec.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Decoder for OtrspCodec {
+    type Item = Response;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Response>> {
+        while let Some(line) = split_line(src) {
+            let text = line.trim_ascii();
+            if text.is_empty() {
+                continue;
+            }
+            let response =
+                parse_response(&line).unwrap_or_else(|_| Response::Unknown(text.to_vec()));
+            return Ok(Some(response));
+        }
+        if src.len() > MAX_LINE_LEN {
+            src.clear();
+            return Err(Error::Protocol(format!(
+                "no line terminator within {MAX_LINE_LEN} bytes"
+            )));
+        }
+        Ok(None)
+    }
+}
+
+impl Encoder<Command> for OtrspCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: Command, dst: &mut BytesMut) -> Result<()> {
+        dst.extend_from_slice(&item.encode()?);
+        Ok(())
+    }
+}
+
+/// Split the first complete line, terminator included, off the buffer.
+pub(crate) fn split_line(buf: &mut BytesMut) -> Option<BytesMut> {
+    let end = buf.iter().position(|&b| b == b'\r' || b == b'\n')?;
+    let line = buf.split_to(end + 1);
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Notification;
+    use crate::types::Radio;
+
+    #[test]
+    fn test_decode_lines() {
+        let mut codec = OtrspCodec::new();
+        let mut buf = BytesMut::from(&b"NAMESO2Rduino\r\n$TX2\rAUX1"[..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Response::Name("SO2Rduino".into()))
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Response::Notification(Notification::Tx(Radio::Radio2)))
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"4\r");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Response::Aux { port: 1, value: 4 })
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decode_malformed_line_is_unknown() {
+        let mut codec = OtrspCodec::new();
+        let mut buf = BytesMut::from(&b"TX9\r"[..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Response::Unknown(b"TX9".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_decode_overlong_line() {
+        let mut codec = OtrspCodec::new();
+        let mut buf = BytesMut::from(&[b'A'; MAX_LINE_LEN + 1][..]);
+        assert!(codec.decode(&mut buf).is_err());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_encode_commands() {
+        let mut codec = OtrspCodec::new();
+        let mut buf = BytesMut::new();
+        codec.encode(Command::Tx(Radio::Radio1), &mut buf).unwrap();
+        codec.encode(Command::QueryAux(2), &mut buf).unwrap();
+        assert_eq!(&buf[..], b"TX1\r?AUX2\r");
+        assert!(codec.encode(Command::QueryAux(10), &mut buf).is_err());
+    }
+}
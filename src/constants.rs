@@ -0,0 +1,44 @@
+//! Canonical OTRSP command strings.
+//!
+//! Command mnemonics without radio, port or value and without the CR
+//! terminator. Use [`Command`](crate::protocol::Command)'s `Display` and
+//! `FromStr` impls to turn complete commands into text and back.
+
+/// Line terminator sent after every command.
+pub const TERMINATOR: &str = "\r";
+
+/// Prefix of unsolicited device notifications (`$TX2`).
+pub const NOTIFICATION_PREFIX: &str = "$";
+
+/// Select the TX radio (`TXr`).
+pub const TX: &str = "TX";
+/// Set RX audio routing (`RXr[S|R]`).
+pub const RX: &str = "RX";
+/// Set an AUX output (`AUXpv`).
+pub const AUX: &str = "AUX";
+/// Route computer keying to a radio (`CRr`).
+pub const CR: &str = "CR";
+/// Turn unsolicited reporting on or off (`EVENTn`).
+pub const EVENT: &str = "EVENT";
+
+/// RX mode suffix for stereo.
+pub const RX_STEREO: &str = "S";
+/// RX mode suffix for reverse stereo.
+pub const RX_REVERSE_STEREO: &str = "R";
+
+/// Query the device name.
+pub const QUERY_NAME: &str = "?NAME";
+/// Query the firmware version.
+pub const QUERY_VERSION: &str = "?VERSION";
+/// Query TX focus.
+pub const QUERY_TX: &str = "?TX";
+/// Query RX routing.
+pub const QUERY_RX: &str = "?RX";
+/// Query an AUX output (`?AUXp`).
+pub const QUERY_AUX: &str = "?AUX";
+/// Query keying routing.
+pub const QUERY_CR: &str = "?CR";
+/// Probe for event reporting support.
+pub const QUERY_EVENT: &str = "?EVENT";
+/// Probe for PTT reporting support.
+pub const QUERY_PTT: &str = "?PTT";
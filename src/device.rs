@@ -1,12 +1,28 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
 use async_trait::async_trait;
-use tokio::sync::broadcast;
+use bytes::Bytes;
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+use tracing::{debug, info, trace, warn};
 
-use crate::error::Result;
-use crate::event::SwitchEvent;
+use crate::error::{Error, Result};
+use crate::event::{FilteredReceiver, Origin, SwitchEvent};
+use crate::extension::Extensions;
 use crate::io::IoHandle;
-use crate::protocol;
-use crate::switch::{So2rSwitch, SwitchCapabilities, SwitchInfo};
-use crate::types::{Radio, RxMode};
+use crate::latch::{FootswitchLatch, TransmitLatch};
+use crate::lease::PortLease;
+use crate::protocol::{self, BcdMap, Command, Response};
+use crate::queue::PendingCommands;
+use crate::state::SwitchState;
+use crate::stats::{LinkMetrics, LinkStats};
+use crate::switch::{ProtocolFeatures, So2rSwitch, SwitchCapabilities, SwitchInfo};
+use crate::transcript::Transcript;
+use crate::transport::{DtrControl, PortLock};
+use crate::types::{Band, Radio, RxFollow, RxMode};
 
 /// An OTRSP device connected via serial port.
 ///
@@ -15,7 +31,56 @@ pub struct OtrspDevice {
     pub(crate) io: IoHandle,
     pub(crate) info: SwitchInfo,
     pub(crate) capabilities: SwitchCapabilities,
+    pub(crate) features: ProtocolFeatures,
+    /// Routing last sent to, or reported by, the device.
+    ///
+    /// Shared with the IO task, which applies unsolicited notifications.
+    pub(crate) state: Arc<Mutex<SwitchState>>,
+    /// Latest [`state`](Self::state), republished after every event.
+    pub(crate) state_watch: watch::Receiver<SwitchState>,
+    /// Skip commands whose target state already holds.
+    pub(crate) skip_redundant: bool,
+    /// Downgrade unsupported RX modes to mono instead of failing.
+    pub(crate) best_effort_rx: bool,
+    /// Route RX audio to the new radio on every `set_tx()`.
+    pub(crate) rx_follow: Option<RxFollow>,
+    /// Relay settle time after a TX focus change, before PTT may be asserted.
+    pub(crate) ptt_lead: Duration,
+    /// Hold-off after PTT release before TX focus may change.
+    pub(crate) ptt_tail: Duration,
+    /// When PTT was last reported released (by the application or the device).
+    pub(crate) last_unkey: Arc<Mutex<Option<Instant>>>,
+    /// Footswitch latch state, shared with the IO task (`None` when disabled).
+    pub(crate) latch: Option<Arc<Mutex<FootswitchLatch>>>,
+    /// Transmit latch state, shared with the IO task (`None` when disabled).
+    pub(crate) transmit_latch: Option<Arc<Mutex<TransmitLatch>>>,
     pub(crate) event_tx: broadcast::Sender<SwitchEvent>,
+    /// Start filtered subscriptions with `Connected` while the link is up.
+    pub(crate) emit_connected: bool,
+    /// Consecutive unanswered queries that signal a reboot (0 = detection off).
+    pub(crate) reset_after_timeouts: u32,
+    /// Queries that have timed out in a row.
+    pub(crate) unanswered: AtomicU32,
+    /// Accept state commands while disconnected and replay them later.
+    pub(crate) offline_queue: bool,
+    /// State commands accepted while disconnected, not yet sent.
+    ///
+    /// Shared with the IO task, which replays them when the link is back.
+    pub(crate) offline: Arc<Mutex<SwitchState>>,
+    /// AUX port and band code table for each radio's band decoder, used by
+    /// [`set_band()`](Self::set_band).
+    pub(crate) band_decoders: [(u8, BcdMap); 2],
+    /// Vendor commands and response parsers registered by the application.
+    ///
+    /// Shared with the IO task, which encodes RX changes made by the latches.
+    pub(crate) extensions: Arc<RwLock<Extensions>>,
+    /// DTR line of the serial port, for [`reset_hardware()`](Self::reset_hardware).
+    pub(crate) dtr: Option<DtrControl>,
+    pub(crate) dtr_pulse: Duration,
+    /// Time the bootloader takes to hand over after a reset.
+    pub(crate) bootloader_delay: Duration,
+    /// Advisory port lock, held for the lifetime of the device.
+    pub(crate) _lock: Option<PortLock>,
 }
 
 #[async_trait]
@@ -29,53 +94,236 @@ impl So2rSwitch for OtrspDevice {
     }
 
     async fn set_tx(&self, radio: Radio) -> Result<()> {
-        let data = protocol::encode_tx(radio);
-        self.io.command(data).await?;
-        let _ = self.event_tx.send(SwitchEvent::TxChanged { radio });
-        Ok(())
+        self.select_tx(radio).await?;
+        let Some(follow) = self.rx_follow else {
+            return Ok(());
+        };
+        let current = self.state.lock().unwrap().rx;
+        let (radio, mode) = follow.routing(radio, current);
+        self.set_rx(radio, mode).await
     }
 
     async fn set_rx(&self, radio: Radio, mode: RxMode) -> Result<()> {
-        let data = protocol::encode_rx(radio, mode);
-        self.io.command(data).await?;
-        let _ = self.event_tx.send(SwitchEvent::RxChanged { radio, mode });
+        let mode = if self.capabilities.supports_rx(mode) {
+            mode
+        } else if self.best_effort_rx {
+            debug!(?mode, "RX mode not supported, falling back to mono");
+            RxMode::Mono
+        } else {
+            return Err(Error::Unsupported(format!(
+                "RX mode {mode:?} not supported by this device"
+            )));
+        };
+        self.replay_offline().await?;
+        if self.skip_redundant && self.state.lock().unwrap().rx == Some((radio, mode)) {
+            trace!(?radio, ?mode, "RX routing already set, skipping");
+        } else if let Err(e) = self.write_rx(radio, mode).await {
+            return self.park_offline(e, |s| s.rx = Some((radio, mode)));
+        }
+        if let Some(latch) = &self.latch {
+            latch.lock().unwrap().release();
+        }
+        if let Some(latch) = &self.transmit_latch {
+            latch.lock().unwrap().release();
+        }
         Ok(())
     }
 
+    /// Uses the cached TX focus, querying the device only when it is unknown.
+    async fn other_radio(&self) -> Result<Radio> {
+        let cached = self.state.lock().unwrap().tx;
+        let tx = match cached {
+            Some(radio) => radio,
+            None => self.query_tx().await?,
+        };
+        Ok(tx.other())
+    }
+
+    /// Uses the cached RX mode, mono if RX was never set.
+    async fn swap(&self) -> Result<Radio> {
+        let mode = self
+            .state
+            .lock()
+            .unwrap()
+            .rx
+            .map_or(RxMode::Mono, |(_, mode)| mode);
+        let radio = self.other_radio().await?;
+        self.select_tx(radio).await?;
+        self.set_rx(radio, mode).await?;
+        Ok(radio)
+    }
+
     async fn set_aux(&self, port: u8, value: u8) -> Result<()> {
-        let data = protocol::encode_aux(port, value)?;
-        self.io.command(data).await?;
-        let _ = self.event_tx.send(SwitchEvent::AuxChanged { port, value });
+        self.set_aux_wide(port, value.into()).await
+    }
+
+    async fn set_aux_wide(&self, port: u8, value: u16) -> Result<()> {
+        self.check_aux_port(port)?;
+        self.capabilities.check_aux_value(port, value)?;
+        let data = protocol::encode_aux_wide(port, value)?;
+        self.replay_offline().await?;
+        if self.skip_redundant && self.state.lock().unwrap().aux.get(&port) == Some(&value) {
+            trace!(port, value, "AUX value already set, skipping");
+            return Ok(());
+        }
+        if let Err(e) = self.io.command(data).await {
+            return self.park_offline(e, |s| {
+                s.aux.insert(port, value);
+            });
+        }
+        self.state.lock().unwrap().aux.insert(port, value);
+        let _ = self.event_tx.send(SwitchEvent::AuxChanged {
+            port,
+            value,
+            origin: Origin::Host,
+        });
         Ok(())
     }
 
+    /// Sends TX changes without [`rx_follows_tx`](crate::OtrspBuilder::rx_follows_tx).
+    async fn apply_command(&self, command: &Command) -> Result<()> {
+        match *command {
+            Command::Tx(radio) => self.select_tx(radio).await,
+            Command::Rx(radio, mode) => self.set_rx(radio, mode).await,
+            Command::Aux { port, value } => self.set_aux_wide(port, value).await,
+            Command::Keying(radio) => self.set_keying(radio).await,
+            _ => Err(Error::InvalidParameter(format!(
+                "{command:?} is not a state command"
+            ))),
+        }
+    }
+
     async fn device_name(&self) -> Result<String> {
         let data = protocol::encode_query_name();
-        let response = self.io.command_read(data).await?;
-        Ok(protocol::parse_name_response(response.as_bytes()))
+        let response = self.query(data).await?;
+        Ok(protocol::parse_name_response(&response))
     }
 
+    /// Fails with [`Error::Protocol`] if the value does not fit in a `u8`;
+    /// use [`query_aux_wide()`](So2rSwitch::query_aux_wide) for those.
     async fn query_aux(&self, port: u8) -> Result<u8> {
+        let value = self.query_aux_wide(port).await?;
+        u8::try_from(value).map_err(|_| {
+            Error::Protocol(format!(
+                "AUX{port} value {value} exceeds 255; use query_aux_wide()"
+            ))
+        })
+    }
+
+    async fn query_aux_wide(&self, port: u8) -> Result<u16> {
+        self.check_aux_port(port)?;
         let data = protocol::encode_query_aux(port)?;
-        let response = self.io.command_read(data).await?;
-        let (returned_port, value) = protocol::parse_aux_response(response.as_bytes())?;
+        let response = self.query(data).await?;
+        let (returned_port, value) = protocol::parse_aux_response(&response)?;
         if returned_port != port {
-            return Err(crate::error::Error::Protocol(format!(
+            return Err(Error::Protocol(format!(
                 "AUX port mismatch: requested port {port}, got port {returned_port}"
             )));
         }
         Ok(value)
     }
 
+    async fn query_tx(&self) -> Result<Radio> {
+        let response = self.query(protocol::encode_query_tx()).await?;
+        let radio = protocol::parse_tx_response(&response)?;
+        let changed = self.state.lock().unwrap().tx.replace(radio) != Some(radio);
+        if changed {
+            debug!(?radio, "device reports different TX focus than cached");
+            let _ = self.event_tx.send(SwitchEvent::TxChanged {
+                radio,
+                origin: Origin::Device,
+            });
+        }
+        Ok(radio)
+    }
+
+    async fn query_rx(&self) -> Result<(Radio, RxMode)> {
+        let response = self.query(protocol::encode_query_rx()).await?;
+        let (radio, mode) = protocol::parse_rx_response(&response)?;
+        let changed = self.state.lock().unwrap().rx.replace((radio, mode)) != Some((radio, mode));
+        if changed {
+            debug!(
+                ?radio,
+                ?mode,
+                "device reports different RX routing than cached"
+            );
+            let _ = self.event_tx.send(SwitchEvent::RxChanged {
+                radio,
+                mode,
+                origin: Origin::Device,
+            });
+        }
+        Ok((radio, mode))
+    }
+
+    async fn set_keying(&self, radio: Radio) -> Result<()> {
+        self.replay_offline().await?;
+        if self.skip_redundant && self.state.lock().unwrap().keying == Some(radio) {
+            trace!(?radio, "keying routing already set, skipping");
+            return Ok(());
+        }
+        let data = protocol::encode_cr(radio);
+        if let Err(e) = self.io.command(data).await {
+            return self.park_offline(e, |s| s.keying = Some(radio));
+        }
+        self.state.lock().unwrap().keying = Some(radio);
+        let _ = self.event_tx.send(SwitchEvent::KeyingChanged {
+            radio,
+            origin: Origin::Host,
+        });
+        Ok(())
+    }
+
+    async fn query_keying(&self) -> Result<Radio> {
+        let response = self.query(protocol::encode_query_cr()).await?;
+        let radio = protocol::parse_cr_response(&response)?;
+        let changed = self.state.lock().unwrap().keying.replace(radio) != Some(radio);
+        if changed {
+            debug!(
+                ?radio,
+                "device reports different keying routing than cached"
+            );
+            let _ = self.event_tx.send(SwitchEvent::KeyingChanged {
+                radio,
+                origin: Origin::Device,
+            });
+        }
+        Ok(radio)
+    }
+
     async fn send_raw(&self, command: &str) -> Result<()> {
-        let data = protocol::encode_raw(command);
+        let data = protocol::encode_raw(command)?;
         self.io.command(data).await
     }
 
+    async fn query_raw(&self, command: &str) -> Result<String> {
+        let data = protocol::encode_raw(command)?;
+        let mut response = &self.query(data).await?[..];
+        while let [rest @ .., b'\r' | b'\n'] = response {
+            response = rest;
+        }
+        Ok(String::from_utf8_lossy(response).into_owned())
+    }
+
     fn subscribe(&self) -> broadcast::Receiver<SwitchEvent> {
         self.event_tx.subscribe()
     }
 
+    fn subscribe_connection(&self) -> FilteredReceiver {
+        self.subscribe_filtered(SwitchEvent::is_connection_event)
+    }
+
+    fn subscribe_filtered(&self, filter: fn(&SwitchEvent) -> bool) -> FilteredReceiver {
+        let rx = FilteredReceiver::new(self.subscribe(), filter);
+        // Nobody can subscribe before build, so each subscriber is told the
+        // link is up, unless it has gone down since.
+        if self.emit_connected && self.state.lock().unwrap().connected && !self.io.tx.is_closed() {
+            rx.starting_with(SwitchEvent::Connected)
+        } else {
+            rx
+        }
+    }
+
     async fn close(&self) -> Result<()> {
         self.io.shutdown().await
     }
@@ -91,4 +339,659 @@ impl OtrspDevice {
     pub fn capabilities(&self) -> &SwitchCapabilities {
         &self.capabilities
     }
+
+    /// Send a TX focus change, without RX following it.
+    async fn select_tx(&self, radio: Radio) -> Result<()> {
+        self.replay_offline().await?;
+        if self.skip_redundant && self.state.lock().unwrap().tx == Some(radio) {
+            trace!(?radio, "TX already selected, skipping");
+            return Ok(());
+        }
+        let switching = self.state.lock().unwrap().tx != Some(radio);
+        if switching {
+            self.wait_ptt_tail().await;
+        }
+        let data = protocol::encode_tx(radio);
+        if let Err(e) = self.io.command(data).await {
+            return self.park_offline(e, |s| s.tx = Some(radio));
+        }
+        self.state.lock().unwrap().tx = Some(radio);
+        let _ = self.event_tx.send(SwitchEvent::TxChanged {
+            radio,
+            origin: Origin::Host,
+        });
+        if switching && !self.ptt_lead.is_zero() {
+            trace!(lead = ?self.ptt_lead, "waiting for TX relays to settle");
+            tokio::time::sleep(self.ptt_lead).await;
+        }
+        Ok(())
+    }
+
+    /// Handle a footswitch press for the footswitch latch.
+    ///
+    /// The first press moves RX audio (mono) to the radio not currently heard;
+    /// the next press restores the previous routing. An explicit
+    /// [`set_rx()`](So2rSwitch::set_rx) while latched releases the latch.
+    /// Devices that report the footswitch drive the latch themselves once
+    /// events are enabled.
+    ///
+    /// Requires [`OtrspBuilder::footswitch_latch`](crate::OtrspBuilder::footswitch_latch)
+    /// and a prior `set_rx()` so the current routing is known.
+    pub async fn footswitch_press(&self) -> Result<()> {
+        let latch = self
+            .latch
+            .as_ref()
+            .ok_or_else(|| Error::Unsupported("footswitch latch not enabled".into()))?;
+        // Toggle under one lock, so a concurrent press sees the new state.
+        let (target, previous) = {
+            let mut latch = latch.lock().unwrap();
+            let current = self.state.lock().unwrap().rx.ok_or_else(|| {
+                Error::InvalidParameter("RX routing unknown; call set_rx() first".into())
+            })?;
+            let previous = latch.clone();
+            (latch.press(current), previous)
+        };
+        let (radio, mode) = target;
+        if let Err(e) = self.write_rx(radio, mode).await {
+            *latch.lock().unwrap() = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Whether the footswitch latch is currently engaged.
+    pub fn is_latched(&self) -> bool {
+        self.latch
+            .as_ref()
+            .is_some_and(|latch| latch.lock().unwrap().is_latched())
+    }
+
+    /// Mark the start or end of a transmission for the transmit latch.
+    ///
+    /// Transmitting moves RX audio (mono) to the radio without TX focus, so
+    /// the operator keeps listening on the other band; ending the
+    /// transmission restores the routing heard before. Devices that report
+    /// PTT drive the latch themselves once events are enabled. An explicit
+    /// [`set_rx()`](So2rSwitch::set_rx) while transmitting is kept when the
+    /// transmission ends.
+    ///
+    /// Requires [`OtrspBuilder::transmit_latch`](crate::OtrspBuilder::transmit_latch)
+    /// and a prior `set_tx()` so the transmitting radio is known.
+    pub async fn set_transmitting(&self, transmitting: bool) -> Result<()> {
+        let latch = self
+            .transmit_latch
+            .as_ref()
+            .ok_or_else(|| Error::Unsupported("transmit latch not enabled".into()))?;
+        let (tx, current) = {
+            let state = self.state.lock().unwrap();
+            (state.tx, state.rx)
+        };
+        let target = if transmitting {
+            let tx = tx.ok_or_else(|| {
+                Error::InvalidParameter("TX radio unknown; call set_tx() first".into())
+            })?;
+            latch.lock().unwrap().key(tx, current)
+        } else {
+            latch.lock().unwrap().unkey(current)
+        };
+        match target {
+            Some((radio, mode)) => self.write_rx(radio, mode).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the transmit latch has a transmission under way.
+    pub fn is_transmitting(&self) -> bool {
+        self.transmit_latch
+            .as_ref()
+            .is_some_and(|latch| latch.lock().unwrap().is_transmitting())
+    }
+
+    /// Report that the operator keyed `radio` outside the library (paddle, footswitch, hand mic).
+    ///
+    /// Keying a radio means the device has routed TX to it, so the cached TX
+    /// state is updated without writing to the port. Emits `TxChanged` and
+    /// returns `true` when this differs from the cached TX radio; a matching
+    /// report only confirms the cache. With
+    /// [`rx_follows_tx`](crate::OtrspBuilder::rx_follows_tx), a change of
+    /// radio also moves RX; the command is queued, not awaited.
+    pub fn report_ptt(&self, radio: Radio) -> bool {
+        let changed = self.state.lock().unwrap().tx.replace(radio) != Some(radio);
+        if changed {
+            debug!(?radio, "external PTT moved TX focus");
+            let _ = self.event_tx.send(SwitchEvent::TxChanged {
+                radio,
+                origin: Origin::Device,
+            });
+            self.follow_ptt(radio);
+        }
+        changed
+    }
+
+    /// Queue the RX change that follows TX focus moved to `radio` by PTT.
+    fn follow_ptt(&self, radio: Radio) {
+        let Some(follow) = self.rx_follow else {
+            return;
+        };
+        let current = self.state.lock().unwrap().rx;
+        let (radio, mode) = follow.routing(radio, current);
+        if current == Some((radio, mode)) {
+            return;
+        }
+        let command = Command::Rx(radio, mode);
+        let result = self.check_unanswered(&command).and_then(|()| {
+            let data = self.extensions.read().unwrap().encode_command(&command)?;
+            self.io.schedule_now(command, data)
+        });
+        if let Err(e) = result {
+            warn!(?radio, ?mode, "RX could not follow PTT: {e}");
+        }
+    }
+
+    /// Report that PTT/keying was released, starting the TX tail.
+    ///
+    /// With [`OtrspBuilder::ptt_timing`](crate::OtrspBuilder::ptt_timing), a
+    /// `set_tx()` that changes radio waits until the tail has expired.
+    pub fn report_unkey(&self) {
+        *self.last_unkey.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Wait until the PTT tail following the last unkey has expired.
+    async fn wait_ptt_tail(&self) {
+        let Some(unkeyed) = *self.last_unkey.lock().unwrap() else {
+            return;
+        };
+        let until = unkeyed + self.ptt_tail;
+        if until > Instant::now() {
+            trace!(tail = ?self.ptt_tail, "waiting for PTT tail before switching TX");
+            tokio::time::sleep_until(until).await;
+        }
+    }
+
+    /// Re-identify the device and restore the cached routing after a reboot.
+    ///
+    /// Re-runs the `?NAME` handshake, re-sends the cached TX, RX and AUX
+    /// state, and emits [`SwitchEvent::DeviceReset`]. Called automatically
+    /// when [`OtrspBuilder::detect_resets`](crate::OtrspBuilder::detect_resets)
+    /// spots a reboot; applications can also call it when they know the box
+    /// was power-cycled.
+    pub async fn reinitialize(&self) -> Result<()> {
+        self.unanswered.store(0, Ordering::Relaxed);
+        match self.io.command_read(protocol::encode_query_name()).await {
+            Ok(response) => {
+                let name = protocol::parse_name_response(&response);
+                info!(name = %name, "device re-identified");
+                self.state.lock().unwrap().name = Some(name);
+            }
+            Err(e) => warn!("failed to re-identify device: {e}"),
+        }
+
+        // The device came back with its power-on routing, so restore everything.
+        let state = self.state();
+        for command in SwitchState::diff(&SwitchState::default(), &state) {
+            let data = self.extensions.read().unwrap().encode_command(&command)?;
+            self.io.command(data).await?;
+        }
+        if state.events {
+            self.io.command(protocol::encode_event(true)).await?;
+        }
+
+        let _ = self.event_tx.send(SwitchEvent::DeviceReset);
+        Ok(())
+    }
+
+    /// Reset an Arduino-based switch by pulsing DTR, then re-identify it.
+    ///
+    /// Asserts DTR for the pulse set with
+    /// [`OtrspBuilder::dtr_reset()`](crate::OtrspBuilder::dtr_reset), clears
+    /// it again, and waits for the bootloader to hand over before
+    /// [`reinitialize()`](Self::reinitialize) re-runs `?NAME` and restores the
+    /// cached routing. The way out when an SO2RDuino wedges mid-contest.
+    ///
+    /// Only serial ports opened by the builder have a DTR line; other links
+    /// fail with [`Error::Unsupported`].
+    pub async fn reset_hardware(&self) -> Result<()> {
+        let Some(dtr) = &self.dtr else {
+            return Err(Error::Unsupported(
+                "hardware reset needs a serial port with a DTR line".into(),
+            ));
+        };
+        info!(pulse = ?self.dtr_pulse, "resetting device with a DTR pulse");
+        dtr.set(true).await?;
+        tokio::time::sleep(self.dtr_pulse).await;
+        dtr.set(false).await?;
+        debug!(delay = ?self.bootloader_delay, "waiting for bootloader");
+        tokio::time::sleep(self.bootloader_delay).await;
+        self.reinitialize().await
+    }
+
+    /// Send a query, watching for the unresponsive-then-recovered reboot pattern.
+    ///
+    /// The device's unknown-command reply becomes [`Error::UnsupportedCommand`].
+    async fn query(&self, data: Vec<u8>) -> Result<Bytes> {
+        let command = String::from_utf8_lossy(data.trim_ascii_end()).into_owned();
+        let result = self.io.command_read(data).await;
+        match &result {
+            Err(Error::Timeout) => {
+                self.unanswered.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(_) => {
+                let missed = self.unanswered.swap(0, Ordering::Relaxed);
+                if self.reset_after_timeouts > 0 && missed >= self.reset_after_timeouts {
+                    warn!(
+                        missed,
+                        "device answered after being unresponsive, assuming reboot"
+                    );
+                    self.reinitialize().await?;
+                }
+            }
+            Err(_) => {}
+        }
+        let response = result?;
+        if let Ok(Response::Error(reply)) = protocol::parse_response(&response) {
+            debug!(%command, %reply, "device rejected command");
+            return Err(Error::UnsupportedCommand { command });
+        }
+        Ok(response)
+    }
+
+    /// Measure the round-trip time of a `?NAME` query.
+    ///
+    /// A cheap health check; also shows the latency added by remote-serial
+    /// setups. Fails with [`Error::Timeout`] if the device does not answer.
+    pub async fn ping(&self) -> Result<Duration> {
+        let started = Instant::now();
+        self.query(protocol::encode_query_name()).await?;
+        let rtt = started.elapsed();
+        trace!(?rtt, "ping");
+        Ok(rtt)
+    }
+
+    /// Check an AUX port number against the declared capabilities.
+    fn check_aux_port(&self, port: u8) -> Result<()> {
+        let ports = self.capabilities.aux_ports;
+        if port == 0 || port > ports {
+            return Err(Error::InvalidParameter(format!(
+                "AUX port {port} not available, device has ports 1-{ports}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Send an RX command and record the resulting routing.
+    async fn write_rx(&self, radio: Radio, mode: RxMode) -> Result<()> {
+        let data = self.extensions.read().unwrap().encode_rx(radio, mode)?;
+        self.io.update(data).await?;
+        self.state.lock().unwrap().rx = Some((radio, mode));
+        let _ = self.event_tx.send(SwitchEvent::RxChanged {
+            radio,
+            mode,
+            origin: Origin::Host,
+        });
+        Ok(())
+    }
+
+    /// Send several commands in a single write.
+    ///
+    /// Saves a round trip per command when TX, RX and AUX change together,
+    /// e.g. at a band change. Every command is validated before anything is
+    /// sent; queries are rejected, as their answers would go unread. On
+    /// success the state cache and events are updated as if each command had
+    /// been sent on its own. Redundant commands are not skipped.
+    pub async fn send_batch(&self, commands: &[Command]) -> Result<()> {
+        for command in commands {
+            self.check_unanswered(command)?;
+        }
+        let data = {
+            let extensions = self.extensions.read().unwrap();
+            let encoded: Result<Vec<_>> = commands
+                .iter()
+                .map(|command| extensions.encode_command(command))
+                .collect();
+            encoded?.concat()
+        };
+        self.replay_offline().await?;
+        let current_tx = self.state.lock().unwrap().tx;
+        let switching = commands
+            .iter()
+            .any(|c| matches!(*c, Command::Tx(radio) if current_tx != Some(radio)));
+        if switching {
+            self.wait_ptt_tail().await;
+        }
+        self.io.command(data).await?;
+        for command in commands {
+            self.record_sent(command);
+        }
+        if switching && !self.ptt_lead.is_zero() {
+            trace!(lead = ?self.ptt_lead, "waiting for TX relays to settle");
+            tokio::time::sleep(self.ptt_lead).await;
+        }
+        Ok(())
+    }
+
+    /// Check a command sent without reading an answer against the declared
+    /// capabilities. Queries are rejected, as their answers would go unread.
+    fn check_unanswered(&self, command: &Command) -> Result<()> {
+        match *command {
+            _ if command.is_query() => Err(Error::InvalidParameter(format!(
+                "{command:?} cannot be sent unanswered, its answer would go unread"
+            ))),
+            Command::Rx(_, mode) if !self.capabilities.supports_rx(mode) => Err(
+                Error::Unsupported(format!("RX mode {mode:?} not supported by this device")),
+            ),
+            Command::Aux { port, value } => {
+                self.check_aux_port(port)?;
+                self.capabilities.check_aux_value(port, value)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Update the cache and announce a state command the host has sent.
+    fn record_sent(&self, command: &Command) {
+        if let Command::Rx(..) = command {
+            if let Some(latch) = &self.latch {
+                latch.lock().unwrap().release();
+            }
+            if let Some(latch) = &self.transmit_latch {
+                latch.lock().unwrap().release();
+            }
+        }
+        let event = self.state.lock().unwrap().apply(command);
+        if let Some(event) = event {
+            let _ = self.event_tx.send(event);
+        }
+    }
+
+    /// Send `command` at `at`, without waiting for it.
+    ///
+    /// The IO task holds the command and sends it once it is due and no
+    /// query is waiting, then updates the state cache and emits the
+    /// command's event. Sequences such as "pulse AUX 1, release it 250 ms
+    /// later" then need no timers in the application. Returns once the
+    /// command is scheduled; a failed send is logged. Commands are validated
+    /// as for [`send_batch()`](Self::send_batch), so queries are rejected. A
+    /// scheduled TX change does not wait out the PTT tail, and commands still
+    /// scheduled when the device closes are dropped.
+    pub async fn send_at(&self, command: Command, at: Instant) -> Result<()> {
+        self.check_unanswered(&command)?;
+        let data = self.extensions.read().unwrap().encode_command(&command)?;
+        self.replay_offline().await?;
+        self.io.schedule(command, data, at).await
+    }
+
+    /// Send `command` once `delay` has passed; see [`send_at()`](Self::send_at).
+    pub async fn send_after(&self, command: Command, delay: Duration) -> Result<()> {
+        self.send_at(command, Instant::now() + delay).await
+    }
+
+    /// Query the device for its full state and refresh the cache.
+    ///
+    /// Runs `?NAME`, `?TX`, `?RX` and `?AUXp` for each of the
+    /// [`aux_ports`](SwitchCapabilities::aux_ports) (1-based). A query the
+    /// device leaves unanswered or rejects is treated as unsupported and
+    /// keeps the cached value; any other error aborts. Intended for resynchronizing after
+    /// attach or reconnect.
+    pub async fn query_all(&self) -> Result<SwitchState> {
+        let name = if_supported(self.device_name().await)?;
+        // These update the cached routing themselves.
+        if_supported(self.query_tx().await)?;
+        if_supported(self.query_rx().await)?;
+        let mut aux = BTreeMap::new();
+        for port in 1..=self.capabilities.aux_ports {
+            if let Some(value) = if_supported(self.query_aux_wide(port).await)? {
+                aux.insert(port, value);
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if name.is_some() {
+            state.name = name;
+        }
+        state.aux.extend(aux);
+        Ok(state.clone())
+    }
+
+    /// Enable or disable unsolicited event reporting (`EVENT1` / `EVENT0`).
+    ///
+    /// While enabled, the IO task reads `$` notification lines and turns
+    /// them into [`SwitchEvent`]s (front-panel changes, footswitch, PTT).
+    /// The setting is tracked in [`SwitchState::events`].
+    pub async fn enable_events(&self, enabled: bool) -> Result<()> {
+        self.io.command(protocol::encode_event(enabled)).await?;
+        self.io.set_listening(enabled).await?;
+        self.state.lock().unwrap().events = enabled;
+        debug!(enabled, "event reporting");
+        Ok(())
+    }
+
+    /// Select `band` on `radio`'s band decoder.
+    ///
+    /// Writes the band's code from the builder's
+    /// [`bcd_map`](crate::OtrspBuilder::bcd_map) to AUX port 1 for Radio 1
+    /// and AUX port 2 for Radio 2, unless
+    /// [`band_decoder()`](crate::OtrspBuilder::band_decoder) set another
+    /// port or table for the radio.
+    pub async fn set_band(&self, radio: Radio, band: Band) -> Result<()> {
+        let (port, map) = match radio {
+            Radio::Radio1 => &self.band_decoders[0],
+            Radio::Radio2 => &self.band_decoders[1],
+        };
+        self.set_aux(*port, map.code(band)).await
+    }
+
+    /// Query the device firmware version (`?VERSION`).
+    pub async fn device_version(&self) -> Result<String> {
+        let response = self.query(protocol::encode_query_version()).await?;
+        Ok(protocol::parse_version_response(&response))
+    }
+
+    /// Bring the switch to `target`, sending only the commands that change something.
+    ///
+    /// Fields left unset in `target` are not touched. Commands go through
+    /// [`set_tx()`](So2rSwitch::set_tx), [`set_rx()`](So2rSwitch::set_rx) and
+    /// [`set_aux()`](So2rSwitch::set_aux), so validation and events apply as
+    /// usual. Returns the commands that were sent.
+    ///
+    /// Differs from [`set_state()`](Self::set_state) in that each command is
+    /// a separate write and is checked only when its turn comes: a failure
+    /// part way leaves the earlier commands applied. While disconnected with
+    /// an [`offline_queue`](crate::OtrspBuilder::offline_queue), the commands
+    /// are parked for replay instead of failing.
+    pub async fn apply_state(&self, target: &SwitchState) -> Result<Vec<Command>> {
+        let commands = SwitchState::diff(&self.state(), target);
+        for command in &commands {
+            self.apply_command(command).await?;
+        }
+        Ok(commands)
+    }
+
+    /// Bring the switch to `target` in a single write, sending only the
+    /// commands that change something.
+    ///
+    /// Like [`apply_state()`](Self::apply_state), but the commands go out
+    /// together through [`send_batch()`](Self::send_batch), so restoring a
+    /// saved setup switches TX, RX and AUX at once. Every command is checked
+    /// before anything is written, so an invalid one means nothing is sent,
+    /// and a lost link fails the call rather than parking the commands in
+    /// the offline queue. Fields left unset in `target` are not touched.
+    /// Returns the commands that were sent. To push the whole cached state
+    /// to a box that lost it, e.g. after a power cycle, use
+    /// [`reinitialize()`](Self::reinitialize).
+    pub async fn set_state(&self, target: &SwitchState) -> Result<Vec<Command>> {
+        let commands = SwitchState::diff(&self.state(), target);
+        if !commands.is_empty() {
+            self.send_batch(&commands).await?;
+        }
+        Ok(commands)
+    }
+
+    /// State commands accepted while disconnected and not yet sent.
+    ///
+    /// Only populated with [`OtrspBuilder::offline_queue`](crate::OtrspBuilder::offline_queue).
+    pub fn offline_backlog(&self) -> SwitchState {
+        self.offline.lock().unwrap().clone()
+    }
+
+    /// Send the state commands accepted while disconnected.
+    ///
+    /// The backlog is replayed as a diff against the current state, so only
+    /// the net result of the offline commands goes out. Runs automatically
+    /// when the link comes back ([`SwitchEvent::Reconnected`]) and before
+    /// each state command. Commands that fail again with a connection error
+    /// stay in the backlog; on any other error, the commands not yet sent
+    /// are put back and the error is returned.
+    pub async fn replay_offline(&self) -> Result<()> {
+        let backlog = std::mem::take(&mut *self.offline.lock().unwrap());
+        if backlog == SwitchState::default() {
+            return Ok(());
+        }
+        let commands = SwitchState::diff(&self.state(), &backlog);
+        info!(
+            count = commands.len(),
+            "replaying commands queued while offline"
+        );
+        for (sent, command) in commands.iter().enumerate() {
+            if let Err(e) = self.apply_command(command).await {
+                let mut offline = self.offline.lock().unwrap();
+                for command in &commands[sent..] {
+                    offline.restore(command);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a failed state command in the offline backlog when queueing is
+    /// enabled and the failure is a lost connection; otherwise return the error.
+    ///
+    /// With [`reconnect()`](crate::OtrspBuilder::reconnect), commands wait
+    /// for the link and time out, so a timeout while the link is down counts
+    /// as a lost connection too.
+    fn park_offline(&self, err: Error, record: impl FnOnce(&mut SwitchState)) -> Result<()> {
+        let offline = err.is_connection_error()
+            || (err.is_timeout() && !self.state.lock().unwrap().connected);
+        if !self.offline_queue || !offline {
+            return Err(err);
+        }
+        warn!("link down, queueing command for replay: {err}");
+        record(&mut self.offline.lock().unwrap());
+        Ok(())
+    }
+
+    /// Send a raw command without validating it (CR terminator appended).
+    ///
+    /// Unlike [`send_raw()`](So2rSwitch::send_raw), control characters and
+    /// over-long commands are passed through as-is.
+    pub async fn send_raw_unchecked(&self, command: &str) -> Result<()> {
+        let data = protocol::encode_raw_unchecked(command);
+        self.io.command(data).await
+    }
+
+    /// Register (or replace) a vendor command for
+    /// [`vendor_command()`](Self::vendor_command) and
+    /// [`vendor_query()`](Self::vendor_query).
+    ///
+    /// `encode` turns the argument string into the bytes to send, CR
+    /// terminator included.
+    pub fn register_command<F>(&self, name: &str, encode: F)
+    where
+        F: Fn(&str) -> Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.extensions.write().unwrap().add_command(name, encode);
+    }
+
+    /// Register a parser for vendor responses starting with `prefix`.
+    ///
+    /// Parsers registered later take precedence when prefixes overlap.
+    pub fn register_parser<T, F>(&self, prefix: &str, parse: F)
+    where
+        T: std::any::Any + Send,
+        F: Fn(&[u8]) -> Result<T> + Send + Sync + 'static,
+    {
+        self.extensions.write().unwrap().add_parser(prefix, parse);
+    }
+
+    /// Send a registered vendor command without waiting for an answer.
+    ///
+    /// Vendor commands bypass the state cache.
+    pub async fn vendor_command(&self, name: &str, args: &str) -> Result<()> {
+        let data = self.extensions.read().unwrap().encode(name, args)?;
+        self.io.command(data).await
+    }
+
+    /// Send a registered vendor command and parse the answer.
+    ///
+    /// The answer is handed to the parser registered for its prefix; `T` must
+    /// be the type that parser returns.
+    pub async fn vendor_query<T: std::any::Any>(&self, name: &str, args: &str) -> Result<T> {
+        let data = self.extensions.read().unwrap().encode(name, args)?;
+        let response = self.query(data).await?;
+        self.extensions.read().unwrap().parse(&response)
+    }
+
+    /// Bytes, commands and errors on the link since the device was built.
+    pub fn stats(&self) -> LinkStats {
+        self.io.stats.snapshot()
+    }
+
+    /// Outcome and latency of every exchange the IO task made since the
+    /// device was built.
+    pub fn metrics(&self) -> LinkMetrics {
+        *self.io.metrics.lock().unwrap()
+    }
+
+    /// Suspend the IO task and take exclusive access to the open link, e.g.
+    /// to flash new firmware through the device's bootloader.
+    ///
+    /// Waits for the commands already queued to finish. Commands sent while
+    /// the lease is held wait until it is dropped, which resumes the IO task.
+    pub async fn lease_port(&self) -> Result<PortLease> {
+        self.io.lease().await
+    }
+
+    /// Commands waiting to be sent or answered, and how long the oldest has
+    /// waited.
+    ///
+    /// An oldest command well past the query timeout means the link has
+    /// stalled, before any command fails.
+    pub fn pending_commands(&self) -> PendingCommands {
+        self.io.pending()
+    }
+
+    /// The command/response transcript, if enabled on the builder.
+    pub fn transcript(&self) -> Option<&Transcript> {
+        self.io.transcript.as_ref()
+    }
+
+    /// Snapshot of the routing last sent to, or reported by, the device.
+    pub fn state(&self) -> SwitchState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Watch the cached state, including whether the link is up.
+    ///
+    /// A UI that only renders the latest state can read it with
+    /// [`borrow()`](watch::Receiver::borrow) and await
+    /// [`changed()`](watch::Receiver::changed), instead of consuming every
+    /// event from [`subscribe()`](So2rSwitch::subscribe).
+    pub fn watch_state(&self) -> watch::Receiver<SwitchState> {
+        self.state_watch.clone()
+    }
+
+    /// Get the protocol extensions detected during negotiation.
+    pub fn features(&self) -> &ProtocolFeatures {
+        &self.features
+    }
+}
+
+/// Map a query timeout or rejection to `None` (query unsupported), passing
+/// other results through.
+fn if_supported<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::Timeout | Error::UnsupportedCommand { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
 }
@@ -0,0 +1,132 @@
+//! Finding the serial port a switch is attached to.
+//!
+//! SO2R boxes attach through a handful of USB serial chips. A [`PortFilter`]
+//! narrows [`list_ports()`](crate::transport::list_ports) to those adapters
+//! and ranks the user's own box first by its USB serial number:
+//!
+//! ```no_run
+//! use otrsp::discovery::{self, PortFilter};
+//!
+//! let filter = PortFilter::known_adapters().prefer_serial("A10KXYZ1");
+//! for port in discovery::discover(&filter)? {
+//!     println!("{}", port.name);
+//! }
+//! # Ok::<(), otrsp::Error>(())
+//! ```
+
+use crate::error::Result;
+use crate::transport::{self, PortInfo};
+
+/// A USB vendor/product ID pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UsbId {
+    /// Vendor ID.
+    pub vid: u16,
+    /// Product ID.
+    pub pid: u16,
+}
+
+impl UsbId {
+    /// The pair `vid:pid`.
+    pub const fn new(vid: u16, pid: u16) -> Self {
+        Self { vid, pid }
+    }
+}
+
+/// USB serial adapters found in common SO2R kits: FTDI FT232R and FT231X,
+/// WCH CH340, Silicon Labs CP210x, and the Arduino Uno and Leonardo.
+pub const KNOWN_ADAPTERS: [UsbId; 6] = [
+    UsbId::new(0x0403, 0x6001),
+    UsbId::new(0x0403, 0x6015),
+    UsbId::new(0x1A86, 0x7523),
+    UsbId::new(0x10C4, 0xEA60),
+    UsbId::new(0x2341, 0x0043),
+    UsbId::new(0x2341, 0x8036),
+];
+
+/// Which ports discovery returns, and in what order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortFilter {
+    ids: Vec<UsbId>,
+    serials: Vec<String>,
+}
+
+impl PortFilter {
+    /// Match every port.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match the adapters in [`KNOWN_ADAPTERS`].
+    pub fn known_adapters() -> Self {
+        Self::new().usb_ids(KNOWN_ADAPTERS)
+    }
+
+    /// Also match USB adapters with this vendor/product ID.
+    pub fn usb_id(mut self, vid: u16, pid: u16) -> Self {
+        self.ids.push(UsbId::new(vid, pid));
+        self
+    }
+
+    /// Also match USB adapters with any of these IDs.
+    pub fn usb_ids(mut self, ids: impl IntoIterator<Item = UsbId>) -> Self {
+        self.ids.extend(ids);
+        self
+    }
+
+    /// Rank the adapter with this USB serial number ahead of the others.
+    ///
+    /// Serial numbers given earlier rank higher.
+    pub fn prefer_serial(mut self, serial_number: &str) -> Self {
+        self.serials.push(serial_number.to_string());
+        self
+    }
+
+    /// Whether `port` passes the filter. With no IDs given, every port does.
+    pub fn matches(&self, port: &PortInfo) -> bool {
+        if self.ids.is_empty() {
+            return true;
+        }
+        match (port.vid, port.pid) {
+            (Some(vid), Some(pid)) => self.ids.contains(&UsbId::new(vid, pid)),
+            _ => false,
+        }
+    }
+
+    /// Keep the matching ports, preferred serial numbers first.
+    ///
+    /// Otherwise the input order is kept.
+    pub fn apply(&self, ports: Vec<PortInfo>) -> Vec<PortInfo> {
+        let mut ports: Vec<PortInfo> = ports.into_iter().filter(|p| self.matches(p)).collect();
+        ports.sort_by_key(|port| self.rank(port));
+        ports
+    }
+
+    /// Position of the port's serial number among the preferred ones.
+    fn rank(&self, port: &PortInfo) -> usize {
+        port.serial_number
+            .as_ref()
+            .and_then(|serial| self.serials.iter().position(|s| s == serial))
+            .unwrap_or(self.serials.len())
+    }
+}
+
+/// The serial ports passing `filter`, best candidates first.
+pub fn discover(filter: &PortFilter) -> Result<Vec<PortInfo>> {
+    Ok(filter.apply(transport::list_ports()?))
+}
+
+/// Progress of [`OtrspBuilder::detect()`](crate::OtrspBuilder::detect),
+/// for showing the scan in a GUI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DetectEvent {
+    /// The scan is starting on these ports, in this order.
+    Scanning { ports: Vec<String> },
+    /// Sending `?NAME` on a port.
+    Probing { port: String },
+    /// The port did not yield the wanted device.
+    Skipped { port: String, reason: String },
+    /// A device answered; the builder connects to it.
+    Found { port: String, name: String },
+}
@@ -1,5 +1,9 @@
 /// Errors returned by the OTRSP library.
+///
+/// Marked `#[non_exhaustive]` so new error kinds can be added without a
+/// breaking release; classify with the `is_*` accessors where possible.
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     #[error("transport error: {0}")]
     Transport(String),
@@ -13,6 +17,10 @@ pub enum Error {
     #[error("unsupported operation: {0}")]
     Unsupported(String),
 
+    /// The device answered with its unknown-command reply.
+    #[error("device does not support command: {command}")]
+    UnsupportedCommand { command: String },
+
     #[error("invalid parameter: {0}")]
     InvalidParameter(String),
 
@@ -22,8 +30,40 @@ pub enum Error {
     #[error("connection lost")]
     ConnectionLost,
 
+    /// The request queue was full under [`QueuePolicy::FailFast`](crate::queue::QueuePolicy::FailFast).
+    #[error("request queue full")]
+    Busy,
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+impl Error {
+    /// Whether the device failed to answer in time.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Timeout)
+    }
+
+    /// Whether the operation is not available on this device or backend.
+    ///
+    /// Covers both commands the device rejected and operations the library
+    /// knows to be unsupported, for feature detection at runtime.
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self, Self::Unsupported(_) | Self::UnsupportedCommand { .. })
+    }
+
+    /// Whether the command was refused because the request queue was full.
+    pub fn is_busy(&self) -> bool {
+        matches!(self, Self::Busy)
+    }
+
+    /// Whether the error means the link to the device is down.
+    pub fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            Self::NotConnected | Self::ConnectionLost | Self::Transport(_) | Self::Io(_)
+        )
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1,19 +1,181 @@
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
 use crate::types::{Radio, RxMode};
 
-/// Events emitted by the OTRSP library when commands succeed.
+/// Events emitted by the OTRSP library.
+///
+/// State changes are emitted when host commands succeed, and also when a
+/// device with events enabled reports a front-panel change; [`Origin`] tells
+/// the two apart.
 ///
-/// These are library-generated state transitions (not device-originated data,
-/// since OTRSP devices send no unsolicited messages).
-#[derive(Debug, Clone)]
+/// Marked `#[non_exhaustive]` so new event kinds can be added without a
+/// breaking release; match with a wildcard arm or use the accessors below.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum SwitchEvent {
     /// TX routing changed to the specified radio.
-    TxChanged { radio: Radio },
+    TxChanged { radio: Radio, origin: Origin },
     /// RX audio routing changed.
-    RxChanged { radio: Radio, mode: RxMode },
+    RxChanged {
+        radio: Radio,
+        mode: RxMode,
+        origin: Origin,
+    },
     /// AUX output changed.
-    AuxChanged { port: u8, value: u8 },
+    AuxChanged {
+        port: u8,
+        value: u16,
+        origin: Origin,
+    },
+    /// Computer keying (CR) routing changed to the specified radio.
+    KeyingChanged { radio: Radio, origin: Origin },
     /// Connected to the device.
     Connected,
     /// Disconnected from the device.
     Disconnected,
+    /// The device rebooted mid-session; it was re-identified and the cached
+    /// routing was restored.
+    DeviceReset,
+    /// The device reported the footswitch being pressed or released.
+    FootswitchChanged { pressed: bool },
+    /// The device reported PTT keyed on a radio, or released (`None`).
+    PttChanged { radio: Option<Radio> },
+    /// A [`FailoverSwitch`](crate::failover::FailoverSwitch) gave up on its
+    /// primary device and moved to the backup, replaying the routing state.
+    FailedOver,
+    /// The link to the device dropped and a
+    /// [`ReconnectingPort`](crate::reconnect::ReconnectingPort) is reopening it.
+    Reconnecting,
+    /// The link was reopened after [`Reconnecting`](Self::Reconnecting).
+    Reconnected,
+    /// The IO task stopped on an internal error (a panic). It is followed by
+    /// [`Reconnected`](Self::Reconnected) once a new task runs on a fresh
+    /// link, or by [`Disconnected`](Self::Disconnected) if it cannot be
+    /// restarted.
+    IoTaskFailed,
+}
+
+impl SwitchEvent {
+    /// Whether this is a connection-lifecycle event (`Connected`, `Disconnected`,
+    /// `DeviceReset`, `FailedOver`, `Reconnecting`, `Reconnected`,
+    /// `IoTaskFailed`).
+    pub fn is_connection_event(&self) -> bool {
+        matches!(
+            self,
+            Self::Connected
+                | Self::Disconnected
+                | Self::DeviceReset
+                | Self::FailedOver
+                | Self::Reconnecting
+                | Self::Reconnected
+                | Self::IoTaskFailed
+        )
+    }
+
+    /// Whether this is a switch state change (TX, RX, AUX or keying routing).
+    pub fn is_state_change(&self) -> bool {
+        matches!(
+            self,
+            Self::TxChanged { .. }
+                | Self::RxChanged { .. }
+                | Self::AuxChanged { .. }
+                | Self::KeyingChanged { .. }
+        )
+    }
+
+    /// Whether this reports operator hardware input (footswitch, PTT).
+    pub fn is_input_event(&self) -> bool {
+        matches!(
+            self,
+            Self::FootswitchChanged { .. } | Self::PttChanged { .. }
+        )
+    }
+
+    /// The radio this event refers to, if any.
+    pub fn radio(&self) -> Option<Radio> {
+        match self {
+            Self::TxChanged { radio, .. }
+            | Self::RxChanged { radio, .. }
+            | Self::KeyingChanged { radio, .. } => Some(*radio),
+            Self::PttChanged { radio } => *radio,
+            _ => None,
+        }
+    }
+
+    /// Who initiated a state change, if this is one.
+    pub fn origin(&self) -> Option<Origin> {
+        match self {
+            Self::TxChanged { origin, .. }
+            | Self::RxChanged { origin, .. }
+            | Self::AuxChanged { origin, .. }
+            | Self::KeyingChanged { origin, .. } => Some(*origin),
+            _ => None,
+        }
+    }
+}
+
+/// Where a state change came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// A command sent by this library.
+    Host,
+    /// The device itself (front-panel control, footswitch, keying).
+    Device,
+}
+
+/// A broadcast subscription that only yields events matching a filter.
+///
+/// Returned by [`So2rSwitch::subscribe_connection()`](crate::So2rSwitch::subscribe_connection),
+/// [`subscribe_state()`](crate::So2rSwitch::subscribe_state) and
+/// [`subscribe_filtered()`](crate::So2rSwitch::subscribe_filtered).
+pub struct FilteredReceiver {
+    rx: broadcast::Receiver<SwitchEvent>,
+    filter: fn(&SwitchEvent) -> bool,
+    /// Returned before anything from `rx`.
+    initial: Option<SwitchEvent>,
+}
+
+impl FilteredReceiver {
+    /// Wrap a receiver so that only events passing `filter` are returned.
+    pub fn new(rx: broadcast::Receiver<SwitchEvent>, filter: fn(&SwitchEvent) -> bool) -> Self {
+        Self {
+            rx,
+            filter,
+            initial: None,
+        }
+    }
+
+    /// Return `event` first, if it passes the filter, e.g. the current
+    /// link status for a subscriber that arrives after it was announced.
+    pub fn starting_with(mut self, event: SwitchEvent) -> Self {
+        self.initial = (self.filter)(&event).then_some(event);
+        self
+    }
+
+    /// Receive the next matching event, skipping the rest.
+    pub async fn recv(&mut self) -> Result<SwitchEvent, RecvError> {
+        if let Some(event) = self.initial.take() {
+            return Ok(event);
+        }
+        loop {
+            let event = self.rx.recv().await?;
+            if (self.filter)(&event) {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Receive the next matching event if one is already queued.
+    pub fn try_recv(&mut self) -> Result<SwitchEvent, TryRecvError> {
+        if let Some(event) = self.initial.take() {
+            return Ok(event);
+        }
+        loop {
+            let event = self.rx.try_recv()?;
+            if (self.filter)(&event) {
+                return Ok(event);
+            }
+        }
+    }
 }
@@ -0,0 +1,147 @@
+//! Vendor extension registry.
+//!
+//! Many OTRSP boxes understand commands beyond the published set. Rather than
+//! patching [`protocol`](crate::protocol), applications register them on the
+//! device: a named command encoder turns arguments into wire bytes, and a
+//! response parser, chosen by line prefix, turns the answer into a typed
+//! value.
+//!
+//! ```no_run
+//! # async fn example(device: &otrsp::OtrspDevice) -> otrsp::Result<()> {
+//! device.register_command("temp", |_| Ok(b"?TEMP\r".to_vec()));
+//! device.register_parser("TEMP", |line| {
+//!     std::str::from_utf8(&line[4..])
+//!         .ok()
+//!         .and_then(|v| v.parse::<i16>().ok())
+//!         .ok_or_else(|| otrsp::Error::Protocol("bad TEMP answer".into()))
+//! });
+//! let celsius: i16 = device.vendor_query("temp", "").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A command registered as [`RX_MIXED`] also gives [`RxMode::Mixed`] its
+//! wire form, which OTRSP does not define.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::protocol::{self, Command};
+use crate::types::{Radio, RxMode};
+
+/// Name of the vendor command that encodes [`RxMode::Mixed`].
+///
+/// Its argument is the radio digit, `"1"` or `"2"`.
+pub const RX_MIXED: &str = "rx_mixed";
+
+type CommandFn = Arc<dyn Fn(&str) -> Result<Vec<u8>> + Send + Sync>;
+type ParserFn = Arc<dyn Fn(&[u8]) -> Result<Box<dyn Any + Send>> + Send + Sync>;
+
+/// Registered vendor commands and response parsers.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    commands: HashMap<String, CommandFn>,
+    parsers: Vec<(Vec<u8>, ParserFn)>,
+}
+
+impl Extensions {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register (or replace) a named command.
+    ///
+    /// `encode` receives the caller's argument string and returns the bytes
+    /// to send, CR terminator included.
+    pub fn add_command<F>(&mut self, name: &str, encode: F)
+    where
+        F: Fn(&str) -> Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.commands.insert(name.to_string(), Arc::new(encode));
+    }
+
+    /// Register a parser for response lines starting with `prefix`.
+    ///
+    /// `parse` receives the line without its CR/LF terminator. Parsers
+    /// registered later take precedence when prefixes overlap.
+    pub fn add_parser<T, F>(&mut self, prefix: &str, parse: F)
+    where
+        T: Any + Send,
+        F: Fn(&[u8]) -> Result<T> + Send + Sync + 'static,
+    {
+        let parse: ParserFn = Arc::new(move |line| Ok(Box::new(parse(line)?)));
+        self.parsers.push((prefix.as_bytes().to_vec(), parse));
+    }
+
+    /// Encode the named command with `args`.
+    pub fn encode(&self, name: &str, args: &str) -> Result<Vec<u8>> {
+        let encode = self.commands.get(name).ok_or_else(|| {
+            Error::Unsupported(format!("no vendor command registered as {name:?}"))
+        })?;
+        encode(args)
+    }
+
+    /// Encode an RX routing command, [`RxMode::Mixed`] through the command
+    /// registered as [`RX_MIXED`].
+    pub fn encode_rx(&self, radio: Radio, mode: RxMode) -> Result<Vec<u8>> {
+        if mode != RxMode::Mixed {
+            return protocol::encode_rx(radio, mode);
+        }
+        let digit = match radio {
+            Radio::Radio1 => "1",
+            Radio::Radio2 => "2",
+        };
+        self.encode(RX_MIXED, digit)
+    }
+
+    /// Encode `command` for the wire, like [`Command::encode()`] but with
+    /// the registered RX encoder.
+    pub fn encode_command(&self, command: &Command) -> Result<Vec<u8>> {
+        match *command {
+            Command::Rx(radio, mode) => self.encode_rx(radio, mode),
+            _ => command.encode(),
+        }
+    }
+
+    /// Parse `line` with the parser registered for its prefix.
+    ///
+    /// Fails with [`Error::Protocol`] if no parser matches or the parsed
+    /// value is not a `T`.
+    pub fn parse<T: Any>(&self, line: &[u8]) -> Result<T> {
+        let line = line.trim_ascii_end();
+        let (_, parse) = self
+            .parsers
+            .iter()
+            .rev()
+            .find(|(prefix, _)| line.starts_with(prefix))
+            .ok_or_else(|| {
+                Error::Protocol(format!(
+                    "no vendor parser for response \"{}\"",
+                    line.escape_ascii()
+                ))
+            })?;
+        parse(line)?.downcast().map(|value| *value).map_err(|_| {
+            Error::Protocol(format!(
+                "vendor parser for \"{}\" returned a different type",
+                line.escape_ascii()
+            ))
+        })
+    }
+}
+
+impl std::fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prefixes: Vec<_> = self
+            .parsers
+            .iter()
+            .map(|(p, _)| String::from_utf8_lossy(p))
+            .collect();
+        f.debug_struct("Extensions")
+            .field("commands", &self.commands.keys().collect::<Vec<_>>())
+            .field("parsers", &prefixes)
+            .finish()
+    }
+}
@@ -0,0 +1,303 @@
+//! Failover between a primary and a backup switch.
+//!
+//! Commands go to the primary. When it fails persistently — a connection
+//! error, or several timeouts in a row — the wrapper moves to the backup,
+//! replays the last known routing onto it, emits
+//! [`SwitchEvent::FailedOver`], and retries the command there.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::event::SwitchEvent;
+use crate::protocol::Command;
+use crate::state::SwitchState;
+use crate::switch::{So2rSwitch, SwitchCapabilities, SwitchInfo};
+use crate::types::{Radio, RxMode};
+
+type SwitchFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// An [`So2rSwitch`] that fails over from a primary to a backup device.
+pub struct FailoverSwitch {
+    primary: Box<dyn So2rSwitch>,
+    backup: Box<dyn So2rSwitch>,
+    on_backup: Arc<AtomicBool>,
+    timeouts: AtomicU32,
+    timeout_threshold: u32,
+    state: Mutex<SwitchState>,
+    event_tx: broadcast::Sender<SwitchEvent>,
+}
+
+impl FailoverSwitch {
+    /// Wrap `primary` and `backup`.
+    ///
+    /// Must be called within a Tokio runtime: events from whichever device
+    /// is active are forwarded to [`subscribe()`](So2rSwitch::subscribe) by
+    /// background tasks.
+    pub fn new(primary: Box<dyn So2rSwitch>, backup: Box<dyn So2rSwitch>) -> Self {
+        let (event_tx, _) = broadcast::channel(64);
+        let on_backup = Arc::new(AtomicBool::new(false));
+        forward(
+            primary.subscribe(),
+            event_tx.clone(),
+            on_backup.clone(),
+            false,
+        );
+        forward(
+            backup.subscribe(),
+            event_tx.clone(),
+            on_backup.clone(),
+            true,
+        );
+        Self {
+            primary,
+            backup,
+            on_backup,
+            timeouts: AtomicU32::new(0),
+            timeout_threshold: 3,
+            state: Mutex::new(SwitchState::default()),
+            event_tx,
+        }
+    }
+
+    /// Consecutive primary timeouts that count as a persistent failure (default: 3).
+    ///
+    /// Connection errors fail over immediately.
+    pub fn timeout_threshold(mut self, timeouts: u32) -> Self {
+        self.timeout_threshold = timeouts.max(1);
+        self
+    }
+
+    /// Whether commands are currently going to the backup.
+    pub fn is_failed_over(&self) -> bool {
+        self.on_backup.load(Ordering::SeqCst)
+    }
+
+    /// Routing state last applied through this wrapper.
+    pub fn state(&self) -> SwitchState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Move to the backup now and replay the routing state onto it.
+    ///
+    /// Does nothing if already failed over.
+    pub async fn fail_over(&self) -> Result<()> {
+        if self.on_backup.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        warn!(
+            primary = %self.primary.info().name,
+            backup = %self.backup.info().name,
+            "failing over to backup switch"
+        );
+        let _ = self.event_tx.send(SwitchEvent::FailedOver);
+
+        for command in SwitchState::diff(&SwitchState::default(), &self.state()) {
+            self.backup.apply_command(&command).await?;
+        }
+        info!("backup switch state restored");
+        Ok(())
+    }
+
+    fn active(&self) -> &dyn So2rSwitch {
+        if self.is_failed_over() {
+            &*self.backup
+        } else {
+            &*self.primary
+        }
+    }
+
+    /// Run `op` on the active device, failing over on persistent failure.
+    async fn run<T, F>(&self, op: F) -> Result<T>
+    where
+        F: for<'a> Fn(&'a dyn So2rSwitch) -> SwitchFuture<'a, T> + Send + Sync,
+        T: Send,
+    {
+        if self.is_failed_over() {
+            return op(&*self.backup).await;
+        }
+        let err = match op(&*self.primary).await {
+            Ok(value) => {
+                self.timeouts.store(0, Ordering::SeqCst);
+                return Ok(value);
+            }
+            Err(e) => e,
+        };
+        let persistent = if err.is_connection_error() {
+            true
+        } else if err.is_timeout() {
+            self.timeouts.fetch_add(1, Ordering::SeqCst) + 1 >= self.timeout_threshold
+        } else {
+            false
+        };
+        if !persistent {
+            return Err(err);
+        }
+        warn!("primary switch failed: {err}");
+        self.fail_over().await?;
+        op(&*self.backup).await
+    }
+}
+
+/// Forward events from one device while it is the active one.
+fn forward(
+    mut rx: broadcast::Receiver<SwitchEvent>,
+    tx: broadcast::Sender<SwitchEvent>,
+    on_backup: Arc<AtomicBool>,
+    is_backup: bool,
+) {
+    tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    if on_backup.load(Ordering::SeqCst) == is_backup {
+                        let _ = tx.send(event);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[async_trait]
+impl So2rSwitch for FailoverSwitch {
+    fn info(&self) -> &SwitchInfo {
+        self.active().info()
+    }
+
+    fn capabilities(&self) -> &SwitchCapabilities {
+        self.active().capabilities()
+    }
+
+    async fn set_tx(&self, radio: Radio) -> Result<()> {
+        self.run(|s| s.set_tx(radio)).await?;
+        self.state.lock().unwrap().tx = Some(radio);
+        Ok(())
+    }
+
+    async fn set_rx(&self, radio: Radio, mode: RxMode) -> Result<()> {
+        self.run(|s| s.set_rx(radio, mode)).await?;
+        self.state.lock().unwrap().rx = Some((radio, mode));
+        Ok(())
+    }
+
+    /// Uses the cached TX focus, asking the active device only when it is unknown.
+    async fn other_radio(&self) -> Result<Radio> {
+        let cached = self.state.lock().unwrap().tx;
+        match cached {
+            Some(radio) => Ok(radio.other()),
+            None => self.run(|s| s.other_radio()).await,
+        }
+    }
+
+    async fn toggle_tx(&self) -> Result<Radio> {
+        let radio = self.run(|s| s.toggle_tx()).await?;
+        self.state.lock().unwrap().tx = Some(radio);
+        Ok(radio)
+    }
+
+    /// Uses the cached RX mode, mono if RX was never set.
+    async fn swap(&self) -> Result<Radio> {
+        let mode = self
+            .state
+            .lock()
+            .unwrap()
+            .rx
+            .map_or(RxMode::Mono, |(_, mode)| mode);
+        let radio = self.toggle_tx().await?;
+        self.set_rx(radio, mode).await?;
+        Ok(radio)
+    }
+
+    async fn set_aux(&self, port: u8, value: u8) -> Result<()> {
+        self.run(|s| s.set_aux(port, value)).await?;
+        self.state.lock().unwrap().aux.insert(port, value.into());
+        Ok(())
+    }
+
+    async fn set_aux_wide(&self, port: u8, value: u16) -> Result<()> {
+        self.run(|s| s.set_aux_wide(port, value)).await?;
+        self.state.lock().unwrap().aux.insert(port, value);
+        Ok(())
+    }
+
+    async fn apply_command(&self, command: &Command) -> Result<()> {
+        self.run(|s| {
+            let command = command.clone();
+            Box::pin(async move { s.apply_command(&command).await })
+        })
+        .await?;
+        self.state.lock().unwrap().apply(command);
+        Ok(())
+    }
+
+    async fn device_name(&self) -> Result<String> {
+        self.run(|s| s.device_name()).await
+    }
+
+    async fn query_aux(&self, port: u8) -> Result<u8> {
+        self.run(|s| s.query_aux(port)).await
+    }
+
+    async fn query_aux_wide(&self, port: u8) -> Result<u16> {
+        self.run(|s| s.query_aux_wide(port)).await
+    }
+
+    async fn query_tx(&self) -> Result<Radio> {
+        self.run(|s| s.query_tx()).await
+    }
+
+    async fn query_rx(&self) -> Result<(Radio, RxMode)> {
+        self.run(|s| s.query_rx()).await
+    }
+
+    async fn set_keying(&self, radio: Radio) -> Result<()> {
+        self.run(|s| s.set_keying(radio)).await?;
+        self.state.lock().unwrap().keying = Some(radio);
+        Ok(())
+    }
+
+    async fn query_keying(&self) -> Result<Radio> {
+        self.run(|s| s.query_keying()).await
+    }
+
+    async fn send_raw(&self, command: &str) -> Result<()> {
+        let command = command.to_string();
+        self.run(|s| {
+            let command = command.clone();
+            Box::pin(async move { s.send_raw(&command).await })
+        })
+        .await
+    }
+
+    async fn query_raw(&self, command: &str) -> Result<String> {
+        let command = command.to_string();
+        self.run(|s| {
+            let command = command.clone();
+            Box::pin(async move { s.query_raw(&command).await })
+        })
+        .await
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<SwitchEvent> {
+        self.event_tx.subscribe()
+    }
+
+    async fn close(&self) -> Result<()> {
+        let primary = self.primary.close().await;
+        let backup = self.backup.close().await;
+        match (primary, backup) {
+            (Err(e), _) | (_, Err(e)) if !matches!(e, Error::NotConnected) => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
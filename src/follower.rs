@@ -0,0 +1,155 @@
+//! Band follower: drive AUX outputs from radio frequency updates.
+//!
+//! Frequency feeds (CAT polling, logger broadcasts) report every step of the
+//! VFO. The follower maps each frequency to an AUX value and only writes a
+//! value once it has been stable for a settle window, so tuning across a band
+//! edge does not chatter the band-decoder relays.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::switch::So2rSwitch;
+use crate::types::Radio;
+
+/// Maps a frequency in Hz to the AUX value for that band (`None` = out of band).
+pub type FrequencyMap = Arc<dyn Fn(u64) -> Option<u8> + Send + Sync>;
+
+/// Configures and spawns a [`BandFollower`].
+pub struct BandFollowerBuilder {
+    map: FrequencyMap,
+    ports: [Option<u8>; 2],
+    settle: Duration,
+}
+
+impl BandFollowerBuilder {
+    /// Set the AUX port driven by `radio`'s band (default: Radio 1 on 1, Radio 2 on 2).
+    pub fn port(mut self, radio: Radio, port: u8) -> Self {
+        self.ports[index(radio)] = Some(port);
+        self
+    }
+
+    /// How long a new AUX value must stay stable before it is written (default: 250ms).
+    pub fn settle(mut self, settle: Duration) -> Self {
+        self.settle = settle;
+        self
+    }
+
+    /// Spawn the follower task driving `switch`.
+    pub fn spawn<S>(self, switch: Arc<S>) -> BandFollower
+    where
+        S: So2rSwitch + ?Sized + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(follow(switch, rx, self.map, self.ports, self.settle));
+        BandFollower { tx }
+    }
+}
+
+/// Handle to a running band follower task.
+///
+/// Clones feed the same task, which stops once every handle is dropped.
+#[derive(Clone)]
+pub struct BandFollower {
+    tx: mpsc::UnboundedSender<(Radio, u64)>,
+}
+
+impl BandFollower {
+    /// Start configuring a follower that maps frequencies with `map`.
+    ///
+    /// By default Radio 1 drives AUX port 1 and Radio 2 drives AUX port 2.
+    pub fn builder<F>(map: F) -> BandFollowerBuilder
+    where
+        F: Fn(u64) -> Option<u8> + Send + Sync + 'static,
+    {
+        BandFollowerBuilder {
+            map: Arc::new(map),
+            ports: [Some(1), Some(2)],
+            settle: Duration::from_millis(250),
+        }
+    }
+
+    /// Feed a frequency update (Hz) for a radio.
+    pub fn update(&self, radio: Radio, freq_hz: u64) {
+        let _ = self.tx.send((radio, freq_hz));
+    }
+}
+
+/// Per-radio debounce state.
+#[derive(Default)]
+struct Track {
+    /// Last value written to the AUX port.
+    written: Option<u8>,
+    /// Value waiting to settle, and when it may be written.
+    pending: Option<(u8, Instant)>,
+}
+
+async fn follow<S>(
+    switch: Arc<S>,
+    mut rx: mpsc::UnboundedReceiver<(Radio, u64)>,
+    map: FrequencyMap,
+    ports: [Option<u8>; 2],
+    settle: Duration,
+) where
+    S: So2rSwitch + ?Sized,
+{
+    let mut tracks: [Track; 2] = Default::default();
+
+    loop {
+        let next_due = tracks
+            .iter()
+            .filter_map(|t| t.pending.map(|(_, due)| due))
+            .min();
+
+        tokio::select! {
+            update = rx.recv() => {
+                let Some((radio, freq_hz)) = update else { break };
+                let track = &mut tracks[index(radio)];
+                match map(freq_hz) {
+                    Some(value) if Some(value) == track.written => track.pending = None,
+                    Some(value) if track.pending.map(|(v, _)| v) != Some(value) => {
+                        track.pending = Some((value, Instant::now() + settle));
+                    }
+                    Some(_) => {}
+                    None => {
+                        debug!(?radio, freq_hz, "frequency outside mapped bands");
+                        track.pending = None;
+                    }
+                }
+            }
+            _ = sleep_until_some(next_due) => {
+                let now = Instant::now();
+                for (i, track) in tracks.iter_mut().enumerate() {
+                    let Some((value, due)) = track.pending else { continue };
+                    if due > now {
+                        continue;
+                    }
+                    track.pending = None;
+                    let Some(port) = ports[i] else { continue };
+                    match switch.set_aux(port, value).await {
+                        Ok(()) => track.written = Some(value),
+                        Err(e) => warn!(port, value, "band follower AUX write failed: {e}"),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sleep until `deadline`, or forever if there is none.
+pub(crate) async fn sleep_until_some(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+fn index(radio: Radio) -> usize {
+    match radio {
+        Radio::Radio1 => 0,
+        Radio::Radio2 => 1,
+    }
+}
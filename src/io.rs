@@ -1,16 +1,46 @@
 //! IO task: single tokio task owns the serial port.
 //!
 //! Single mpsc channel (no priority split — all OTRSP commands are equal).
-//! No unsolicited data from devices, so no read arm in the select loop.
+//! Writes queued back to back go out in one write, in order.
+//! Each request is numbered and traced in a `request` span, entered again by
+//! the IO task when it writes the command and reads the answer.
+//! The port is read continuously. Answers complete the query waiting for
+//! them; once events are enabled, `$` notifications become state changes and
+//! operator input events (footswitch, PTT). Lines that arrive with no query
+//! waiting are kept for the next one, except late answers to a timed-out
+//! query, which are recognized and dropped.
 
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, trace, warn};
+use tracing::{Instrument, Span, debug, debug_span, error, info, trace, warn};
 
+use crate::audit;
+use crate::codec;
 use crate::error::{Error, Result};
-use crate::event::SwitchEvent;
+use crate::event::{Origin, SwitchEvent};
+use crate::extension::Extensions;
+use crate::latch::{FootswitchLatch, TransmitLatch};
+use crate::lease::PortLease;
+use crate::protocol::{self, Command, Notification, ParseMode, Response};
+use crate::queue::{PendingCommands, QueuePolicy};
+use crate::reconnect::ResetHandle;
+use crate::state::SwitchState;
+use crate::stats::{LinkMetrics, StatsCounters};
+use crate::transcript::{Transcript, TranscriptEntry};
+use crate::transport::BoxedTransport;
+use crate::types::{Radio, RxFollow};
 
 /// A request sent to the IO task.
 #[derive(Debug)]
@@ -19,60 +49,377 @@ pub(crate) enum Request {
     Write {
         data: Vec<u8>,
         reply: oneshot::Sender<Result<()>>,
+        /// Set for an RX routing update, which a newer one may make redundant.
+        update: Option<RoutingUpdate>,
+        span: Span,
     },
     /// Write bytes and read back a line response (for `?NAME`, `?AUX`).
     WriteAndRead {
         data: Vec<u8>,
-        reply: oneshot::Sender<Result<String>>,
+        /// Prefix the answer must start with, for standard queries.
+        expect: Option<Vec<u8>>,
+        reply: oneshot::Sender<Result<Bytes>>,
+        span: Span,
+    },
+    /// Hold a command and write it once `at` has passed.
+    Schedule {
+        at: Instant,
+        command: Command,
+        data: Vec<u8>,
+        span: Span,
     },
+    /// Lend the port out until the lease is dropped.
+    Lease { reply: oneshot::Sender<PortLease> },
+    /// Start or stop reading unsolicited notifications while idle.
+    Listen { enabled: bool },
     /// Shut down the IO task.
     Shutdown { reply: oneshot::Sender<Result<()>> },
 }
 
+/// A queued RX routing update.
+#[derive(Debug)]
+pub(crate) struct RoutingUpdate {
+    /// Set once the queue filled up behind this update under
+    /// [`QueuePolicy::DropOldest`].
+    superseded: Arc<AtomicBool>,
+    /// Position among the updates sent, counting from 1.
+    seq: u64,
+    queued: Instant,
+}
+
+/// IO task settings chosen on the builder.
+#[derive(Debug, Clone)]
+pub(crate) struct IoConfig {
+    /// The device echoes each command before answering.
+    pub echo: bool,
+    /// How received lines are normalized before parsing.
+    pub parse_mode: ParseMode,
+    /// Lines a standard query may skip while waiting for its answer.
+    pub skip_lines: usize,
+    /// Time a standard query may spend skipping lines.
+    pub skip_budget: Duration,
+    /// How long a query waits for its answer.
+    pub query_timeout: Duration,
+    /// Recorder for every command and its answer, if enabled.
+    pub transcript: Option<Transcript>,
+    /// Link activity counters.
+    pub stats: Arc<StatsCounters>,
+    /// Latency and outcome of each exchange, kept by the IO task.
+    pub metrics: Arc<Mutex<LinkMetrics>>,
+    /// Requests that may wait for the IO task.
+    pub capacity: usize,
+    /// What a command does when the queue is full.
+    pub policy: QueuePolicy,
+    /// Tries per write when it fails with a transient error.
+    pub write_attempts: u32,
+    /// Pause between write tries.
+    pub write_retry_delay: Duration,
+    /// Idle time after which the link is probed with `?NAME`.
+    pub keepalive: Option<Duration>,
+    /// Forces a reconnect when a keepalive goes unanswered.
+    pub link_reset: Option<ResetHandle>,
+    /// Age after which an RX update with a newer one behind it is dropped.
+    pub routing_deadline: Option<Duration>,
+    /// Sequence number of the newest RX update queued.
+    pub latest_update: Arc<AtomicU64>,
+    /// Transmit latch driven by device PTT reports, if enabled.
+    pub transmit_latch: Option<Arc<Mutex<TransmitLatch>>>,
+    /// Footswitch latch driven by device footswitch reports, if enabled.
+    pub footswitch_latch: Option<Arc<Mutex<FootswitchLatch>>>,
+    /// How RX follows TX focus moved by device PTT reports, if at all.
+    pub rx_follow: Option<RxFollow>,
+    /// State commands accepted while offline, replayed when the link is back.
+    pub offline: Option<Arc<Mutex<SwitchState>>>,
+    /// Vendor encoders, for RX modes outside the OTRSP command set.
+    pub extensions: Arc<RwLock<Extensions>>,
+}
+
+impl Default for IoConfig {
+    fn default() -> Self {
+        Self {
+            echo: false,
+            parse_mode: ParseMode::default(),
+            skip_lines: 0,
+            skip_budget: Duration::ZERO,
+            query_timeout: Duration::from_secs(1),
+            transcript: None,
+            stats: Arc::default(),
+            metrics: Arc::default(),
+            capacity: 32,
+            policy: QueuePolicy::default(),
+            write_attempts: 1,
+            write_retry_delay: Duration::ZERO,
+            keepalive: None,
+            link_reset: None,
+            routing_deadline: None,
+            latest_update: Arc::default(),
+            transmit_latch: None,
+            footswitch_latch: None,
+            rx_follow: None,
+            offline: None,
+            extensions: Arc::default(),
+        }
+    }
+}
+
 /// Handle for communicating with the IO task.
 pub(crate) struct IoHandle {
     pub tx: mpsc::Sender<Request>,
     pub cancel: CancellationToken,
+    pub transcript: Option<Transcript>,
+    pub stats: Arc<StatsCounters>,
+    pub metrics: Arc<Mutex<LinkMetrics>>,
+    pub query_timeout: Duration,
+    /// Longest a command can spend in write retries, added to the reply
+    /// timeouts so a caller never gives up on a write that may still land.
+    pub retry_budget: Duration,
+    pub policy: QueuePolicy,
+    /// Queued RX updates, oldest first, for [`QueuePolicy::DropOldest`].
+    pub updates: Mutex<VecDeque<Weak<AtomicBool>>>,
+    /// Sequence number of the newest RX update queued.
+    pub latest_update: Arc<AtomicU64>,
+    /// ID given to the last request submitted.
+    pub last_id: AtomicU64,
+    /// Submission time of each command not yet completed, by ID.
+    pub in_flight: Mutex<BTreeMap<u64, Instant>>,
     pub _task: JoinHandle<()>,
 }
 
 impl IoHandle {
     /// Send a write command and wait for acknowledgment.
     pub async fn command(&self, data: Vec<u8>) -> Result<()> {
+        self.send_command(data, false).await
+    }
+
+    /// Send an RX routing update, which a newer update may supersede while
+    /// it waits in the queue.
+    pub async fn update(&self, data: Vec<u8>) -> Result<()> {
+        self.send_command(data, true).await
+    }
+
+    async fn send_command(&self, data: Vec<u8>, update: bool) -> Result<()> {
+        let (_in_flight, span) = self.submit(&data);
+        async move {
+            let started = self.start_entry(&data);
+            let result = self.write(data, update).await;
+            self.finish_entry(started, &result, |_| None);
+            self.stats.command(false, &result);
+            trace!(ok = result.is_ok(), "completed");
+            result
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Send a command and read back a line response, terminator included.
+    pub async fn command_read(&self, data: Vec<u8>) -> Result<Bytes> {
+        let (_in_flight, span) = self.submit(&data);
+        async move {
+            let started = self.start_entry(&data);
+            let result = self.write_read(data).await;
+            self.finish_entry(started, &result, |line| Some(line.to_vec()));
+            self.stats.command(true, &result);
+            trace!(ok = result.is_ok(), "completed");
+            result
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Number a request and count it as pending until the returned guard
+    /// drops. Also opens the span it is traced in, from submission to
+    /// response.
+    ///
+    /// Requests are numbered so the device layer's and the IO task's log
+    /// lines for one command can be told apart from its neighbours'.
+    fn submit(&self, data: &[u8]) -> (InFlight<'_>, Span) {
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.in_flight.lock().unwrap().insert(id, Instant::now());
+        let span = debug_span!("request", id, command = %data.trim_ascii_end().escape_ascii());
+        (InFlight { handle: self, id }, span)
+    }
+
+    /// Commands submitted but not yet completed.
+    pub fn pending(&self) -> PendingCommands {
+        let in_flight = self.in_flight.lock().unwrap();
+        PendingCommands {
+            count: in_flight.len(),
+            // IDs grow with time, so the first entry is the oldest.
+            oldest: in_flight.values().next().map(Instant::elapsed),
+        }
+    }
+
+    /// Begin a transcript entry for `data`, if a transcript is kept.
+    fn start_entry(&self, data: &[u8]) -> Option<(TranscriptEntry, Instant)> {
+        self.transcript.as_ref()?;
+        let entry = TranscriptEntry {
+            ts: audit::now_ms(),
+            command: data.to_vec(),
+            response: None,
+            error: None,
+            elapsed: Duration::ZERO,
+        };
+        Some((entry, Instant::now()))
+    }
+
+    /// Complete and record an entry begun by [`start_entry()`](Self::start_entry).
+    fn finish_entry<T>(
+        &self,
+        started: Option<(TranscriptEntry, Instant)>,
+        result: &Result<T>,
+        response: impl FnOnce(&T) -> Option<Vec<u8>>,
+    ) {
+        let (Some(transcript), Some((mut entry, at))) = (&self.transcript, started) else {
+            return;
+        };
+        entry.elapsed = at.elapsed();
+        match result {
+            Ok(value) => entry.response = response(value),
+            Err(e) => entry.error = Some(e.to_string()),
+        }
+        transcript.record(entry);
+    }
+
+    async fn write(&self, data: Vec<u8>, update: bool) -> Result<()> {
         let (reply_tx, reply_rx) = oneshot::channel();
-        self.tx
-            .send(Request::Write {
+        let update = update.then(|| RoutingUpdate {
+            superseded: Arc::default(),
+            seq: self.latest_update.fetch_add(1, Ordering::Relaxed) + 1,
+            queued: Instant::now(),
+        });
+        let queued = update
+            .as_ref()
+            .filter(|_| self.policy == QueuePolicy::DropOldest)
+            .map(|u| Arc::downgrade(&u.superseded));
+        let is_update = update.is_some();
+        self.enqueue(
+            Request::Write {
                 data,
                 reply: reply_tx,
-            })
-            .await
-            .map_err(|_| Error::NotConnected)?;
+                update,
+                span: Span::current(),
+            },
+            is_update,
+        )
+        .await?;
+        trace!("submitted");
+        if let Some(queued) = queued {
+            let mut updates = self.updates.lock().unwrap();
+            updates.retain(|u| u.strong_count() > 0);
+            updates.push_back(queued);
+        }
 
-        match tokio::time::timeout(std::time::Duration::from_secs(5), reply_rx).await {
+        match tokio::time::timeout(Duration::from_secs(5) + self.retry_budget, reply_rx).await {
             Ok(Ok(result)) => result,
             Ok(Err(_)) => Err(Error::NotConnected),
             Err(_) => Err(Error::Timeout),
         }
     }
 
-    /// Send a command and read back a line response.
-    pub async fn command_read(&self, data: Vec<u8>) -> Result<String> {
+    async fn write_read(&self, data: Vec<u8>) -> Result<Bytes> {
         let (reply_tx, reply_rx) = oneshot::channel();
-        self.tx
-            .send(Request::WriteAndRead {
+        self.enqueue(
+            Request::WriteAndRead {
+                expect: protocol::answer_prefix(&data).map(<[u8]>::to_vec),
                 data,
                 reply: reply_tx,
-            })
-            .await
-            .map_err(|_| Error::NotConnected)?;
+                span: Span::current(),
+            },
+            false,
+        )
+        .await?;
+        trace!("submitted");
 
-        match tokio::time::timeout(std::time::Duration::from_secs(5), reply_rx).await {
+        let timeout = self.query_timeout + Duration::from_secs(4) + self.retry_budget;
+        match tokio::time::timeout(timeout, reply_rx).await {
             Ok(Ok(result)) => result,
             Ok(Err(_)) => Err(Error::NotConnected),
             Err(_) => Err(Error::Timeout),
         }
     }
 
+    /// Queue a command under the configured [`QueuePolicy`].
+    async fn enqueue(&self, req: Request, update: bool) -> Result<()> {
+        let req = match self.policy {
+            QueuePolicy::Block => req,
+            policy => match self.tx.try_send(req) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Closed(_)) => return Err(Error::NotConnected),
+                Err(TrySendError::Full(_)) if policy == QueuePolicy::FailFast => {
+                    debug!("request queue full, refusing command");
+                    return Err(Error::Busy);
+                }
+                Err(TrySendError::Full(req)) => {
+                    if update {
+                        self.supersede_oldest();
+                    }
+                    req
+                }
+            },
+        };
+        self.tx.send(req).await.map_err(|_| Error::NotConnected)
+    }
+
+    /// Mark the oldest queued RX update as superseded, so the IO task skips it.
+    fn supersede_oldest(&self) {
+        let mut updates = self.updates.lock().unwrap();
+        while let Some(update) = updates.pop_front() {
+            if let Some(superseded) = update.upgrade() {
+                debug!("request queue full, dropping oldest RX update");
+                superseded.store(true, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    /// Have the IO task send `command` at `at`; returns once it is queued.
+    pub async fn schedule(&self, command: Command, data: Vec<u8>, at: Instant) -> Result<()> {
+        let (_in_flight, span) = self.submit(&data);
+        span.in_scope(|| trace!(?at, "scheduled"));
+        let req = Request::Schedule {
+            at,
+            command,
+            data,
+            span,
+        };
+        self.enqueue(req, false).await
+    }
+
+    /// Have the IO task send `command` as soon as it can, without waiting
+    /// for room in the queue.
+    ///
+    /// Fails with [`Error::Busy`] if the queue is full.
+    pub fn schedule_now(&self, command: Command, data: Vec<u8>) -> Result<()> {
+        let (_in_flight, span) = self.submit(&data);
+        span.in_scope(|| trace!("scheduled now"));
+        let req = Request::Schedule {
+            at: Instant::now(),
+            command,
+            data,
+            span,
+        };
+        self.tx.try_send(req).map_err(|e| match e {
+            TrySendError::Full(_) => Error::Busy,
+            TrySendError::Closed(_) => Error::NotConnected,
+        })
+    }
+
+    /// Suspend the IO task and take over its port.
+    pub async fn lease(&self) -> Result<PortLease> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.enqueue(Request::Lease { reply: reply_tx }, false)
+            .await?;
+        reply_rx.await.map_err(|_| Error::NotConnected)
+    }
+
+    /// Start or stop reading unsolicited device notifications.
+    pub async fn set_listening(&self, enabled: bool) -> Result<()> {
+        self.tx
+            .send(Request::Listen { enabled })
+            .await
+            .map_err(|_| Error::NotConnected)
+    }
+
     /// Request graceful shutdown of the IO task.
     pub async fn shutdown(&self) -> Result<()> {
         let (reply_tx, reply_rx) = oneshot::channel();
@@ -100,37 +447,207 @@ impl IoHandle {
     }
 }
 
+/// Keeps a command counted as pending while it is alive.
+struct InFlight<'a> {
+    handle: &'a IoHandle,
+    id: u64,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.handle.in_flight.lock().unwrap().remove(&self.id);
+    }
+}
+
 /// Spawn the IO task that owns the serial port.
-pub(crate) fn spawn_io_task<P>(port: P, event_tx: broadcast::Sender<SwitchEvent>) -> IoHandle
+///
+/// `state` is the device's cached routing, updated in place when the device
+/// reports a change of its own; `last_unkey` records reported PTT releases.
+///
+/// The task is supervised: if it panics, [`SwitchEvent::IoTaskFailed`] is
+/// emitted and the task is restarted on a link from `restart`, if given.
+pub(crate) fn spawn_io_task<P>(
+    port: P,
+    event_tx: broadcast::Sender<SwitchEvent>,
+    state: Arc<Mutex<SwitchState>>,
+    last_unkey: Arc<Mutex<Option<Instant>>>,
+    config: IoConfig,
+    restart: Option<Restart>,
+) -> IoHandle
 where
     P: AsyncRead + AsyncWrite + Send + Unpin + 'static,
 {
-    let (tx, rx) = mpsc::channel::<Request>(32);
+    let (tx, rx) = mpsc::channel::<Request>(config.capacity.max(1));
     let cancel = CancellationToken::new();
+    let transcript = config.transcript.clone();
+    let stats = config.stats.clone();
+    let metrics = config.metrics.clone();
+    let query_timeout = config.query_timeout;
+    let retry_budget = config.write_retry_delay * config.write_attempts.saturating_sub(1);
+    let policy = config.policy;
+    let latest_update = config.latest_update.clone();
 
-    let task = tokio::spawn(io_loop(port, rx, cancel.clone(), event_tx));
+    let task = IoTask {
+        rx: Arc::new(tokio::sync::Mutex::new(rx)),
+        cancel: cancel.clone(),
+        event_tx,
+        state,
+        last_unkey,
+        config,
+    };
+    let task = tokio::spawn(supervise(Box::new(port), task, restart));
 
     IoHandle {
         tx,
         cancel,
+        transcript,
+        stats,
+        metrics,
+        query_timeout,
+        retry_budget,
+        policy,
+        updates: Mutex::default(),
+        latest_update,
+        last_id: AtomicU64::new(0),
+        in_flight: Mutex::default(),
         _task: task,
     }
 }
 
-/// The main IO loop.
-async fn io_loop<P>(
-    mut port: P,
-    mut rx: mpsc::Receiver<Request>,
+/// Opens a fresh link for a restarted IO task, with the new port's
+/// [`ResetHandle`] if it has one.
+pub(crate) type Restart = Arc<
+    dyn Fn() -> Pin<Box<dyn Future<Output = Result<(BoxedTransport, Option<ResetHandle>)>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// Everything an IO loop runs with besides its port.
+#[derive(Clone)]
+struct IoTask {
+    /// Shared so a restarted loop picks up the requests still queued.
+    rx: Arc<tokio::sync::Mutex<mpsc::Receiver<Request>>>,
     cancel: CancellationToken,
     event_tx: broadcast::Sender<SwitchEvent>,
-) where
-    P: AsyncRead + AsyncWrite + Send + Unpin + 'static,
-{
+    state: Arc<Mutex<SwitchState>>,
+    last_unkey: Arc<Mutex<Option<Instant>>>,
+    config: IoConfig,
+}
+
+/// Run the IO loop, reporting a panic and restarting the loop if possible.
+///
+/// Requests in flight when the loop panicked fail as not connected.
+async fn supervise(mut port: BoxedTransport, mut task: IoTask, restart: Option<Restart>) {
+    loop {
+        let panic = match tokio::spawn(io_loop(port, task.clone())).await {
+            Err(e) if e.is_panic() => e.into_panic(),
+            _ => return,
+        };
+        let reason = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        error!("IO task panicked: {reason}");
+        let _ = task.event_tx.send(SwitchEvent::IoTaskFailed);
+
+        let Some(restart) = restart.as_ref().filter(|_| !task.cancel.is_cancelled()) else {
+            let _ = task.event_tx.send(SwitchEvent::Disconnected);
+            return;
+        };
+        match restart().await {
+            Ok((link, link_reset)) => {
+                info!("IO task restarted on a new link");
+                port = link;
+                task.config.link_reset = link_reset;
+                let _ = task.event_tx.send(SwitchEvent::Reconnected);
+            }
+            Err(e) => {
+                error!("could not restart IO task: {e}");
+                let _ = task.event_tx.send(SwitchEvent::Disconnected);
+                return;
+            }
+        }
+    }
+}
+
+/// Time allowed for sending the writes queued behind a shutdown.
+const SHUTDOWN_FLUSH: Duration = Duration::from_millis(500);
+
+/// Most queued writes sent together in one write.
+const MAX_BATCH: usize = 16;
+
+/// Lines kept for the next query when none is waiting.
+const MAX_HELD: usize = 32;
+
+/// The main IO loop.
+///
+/// The port is split so the read half is polled on every pass, whether or
+/// not a query is waiting. At most one query is outstanding; further
+/// requests wait in the channel until it is answered or times out.
+async fn io_loop(port: BoxedTransport, task: IoTask) {
     debug!("IO task started");
-    let mut disconnected_sent = false;
-    let mut needs_drain = false;
+    let IoTask {
+        rx,
+        cancel,
+        event_tx,
+        state,
+        last_unkey,
+        config,
+    } = task;
+    let mut rx = rx.lock().await;
+    let (mut reader, mut writer) = tokio::io::split(port);
+    let mut session = Session {
+        listener: Listener {
+            enabled: false,
+            pending: BytesMut::new(),
+            parse_mode: config.parse_mode,
+            skip: LineSkip {
+                max_lines: config.skip_lines,
+                budget: config.skip_budget,
+            },
+            state,
+            last_unkey,
+        },
+        echoes: EchoFilter {
+            enabled: config.echo,
+            sent: VecDeque::new(),
+        },
+        event_tx,
+        disconnected_sent: false,
+        query: None,
+        held: VecDeque::new(),
+        late: 0,
+        reading: true,
+        query_timeout: config.query_timeout,
+        write_attempts: config.write_attempts.max(1),
+        write_retry_delay: config.write_retry_delay,
+        keepalive: config.keepalive,
+        last_activity: Instant::now(),
+        link_reset: config.link_reset,
+        metrics: config.metrics,
+        routing_deadline: config.routing_deadline,
+        latest_update: config.latest_update,
+        transmit_latch: config.transmit_latch,
+        footswitch_latch: config.footswitch_latch,
+        rx_follow: config.rx_follow,
+        offline: config.offline,
+        extensions: config.extensions,
+        scheduled: BTreeMap::new(),
+        scheduled_count: 0,
+        stats: config.stats,
+    };
+    let mut chunk = [0u8; 64];
+    let mut lease = None;
+    let mut link_events = session.event_tx.subscribe();
+    // A restarted task starts on a fresh link.
+    session.replay_offline();
 
     loop {
+        let deadline = session
+            .query
+            .as_ref()
+            .map(|q| q.started + session.query_timeout);
         tokio::select! {
             biased;
 
@@ -139,130 +656,939 @@ async fn io_loop<P>(
                 break;
             }
 
-            req = rx.recv() => {
+            _ = tokio::time::sleep_until(session.next_scheduled()), if !session.scheduled.is_empty() && session.query.is_none() => {
+                session.send_scheduled(&mut writer).await;
+            }
+
+            req = rx.recv(), if session.query.is_none() => {
+                let Some(mut req) = req else {
+                    debug!("channel closed");
+                    break;
+                };
+                if let Request::Write { data, reply, update, span } = req {
+                    let first = QueuedWrite { data, reply, update, span };
+                    let (batch, next) = gather_writes(first, &mut rx);
+                    session.write_batch(batch, &mut writer).await;
+                    let Some(next) = next else {
+                        continue;
+                    };
+                    req = next;
+                }
                 match req {
-                    Some(Request::Shutdown { reply }) => {
+                    Request::Shutdown { reply } => {
                         debug!("IO task shutdown requested");
+                        session.flush_queue(&mut rx, &mut writer).await;
                         let _ = reply.send(Ok(()));
                         break;
                     }
-                    Some(req) => {
-                        handle_request(req, &mut port, &event_tx, &mut disconnected_sent, &mut needs_drain).await;
-                    }
-                    None => {
-                        debug!("channel closed");
-                        break;
-                    }
+                    // Lent out below, once the select no longer borrows the port.
+                    Request::Lease { reply } => lease = Some(reply),
+                    req => session.handle_request(req, &mut writer).await,
+                }
+            }
+
+            read = reader.read(&mut chunk), if session.reading => {
+                match read {
+                    Ok(n) if n > 0 => session.received(&chunk[..n]),
+                    Ok(_) => session.read_failed(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "port closed during read",
+                    )),
+                    Err(e) => session.read_failed(e),
+                }
+            }
+
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                session.timed_out();
+            }
+
+            _ = tokio::time::sleep_until(session.next_keepalive()), if session.keepalive.is_some() && session.query.is_none() => {
+                session.send_keepalive(&mut writer).await;
+            }
+
+            event = link_events.recv(), if session.offline.is_some() => {
+                if let Ok(SwitchEvent::Reconnected) = event {
+                    session.replay_offline();
                 }
             }
         }
+
+        if let Some(reply) = lease.take() {
+            let Some(port) = session.lend(reader.unsplit(writer), reply, &cancel).await else {
+                break;
+            };
+            (reader, writer) = tokio::io::split(port);
+        }
     }
 
-    if !disconnected_sent {
-        let _ = event_tx.send(SwitchEvent::Disconnected);
+    if !session.scheduled.is_empty() {
+        debug!(
+            count = session.scheduled.len(),
+            "dropping commands still scheduled"
+        );
+    }
+    if !session.disconnected_sent {
+        let _ = session.event_tx.send(SwitchEvent::Disconnected);
     }
     debug!("IO task exiting");
 }
 
-/// Handle a single request.
-async fn handle_request<P>(
-    req: Request,
-    port: &mut P,
-    event_tx: &broadcast::Sender<SwitchEvent>,
-    disconnected_sent: &mut bool,
-    needs_drain: &mut bool,
-) where
-    P: AsyncRead + AsyncWrite + Send + Unpin,
-{
-    match req {
-        Request::Write { data, reply } => {
-            trace!("writing {} bytes: {:02X?}", data.len(), data);
-            let result = port.write_all(&data).await.map_err(|e| {
-                error!("write error: {e}");
-                if !*disconnected_sent {
-                    let _ = event_tx.send(SwitchEvent::Disconnected);
-                    *disconnected_sent = true;
+/// A plain write taken off the request queue.
+struct QueuedWrite {
+    data: Vec<u8>,
+    reply: oneshot::Sender<Result<()>>,
+    update: Option<RoutingUpdate>,
+    span: Span,
+}
+
+/// Take the writes queued right behind `first`, up to [`MAX_BATCH`].
+///
+/// Also returns the request that ended the run, if one did.
+fn gather_writes(
+    first: QueuedWrite,
+    rx: &mut mpsc::Receiver<Request>,
+) -> (Vec<QueuedWrite>, Option<Request>) {
+    let mut batch = vec![first];
+    while batch.len() < MAX_BATCH {
+        match rx.try_recv() {
+            Ok(Request::Write {
+                data,
+                reply,
+                update,
+                span,
+            }) => batch.push(QueuedWrite {
+                data,
+                reply,
+                update,
+                span,
+            }),
+            Ok(other) => return (batch, Some(other)),
+            Err(_) => break,
+        }
+    }
+    (batch, None)
+}
+
+/// A command held until its scheduled time.
+struct ScheduledWrite {
+    command: Command,
+    data: Vec<u8>,
+    span: Span,
+    source: WriteSource,
+}
+
+/// Who queued a [`ScheduledWrite`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WriteSource {
+    /// The application, through [`Request::Schedule`].
+    Caller,
+    /// A latch itself, so sending it keeps the latches.
+    Latch,
+    /// RX following TX focus moved by PTT, which overrides the latches
+    /// like an explicit RX change.
+    Follow,
+    /// The offline backlog, so a failed write goes back there.
+    Offline,
+}
+
+/// A query waiting for its answer.
+struct PendingQuery {
+    /// Prefix the answer must start with, for standard queries.
+    expect: Option<Vec<u8>>,
+    /// Where the answer goes; `None` for a keepalive.
+    reply: Option<oneshot::Sender<Result<Bytes>>>,
+    started: Instant,
+    skipped: usize,
+    span: Span,
+}
+
+/// A line that arrived while no query was waiting.
+struct HeldLine {
+    line: Bytes,
+    /// Arrived after a query timed out, so it is likely that query's answer.
+    late: bool,
+}
+
+/// IO loop state: line handling, the outstanding query, and lines kept for
+/// the next one.
+struct Session {
+    listener: Listener,
+    echoes: EchoFilter,
+    event_tx: broadcast::Sender<SwitchEvent>,
+    disconnected_sent: bool,
+    query: Option<PendingQuery>,
+    held: VecDeque<HeldLine>,
+    /// Timed-out queries whose answers have not arrived yet.
+    late: usize,
+    /// Whether the read half is polled. Cleared by a read error and set
+    /// again by the next query, so a dead port is not polled in a loop.
+    reading: bool,
+    query_timeout: Duration,
+    /// Tries per write when it fails with a transient error.
+    write_attempts: u32,
+    write_retry_delay: Duration,
+    keepalive: Option<Duration>,
+    /// When a line last crossed the link in either direction.
+    last_activity: Instant,
+    link_reset: Option<ResetHandle>,
+    metrics: Arc<Mutex<LinkMetrics>>,
+    routing_deadline: Option<Duration>,
+    latest_update: Arc<AtomicU64>,
+    transmit_latch: Option<Arc<Mutex<TransmitLatch>>>,
+    footswitch_latch: Option<Arc<Mutex<FootswitchLatch>>>,
+    rx_follow: Option<RxFollow>,
+    offline: Option<Arc<Mutex<SwitchState>>>,
+    extensions: Arc<RwLock<Extensions>>,
+    /// Commands waiting for their time, keyed by when and arrival order.
+    scheduled: BTreeMap<(Instant, u64), ScheduledWrite>,
+    scheduled_count: u64,
+    stats: Arc<StatsCounters>,
+}
+
+impl Session {
+    /// Handle a request other than shutdown.
+    async fn handle_request<W>(&mut self, req: Request, writer: &mut W)
+    where
+        W: AsyncWrite + Unpin,
+    {
+        match req {
+            Request::Write {
+                data,
+                reply,
+                update,
+                span,
+            } => {
+                let write = QueuedWrite {
+                    data,
+                    reply,
+                    update,
+                    span,
+                };
+                self.write_batch(vec![write], writer).await;
+            }
+            Request::WriteAndRead {
+                data,
+                expect,
+                reply,
+                span,
+            } => {
+                span.in_scope(|| trace!("write+read {} bytes", data.len()));
+                self.echoes.record(&data);
+                let started = Instant::now();
+                let written = self.write(writer, &data).instrument(span.clone()).await;
+                if let Err(e) = written {
+                    span.in_scope(|| error!("write error: {e}"));
+                    self.metrics.lock().unwrap().failed();
+                    self.disconnected();
+                    let _ = reply.send(Err(Error::Io(e)));
+                    return;
+                }
+                span.in_scope(|| trace!("written, awaiting answer"));
+                self.query = Some(PendingQuery {
+                    expect,
+                    reply: Some(reply),
+                    started,
+                    skipped: 0,
+                    span,
+                });
+                self.reading = true;
+                // Lines that arrived while idle may hold the answer.
+                let held = std::mem::take(&mut self.held);
+                for HeldLine { line, late } in held {
+                    self.handle_line(line, late);
+                }
+            }
+            Request::Schedule {
+                at,
+                command,
+                data,
+                span,
+            } => {
+                self.scheduled_count += 1;
+                let write = ScheduledWrite {
+                    command,
+                    data,
+                    span,
+                    source: WriteSource::Caller,
+                };
+                self.scheduled.insert((at, self.scheduled_count), write);
+            }
+            Request::Listen { enabled } => {
+                debug!(enabled, "unsolicited notifications");
+                self.listener.enabled = enabled;
+                if enabled {
+                    self.reading = true;
                 }
-                Error::Io(e)
-            });
+            }
+            Request::Shutdown { reply } => {
+                let _ = reply.send(Ok(()));
+            }
+            // The loop lends the port itself; this arm only drops the request.
+            Request::Lease { .. } => {}
+        }
+    }
+
+    /// Send queued writes as a single write, in order, sparing a syscall and
+    /// a USB frame per command.
+    async fn write_batch<W>(&mut self, batch: Vec<QueuedWrite>, writer: &mut W)
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut data = Vec::new();
+        let mut replies = Vec::with_capacity(batch.len());
+        for write in batch {
+            let redundant = match &write.update {
+                Some(update) => write.span.in_scope(|| self.is_redundant(update)),
+                None => false,
+            };
+            if redundant {
+                let _ = write.reply.send(Ok(()));
+                continue;
+            }
+            self.echoes.record(&write.data);
+            data.extend_from_slice(&write.data);
+            replies.push((write.reply, write.span));
+        }
+        if replies.is_empty() {
+            return;
+        }
+        trace!(
+            commands = replies.len(),
+            "writing {} bytes: {:02X?}",
+            data.len(),
+            data
+        );
+        let started = Instant::now();
+        let result = self.write(writer, &data).await;
+        let elapsed = started.elapsed();
+        if let Err(e) = &result {
+            error!("write error: {e}");
+            self.disconnected();
+        }
+        let mut metrics = self.metrics.lock().unwrap();
+        for (reply, span) in replies {
+            let result = match &result {
+                Ok(()) => {
+                    span.in_scope(|| trace!(?elapsed, "written"));
+                    metrics.write_done(elapsed);
+                    Ok(())
+                }
+                Err(e) => {
+                    metrics.failed();
+                    Err(Error::Io(std::io::Error::new(e.kind(), e.to_string())))
+                }
+            };
             let _ = reply.send(result);
         }
-        Request::WriteAndRead { data, reply } => {
-            trace!("write+read {} bytes", data.len());
-            // Drain stale bytes from a previous timed-out read before sending
-            // a new command. Anything in the buffer now is from a prior response.
-            if *needs_drain {
-                drain_stale(port).await;
-                *needs_drain = false;
-            }
-            if let Err(e) = port.write_all(&data).await {
-                error!("write error: {e}");
-                if !*disconnected_sent {
-                    let _ = event_tx.send(SwitchEvent::Disconnected);
-                    *disconnected_sent = true;
+    }
+
+    /// Whether a queued RX update can be skipped because a newer one
+    /// replaces it.
+    fn is_redundant(&self, update: &RoutingUpdate) -> bool {
+        if update.superseded.load(Ordering::Relaxed) {
+            debug!("skipping RX update superseded by a newer one");
+            return true;
+        }
+        let newer_queued = update.seq < self.latest_update.load(Ordering::Relaxed);
+        let age = update.queued.elapsed();
+        if newer_queued
+            && self
+                .routing_deadline
+                .is_some_and(|deadline| age >= deadline)
+        {
+            debug!(?age, "dropping stale RX update, a newer one is queued");
+            return true;
+        }
+        false
+    }
+
+    /// Send the writes that were queued behind a shutdown, so commands
+    /// issued just before `close()` still reach the device. Queries and
+    /// writes left once [`SHUTDOWN_FLUSH`] has passed fail as not connected.
+    async fn flush_queue<W>(&mut self, rx: &mut mpsc::Receiver<Request>, writer: &mut W)
+    where
+        W: AsyncWrite + Unpin,
+    {
+        rx.close();
+        let deadline = Instant::now() + SHUTDOWN_FLUSH;
+        while let Ok(req) = rx.try_recv() {
+            match req {
+                req @ Request::Write { .. } if Instant::now() < deadline => {
+                    let write = self.handle_request(req, writer);
+                    if tokio::time::timeout_at(deadline, write).await.is_err() {
+                        warn!("shutdown flush ran out of time");
+                    }
                 }
-                let _ = reply.send(Err(Error::Io(e)));
-                return;
+                Request::Write { reply, .. } => {
+                    let _ = reply.send(Err(Error::NotConnected));
+                }
+                Request::WriteAndRead { reply, .. } => {
+                    let _ = reply.send(Err(Error::NotConnected));
+                }
+                Request::Shutdown { reply } => {
+                    let _ = reply.send(Ok(()));
+                }
+                Request::Schedule { .. } | Request::Lease { .. } | Request::Listen { .. } => {}
+            }
+        }
+    }
+
+    /// Write a command, retrying transient failures.
+    ///
+    /// A retry resumes after the bytes already written, so a batch cut off
+    /// part way is not sent twice.
+    async fn write<W>(&mut self, writer: &mut W, data: &[u8]) -> std::io::Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut attempt = 1;
+        let mut written = 0;
+        loop {
+            match write_flush(writer, data, &mut written).await {
+                Err(e) if attempt < self.write_attempts && is_transient(&e) => {
+                    debug!(attempt, "transient write error, retrying: {e}");
+                    attempt += 1;
+                    tokio::time::sleep(self.write_retry_delay).await;
+                }
+                result => {
+                    self.last_activity = Instant::now();
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Hand the port out through `reply` and wait for the lease to drop.
+    ///
+    /// Returns the port, or `None` if the task was cancelled or the lease
+    /// never gave it back. Anything read before the lease is discarded.
+    async fn lend(
+        &mut self,
+        port: BoxedTransport,
+        reply: oneshot::Sender<PortLease>,
+        cancel: &CancellationToken,
+    ) -> Option<BoxedTransport> {
+        let (back_tx, back_rx) = oneshot::channel();
+        info!("port leased, IO task suspended");
+        // If the caller gave up, dropping the refused lease returns the port.
+        let _ = reply.send(PortLease::new(port, back_tx));
+        let port = tokio::select! {
+            _ = cancel.cancelled() => return None,
+            port = back_rx => port.ok()?,
+        };
+        info!("port returned, IO task resumed");
+        self.listener.pending.clear();
+        self.held.clear();
+        self.echoes.sent.clear();
+        self.late = 0;
+        self.reading = true;
+        self.last_activity = Instant::now();
+        Some(port)
+    }
+
+    /// When the next scheduled command is due.
+    fn next_scheduled(&self) -> Instant {
+        match self.scheduled.first_key_value() {
+            Some(((at, _), _)) => *at,
+            None => Instant::now(),
+        }
+    }
+
+    /// Send the scheduled commands that are due, in order, and record each
+    /// one in the state cache.
+    async fn send_scheduled<W>(&mut self, writer: &mut W)
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let now = Instant::now();
+        while let Some(entry) = self.scheduled.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            let ScheduledWrite {
+                command,
+                data,
+                span,
+                source,
+            } = entry.remove();
+            self.echoes.record(&data);
+            let started = Instant::now();
+            let result = self.write(writer, &data).instrument(span.clone()).await;
+            let elapsed = started.elapsed();
+            let result = result.map_err(Error::Io);
+            self.stats.command(false, &result);
+            let _enter = span.enter();
+            if let Err(e) = result {
+                error!("scheduled write error: {e}");
+                self.metrics.lock().unwrap().failed();
+                if source == WriteSource::Offline
+                    && let Some(offline) = &self.offline
+                {
+                    offline.lock().unwrap().restore(&command);
+                }
+                self.disconnected();
+                continue;
             }
+            trace!(?elapsed, "scheduled command written");
+            self.metrics.lock().unwrap().write_done(elapsed);
+            // An explicit RX change overrides the routing saved by the latches.
+            if let Command::Rx(..) = command
+                && source != WriteSource::Latch
+            {
+                if let Some(latch) = &self.transmit_latch {
+                    latch.lock().unwrap().release();
+                }
+                if let Some(latch) = &self.footswitch_latch {
+                    latch.lock().unwrap().release();
+                }
+            }
+            let event = self.listener.state.lock().unwrap().apply(&command);
+            if let Some(event) = event {
+                let _ = self.event_tx.send(event);
+            }
+        }
+    }
 
-            match tokio::time::timeout(std::time::Duration::from_secs(1), read_line(port)).await {
-                Ok(Ok(line)) => {
-                    let _ = reply.send(Ok(line));
+    /// Move RX when the device reports PTT: for the transmit latch if
+    /// enabled, otherwise to follow TX focus if it `moved` and RX follows TX.
+    ///
+    /// The routing goes out as a scheduled command due now, so it waits
+    /// for any outstanding query and updates the state cache when sent.
+    fn ptt_changed(&mut self, ptt: Option<Radio>, moved: bool) {
+        let current = self.listener.state.lock().unwrap().rx;
+        let Some(latch) = &self.transmit_latch else {
+            if let (Some(radio), true, Some(follow)) = (ptt, moved, self.rx_follow) {
+                let (radio, mode) = follow.routing(radio, current);
+                if current != Some((radio, mode)) {
+                    trace!(?radio, ?mode, "RX following PTT");
+                    let span = debug_span!("rx follows tx");
+                    self.schedule_now(Command::Rx(radio, mode), span, WriteSource::Follow);
                 }
-                Ok(Err(e)) => {
-                    error!("read error: {e}");
-                    if !*disconnected_sent {
-                        let _ = event_tx.send(SwitchEvent::Disconnected);
-                        *disconnected_sent = true;
+            }
+            return;
+        };
+        let target = match ptt {
+            Some(radio) => latch.lock().unwrap().key(radio, current),
+            None => latch.lock().unwrap().unkey(current),
+        };
+        let Some((radio, mode)) = target else {
+            return;
+        };
+        trace!(?radio, ?mode, "transmit latch moving RX");
+        let span = debug_span!("transmit latch");
+        self.schedule_now(Command::Rx(radio, mode), span, WriteSource::Latch);
+    }
+
+    /// Toggle the footswitch latch when the device reports a press.
+    fn footswitch_pressed(&mut self) {
+        let Some(latch) = &self.footswitch_latch else {
+            return;
+        };
+        let Some(current) = self.listener.state.lock().unwrap().rx else {
+            return;
+        };
+        let (radio, mode) = latch.lock().unwrap().press(current);
+        trace!(?radio, ?mode, "footswitch latch moving RX");
+        let span = debug_span!("footswitch latch");
+        self.schedule_now(Command::Rx(radio, mode), span, WriteSource::Latch);
+    }
+
+    /// Send the state commands accepted while the link was down, as a diff
+    /// against the current state.
+    fn replay_offline(&mut self) {
+        let Some(offline) = &self.offline else {
+            return;
+        };
+        let backlog = std::mem::take(&mut *offline.lock().unwrap());
+        let commands = SwitchState::diff(&self.listener.state.lock().unwrap(), &backlog);
+        if commands.is_empty() {
+            return;
+        }
+        info!(
+            count = commands.len(),
+            "link back, replaying commands queued while offline"
+        );
+        for command in commands {
+            self.schedule_now(command, debug_span!("offline replay"), WriteSource::Offline);
+        }
+    }
+
+    /// Queue a state command due now, behind any outstanding query.
+    fn schedule_now(&mut self, command: Command, span: Span, source: WriteSource) {
+        let data = match self.extensions.read().unwrap().encode_command(&command) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("dropping {command}: {e}");
+                return;
+            }
+        };
+        self.scheduled_count += 1;
+        let write = ScheduledWrite {
+            command,
+            data,
+            span,
+            source,
+        };
+        self.scheduled
+            .insert((Instant::now(), self.scheduled_count), write);
+    }
+
+    /// When the idle link is due for a keepalive.
+    fn next_keepalive(&self) -> Instant {
+        self.last_activity + self.keepalive.unwrap_or_default()
+    }
+
+    /// Probe the idle link with `?NAME`; the answer is read like any other.
+    async fn send_keepalive<W>(&mut self, writer: &mut W)
+    where
+        W: AsyncWrite + Unpin,
+    {
+        trace!("link idle, sending keepalive");
+        let data = protocol::encode_query_name();
+        self.echoes.record(&data);
+        let started = Instant::now();
+        if let Err(e) = self.write(writer, &data).await {
+            error!("keepalive write error: {e}");
+            self.metrics.lock().unwrap().failed();
+            self.disconnected();
+            return;
+        }
+        self.query = Some(PendingQuery {
+            expect: protocol::answer_prefix(&data).map(<[u8]>::to_vec),
+            reply: None,
+            started,
+            skipped: 0,
+            span: debug_span!("keepalive"),
+        });
+        self.reading = true;
+    }
+
+    /// A keepalive went unanswered: reconnect if the port can, otherwise
+    /// report the link down.
+    fn link_dead(&mut self) {
+        // Wait a full interval before probing again.
+        self.last_activity = Instant::now();
+        match &self.link_reset {
+            Some(reset) => {
+                warn!("no answer to keepalive, reconnecting");
+                reset.reset();
+            }
+            None => {
+                warn!("no answer to keepalive, link is dead");
+                self.disconnected();
+            }
+        }
+    }
+
+    /// Split freshly read bytes into lines and handle each one.
+    fn received(&mut self, bytes: &[u8]) {
+        self.last_activity = Instant::now();
+        self.listener.pending.extend_from_slice(bytes);
+        while let Some(line) = self.listener.take_line() {
+            self.handle_line(line, false);
+        }
+    }
+
+    /// Route one line: notifications become events, an answer completes
+    /// the waiting query, and anything else is held for the next query.
+    fn handle_line(&mut self, line: Bytes, late: bool) {
+        if let Ok(Response::Notification(n)) = protocol::parse_response(&line) {
+            if self.listener.enabled {
+                let tx = self.listener.state.lock().unwrap().tx;
+                self.listener.dispatch(n, &self.event_tx);
+                match n {
+                    Notification::Ptt(radio) => {
+                        self.ptt_changed(radio, radio.is_some() && radio != tx);
                     }
-                    let _ = reply.send(Err(Error::Io(e)));
+                    Notification::Footswitch(true) => self.footswitch_pressed(),
+                    _ => {}
+                }
+            } else {
+                trace!("discarding notification: \"{}\"", line.escape_ascii());
+            }
+            return;
+        }
+        if self.echoes.take(&line) {
+            trace!("skipping echoed command: \"{}\"", line.escape_ascii());
+            return;
+        }
+        let Some(query) = &mut self.query else {
+            if self.held.len() == MAX_HELD {
+                self.held.pop_front();
+            }
+            trace!("holding unsolicited line: \"{}\"", line.escape_ascii());
+            let late = self.late > 0;
+            self.late = self.late.saturating_sub(1);
+            self.held.push_back(HeldLine { line, late });
+            return;
+        };
+        let span = query.span.clone();
+        let _enter = span.enter();
+        match &query.expect {
+            Some(prefix) if !line.trim_ascii_start().starts_with(prefix) => {
+                // A late answer to an earlier query, e.g. NAME while waiting for AUX.
+                if is_query_answer(&line) {
+                    debug!("discarding stale response: \"{}\"", line.escape_ascii());
+                    return;
                 }
-                Err(_) => {
-                    warn!("read timeout waiting for response");
-                    *needs_drain = true;
-                    let _ = reply.send(Err(Error::Timeout));
+                // Chatty devices may print banners or debug output ahead of the answer.
+                if query.skipped < self.listener.skip.max_lines
+                    && query.started.elapsed() < self.listener.skip.budget
+                {
+                    query.skipped += 1;
+                    debug!("skipping unexpected line: \"{}\"", line.escape_ascii());
+                    return;
                 }
             }
+            // A raw query cannot tell answers apart, so it relies on arrival order.
+            None if late => {
+                debug!("discarding late response: \"{}\"", line.escape_ascii());
+                return;
+            }
+            _ => {}
         }
-        Request::Shutdown { reply } => {
-            let _ = reply.send(Ok(()));
+        let Some(query) = self.query.take() else {
+            return;
+        };
+        self.late = 0;
+        self.echoes.sent.clear();
+        let latency = query.started.elapsed();
+        trace!(?latency, "answered");
+        self.metrics.lock().unwrap().query_done(latency);
+        let Some(reply) = query.reply else {
+            trace!("keepalive answered");
+            return;
+        };
+        let result = match self.listener.parse_mode {
+            ParseMode::Strict => protocol::validate_response(&line).map(|()| line),
+            _ => Ok(line),
+        };
+        let _ = reply.send(result);
+    }
+
+    /// Fail the waiting query once its answer is overdue.
+    fn timed_out(&mut self) {
+        let Some(query) = self.query.take() else {
+            return;
+        };
+        let _enter = query.span.enter();
+        self.late += 1;
+        self.metrics.lock().unwrap().failed();
+        match query.reply {
+            Some(reply) => {
+                warn!("read timeout waiting for response");
+                let _ = reply.send(Err(Error::Timeout));
+            }
+            None => self.link_dead(),
+        }
+    }
+
+    /// Report a failed read and stop polling until the next query.
+    fn read_failed(&mut self, e: std::io::Error) {
+        error!("read error: {e}");
+        self.disconnected();
+        self.reading = false;
+        if self.query.is_some() {
+            self.metrics.lock().unwrap().failed();
+        }
+        match self.query.take() {
+            Some(PendingQuery {
+                reply: Some(reply),
+                span,
+                ..
+            }) => {
+                span.in_scope(|| debug!("read failed while awaiting answer"));
+                let _ = reply.send(Err(Error::Io(e)));
+            }
+            Some(_) => {}
+            None => self.listener.enabled = false,
+        }
+    }
+
+    /// Emit [`SwitchEvent::Disconnected`], once.
+    fn disconnected(&mut self) {
+        if !self.disconnected_sent {
+            let _ = self.event_tx.send(SwitchEvent::Disconnected);
+            self.disconnected_sent = true;
         }
     }
 }
 
-/// Drain any stale bytes from the port buffer.
+/// Whether a write error may go away if the write is tried again.
+fn is_transient(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::WouldBlock
+            | std::io::ErrorKind::Interrupted
+            | std::io::ErrorKind::TimedOut
+    )
+}
+
+/// Whether `line` is a well-formed answer to one of the standard queries.
+fn is_query_answer(line: &[u8]) -> bool {
+    matches!(
+        protocol::parse_response(line),
+        Ok(Response::Name(_)
+            | Response::Version(_)
+            | Response::Aux { .. }
+            | Response::Tx(_)
+            | Response::Rx(..)
+            | Response::Keying(_))
+    )
+}
+
+/// How many non-matching lines a standard query may skip, and for how long.
+#[derive(Debug, Clone, Copy)]
+struct LineSkip {
+    max_lines: usize,
+    budget: Duration,
+}
+
+/// Commands sent to an echoing device whose echo has not been read yet.
 ///
-/// Called before `WriteAndRead` to clear bytes left over from a previous
-/// timed-out read. Uses a bounded total window (200ms) with a per-read
-/// idle cutoff (20ms) so that late-arriving serial bytes are reliably
-/// consumed before the next command is sent.
-async fn drain_stale<P>(port: &mut P)
-where
-    P: AsyncRead + Unpin,
-{
-    let mut buf = [0u8; 64];
-    let deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(200);
-    let idle_cutoff = std::time::Duration::from_millis(20);
+/// Echoes of plain writes stay in the buffer until the next query reads
+/// them, so every command is remembered until an answer arrives.
+struct EchoFilter {
+    enabled: bool,
+    sent: VecDeque<Vec<u8>>,
+}
 
-    loop {
-        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
-        if remaining.is_zero() {
-            debug!("drain: total window expired");
-            break;
+impl EchoFilter {
+    /// Remember a command that is about to be sent.
+    fn record(&mut self, data: &[u8]) {
+        if !self.enabled {
+            return;
         }
-        let timeout = remaining.min(idle_cutoff);
-        match tokio::time::timeout(timeout, port.read(&mut buf)).await {
-            Ok(Ok(n)) if n > 0 => {
-                debug!("drained {n} stale bytes");
-                continue;
+        if self.sent.len() == 32 {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(trim_line(data).to_vec());
+    }
+
+    /// Whether `line` echoes a pending command; consumes it and any older ones.
+    fn take(&mut self, line: &[u8]) -> bool {
+        let line = trim_line(line);
+        match self.sent.iter().position(|sent| sent == line) {
+            Some(i) => {
+                self.sent.drain(..=i);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Strip CR/LF terminators from a line.
+fn trim_line(mut line: &[u8]) -> &[u8] {
+    while let [rest @ .., b'\r' | b'\n'] = line {
+        line = rest;
+    }
+    line
+}
+
+/// Line reader state: whether to dispatch notifications, and bytes read past the
+/// last complete line.
+struct Listener {
+    enabled: bool,
+    pending: BytesMut,
+    parse_mode: ParseMode,
+    skip: LineSkip,
+    state: Arc<Mutex<SwitchState>>,
+    last_unkey: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Listener {
+    /// Split the first non-empty line off the buffer, normalized for the
+    /// configured parse mode.
+    fn take_line(&mut self) -> Option<Bytes> {
+        loop {
+            let mut line = codec::split_line(&mut self.pending)?;
+            // Keep a CRLF pair together so strict mode can reject it.
+            if self.parse_mode == ParseMode::Strict
+                && line.ends_with(b"\r")
+                && self.pending.starts_with(b"\n")
+            {
+                line.unsplit(self.pending.split_to(1));
             }
-            _ => break,
+            if !trim_line(&line).is_empty() {
+                return Some(match self.parse_mode {
+                    ParseMode::Permissive => {
+                        protocol::normalize_response(&line, self.parse_mode).into()
+                    }
+                    _ => line.freeze(),
+                });
+            }
+        }
+    }
+
+    /// Apply a notification to the cached state and emit the resulting events.
+    fn dispatch(&self, notification: Notification, event_tx: &broadcast::Sender<SwitchEvent>) {
+        trace!(?notification, "device notification");
+        let origin = Origin::Device;
+        let mut state = self.state.lock().unwrap();
+        let mut input = None;
+        let change = match notification {
+            Notification::Tx(radio) => (state.tx.replace(radio) != Some(radio))
+                .then_some(SwitchEvent::TxChanged { radio, origin }),
+            Notification::Rx(radio, mode) => (state.rx.replace((radio, mode))
+                != Some((radio, mode)))
+            .then_some(SwitchEvent::RxChanged {
+                radio,
+                mode,
+                origin,
+            }),
+            Notification::Aux { port, value } => (state.aux.insert(port, value) != Some(value))
+                .then_some(SwitchEvent::AuxChanged {
+                    port,
+                    value,
+                    origin,
+                }),
+            Notification::Keying(radio) => (state.keying.replace(radio) != Some(radio))
+                .then_some(SwitchEvent::KeyingChanged { radio, origin }),
+            Notification::Footswitch(pressed) => {
+                input = Some(SwitchEvent::FootswitchChanged { pressed });
+                None
+            }
+            Notification::Ptt(radio) => {
+                input = Some(SwitchEvent::PttChanged { radio });
+                match radio {
+                    // Keying a radio means the device routed TX to it.
+                    Some(radio) => (state.tx.replace(radio) != Some(radio))
+                        .then_some(SwitchEvent::TxChanged { radio, origin }),
+                    None => {
+                        *self.last_unkey.lock().unwrap() = Some(Instant::now());
+                        None
+                    }
+                }
+            }
+        };
+        drop(state);
+        for event in change.into_iter().chain(input) {
+            let _ = event_tx.send(event);
+        }
+    }
+}
+
+/// Write `data` from offset `*written` on and flush it, so buffering
+/// transports (TLS, RFC 2217) put it on the wire right away.
+///
+/// `*written` advances as bytes are accepted, so after an error it tells
+/// where to resume.
+async fn write_flush<P>(port: &mut P, data: &[u8], written: &mut usize) -> std::io::Result<()>
+where
+    P: AsyncWrite + Unpin,
+{
+    while *written < data.len() {
+        match port.write(&data[*written..]).await? {
+            0 => return Err(std::io::ErrorKind::WriteZero.into()),
+            n => *written += n,
         }
     }
+    port.flush().await
 }
 
-/// Read bytes until CR or LF, returning the line as a string (with terminators).
-async fn read_line<P>(port: &mut P) -> std::io::Result<String>
+/// Read bytes until CR or LF, returning the line with its terminator.
+pub(crate) async fn read_line<P>(port: &mut P) -> std::io::Result<Vec<u8>>
 where
     P: AsyncRead + Unpin,
 {
@@ -283,5 +1609,5 @@ where
         }
     }
 
-    Ok(String::from_utf8_lossy(&buf).into_owned())
+    Ok(buf)
 }
@@ -0,0 +1,84 @@
+//! RX latch state machines: footswitch-driven, and transmit-driven.
+//!
+//! Pure state (no I/O): the device feeds them the current RX routing on each
+//! press or PTT change and applies the routing they return.
+
+use crate::types::{Radio, RxMode};
+
+/// Toggles RX focus to the other radio on one press and restores it on the next.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FootswitchLatch {
+    /// Routing to restore when the latch is released (`Some` while latched).
+    saved: Option<(Radio, RxMode)>,
+}
+
+impl FootswitchLatch {
+    /// Whether RX is currently latched to the other radio.
+    pub fn is_latched(&self) -> bool {
+        self.saved.is_some()
+    }
+
+    /// Toggle the latch for a press, given the current RX routing, and
+    /// return the routing to apply.
+    ///
+    /// Latching moves mono audio to the radio not currently heard; releasing
+    /// restores the routing saved when the latch was engaged.
+    pub fn press(&mut self, current: (Radio, RxMode)) -> (Radio, RxMode) {
+        match self.saved.take() {
+            Some(saved) => saved,
+            None => {
+                self.saved = Some(current);
+                (current.0.other(), RxMode::Mono)
+            }
+        }
+    }
+
+    /// Drop any saved routing (RX was set explicitly while latched).
+    pub fn release(&mut self) {
+        self.saved = None;
+    }
+}
+
+/// Routes RX to the radio not transmitting, and restores the prior routing
+/// when transmission ends.
+#[derive(Debug, Default)]
+pub(crate) struct TransmitLatch {
+    /// Radio transmitting (`Some` while transmitting).
+    transmitting: Option<Radio>,
+    /// Routing to restore when transmission ends.
+    saved: Option<(Radio, RxMode)>,
+}
+
+impl TransmitLatch {
+    /// Whether a transmission is under way.
+    pub fn is_transmitting(&self) -> bool {
+        self.transmitting.is_some()
+    }
+
+    /// Start transmitting on `radio`, returning the routing to apply, if any.
+    ///
+    /// Moving to the other radio mid-transmission keeps the routing saved
+    /// when the transmission started.
+    pub fn key(
+        &mut self,
+        radio: Radio,
+        current: Option<(Radio, RxMode)>,
+    ) -> Option<(Radio, RxMode)> {
+        if self.transmitting.replace(radio).is_none() {
+            self.saved = current;
+        }
+        let target = (radio.other(), RxMode::Mono);
+        (current != Some(target)).then_some(target)
+    }
+
+    /// End the transmission, returning the routing to restore, if any.
+    pub fn unkey(&mut self, current: Option<(Radio, RxMode)>) -> Option<(Radio, RxMode)> {
+        self.transmitting.take()?;
+        self.saved.take().filter(|&saved| current != Some(saved))
+    }
+
+    /// Drop any saved routing (RX was set explicitly while transmitting).
+    pub fn release(&mut self) {
+        self.saved = None;
+    }
+}
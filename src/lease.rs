@@ -0,0 +1,83 @@
+//! Exclusive access to a device's open link.
+//!
+//! Flashing new firmware onto an Arduino-based switch means talking to its
+//! bootloader over the same port. [`OtrspDevice::lease_port()`](crate::OtrspDevice::lease_port)
+//! suspends the IO task and hands the link over as a [`PortLease`], so an
+//! avrdude-style flasher can reuse the open connection instead of closing the
+//! device and reopening the port.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::oneshot;
+
+use crate::transport::BoxedTransport;
+
+/// The link to a switch, lent out by its IO task.
+///
+/// Reads and writes go straight to the port. Commands sent to the device
+/// meanwhile wait in its queue. Dropping the lease hands the link back and
+/// the IO task resumes; a device that was reflashed may then need
+/// [`OtrspDevice::reinitialize()`](crate::OtrspDevice::reinitialize).
+pub struct PortLease {
+    port: Option<BoxedTransport>,
+    back: Option<oneshot::Sender<BoxedTransport>>,
+}
+
+impl PortLease {
+    pub(crate) fn new(port: BoxedTransport, back: oneshot::Sender<BoxedTransport>) -> Self {
+        Self {
+            port: Some(port),
+            back: Some(back),
+        }
+    }
+
+    fn port(&mut self) -> &mut BoxedTransport {
+        self.port.as_mut().expect("port held until the lease drops")
+    }
+}
+
+impl std::fmt::Debug for PortLease {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PortLease").finish_non_exhaustive()
+    }
+}
+
+impl Drop for PortLease {
+    fn drop(&mut self) {
+        if let (Some(port), Some(back)) = (self.port.take(), self.back.take()) {
+            // The IO task may be gone, in which case the port just closes.
+            let _ = back.send(port);
+        }
+    }
+}
+
+impl AsyncRead for PortLease {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(self.get_mut().port()).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PortLease {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(self.get_mut().port()).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(self.get_mut().port()).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(self.get_mut().port()).poll_shutdown(cx)
+    }
+}
@@ -1,17 +1,40 @@
+pub mod alternator;
+pub mod audit;
+pub mod bandplan;
 pub mod builder;
+pub mod codec;
+pub mod constants;
 pub mod device;
+pub mod discovery;
 pub mod error;
 pub mod event;
+pub mod extension;
+pub mod failover;
+pub mod follower;
 pub(crate) mod io;
+pub(crate) mod latch;
+pub mod lease;
+pub mod n1mm;
 pub mod protocol;
+pub mod queue;
+pub mod reconnect;
+pub mod rfc2217;
+pub mod state;
+pub mod stats;
 pub mod switch;
+pub mod tap;
+pub mod timeline;
+pub mod transcript;
 pub mod transport;
 pub mod types;
 
 pub use builder::OtrspBuilder;
+pub use codec::OtrspCodec;
 pub use device::OtrspDevice;
 pub use error::{Error, Result};
-pub use event::SwitchEvent;
-pub use switch::{So2rSwitch, SwitchCapabilities, SwitchInfo};
+pub use event::{FilteredReceiver, Origin, SwitchEvent};
+pub use protocol::DeviceIdentity;
+pub use state::SwitchState;
+pub use switch::{AuxLimit, ProtocolFeatures, So2rSwitch, SwitchCapabilities, SwitchInfo};
 pub use transport::MockPort;
-pub use types::{Radio, RxMode};
+pub use types::{AudioRoute, Band, Radio, RxFollow, RxMode};
@@ -0,0 +1,106 @@
+//! N1MM+ RadioInfo UDP listener feeding the band follower.
+//!
+//! N1MM Logger+ broadcasts a `<RadioInfo>` XML datagram whenever a radio's
+//! frequency or state changes. The listener extracts the radio number and
+//! frequency and forwards them to a [`BandFollower`], so AUX band outputs
+//! track the logger without any CAT wiring.
+
+use std::net::SocketAddr;
+
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::task::JoinHandle;
+use tracing::{debug, trace, warn};
+
+use crate::error::Result;
+use crate::follower::BandFollower;
+use crate::types::Radio;
+
+/// UDP port N1MM+ broadcasts RadioInfo packets on by default.
+pub const DEFAULT_PORT: u16 = 12060;
+
+/// Frequency report extracted from a RadioInfo packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RadioInfo {
+    /// Radio the report refers to.
+    pub radio: Radio,
+    /// Receive frequency in Hz.
+    pub freq_hz: u64,
+}
+
+/// Parse an N1MM+ RadioInfo datagram.
+///
+/// Returns `None` for other packet types, radios other than 1 and 2, or a
+/// missing/invalid frequency. N1MM reports `<Freq>` in units of 10 Hz.
+pub fn parse_radio_info(xml: &str) -> Option<RadioInfo> {
+    let body = tag(xml, "RadioInfo")?;
+    let radio = match tag(body, "RadioNr")?.trim() {
+        "1" => Radio::Radio1,
+        "2" => Radio::Radio2,
+        _ => return None,
+    };
+    let tens_of_hz: u64 = tag(body, "Freq")?.trim().parse().ok()?;
+    Some(RadioInfo {
+        radio,
+        freq_hz: tens_of_hz * 10,
+    })
+}
+
+/// Text between `<name>` and `</name>`.
+fn tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{name}>");
+    let close = format!("</{name}>");
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(&xml[start..end])
+}
+
+/// A running RadioInfo listener; stops when dropped.
+pub struct N1mmListener {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl N1mmListener {
+    /// Bind a UDP socket and forward RadioInfo frequencies to `follower`.
+    ///
+    /// Use `("0.0.0.0", DEFAULT_PORT)` to receive N1MM's default broadcasts.
+    pub async fn bind<A: ToSocketAddrs>(addr: A, follower: BandFollower) -> Result<Self> {
+        let socket = UdpSocket::bind(addr).await?;
+        let local_addr = socket.local_addr()?;
+        debug!(%local_addr, "N1MM RadioInfo listener bound");
+        let task = tokio::spawn(listen(socket, follower));
+        Ok(Self { local_addr, task })
+    }
+
+    /// Address the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for N1mmListener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn listen(socket: UdpSocket, follower: BandFollower) {
+    let mut buf = vec![0u8; 8192];
+    loop {
+        let n = match socket.recv(&mut buf).await {
+            Ok(n) => n,
+            Err(e) => {
+                warn!("N1MM listener receive error: {e}");
+                continue;
+            }
+        };
+        let packet = String::from_utf8_lossy(&buf[..n]);
+        match parse_radio_info(&packet) {
+            Some(info) => {
+                trace!(?info, "N1MM RadioInfo");
+                follower.update(info.radio, info.freq_hz);
+            }
+            None => trace!("ignoring non-RadioInfo N1MM packet"),
+        }
+    }
+}
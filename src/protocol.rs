@@ -2,8 +2,15 @@
 //!
 //! All functions are pure (no I/O), fully unit-testable.
 
+use std::fmt;
+use std::str::FromStr;
+
+use crate::constants::*;
 use crate::error::{Error, Result};
-use crate::types::{Radio, RxMode};
+use crate::types::{Band, Radio, RxMode};
+
+/// Maximum length of a raw command, excluding the CR terminator.
+pub const MAX_COMMAND_LEN: usize = 64;
 
 /// Encode a TX selection command (`TX1\r` or `TX2\r`).
 pub fn encode_tx(radio: Radio) -> Vec<u8> {
@@ -13,26 +20,62 @@ pub fn encode_tx(radio: Radio) -> Vec<u8> {
     }
 }
 
+/// Encode a CR keying routing command (`CR1\r` or `CR2\r`).
+///
+/// Routes computer-generated keying (CW, PTT from the logger) to a radio
+/// independently of TX focus.
+pub fn encode_cr(radio: Radio) -> Vec<u8> {
+    match radio {
+        Radio::Radio1 => b"CR1\r".to_vec(),
+        Radio::Radio2 => b"CR2\r".to_vec(),
+    }
+}
+
 /// Encode an RX audio routing command.
 ///
 /// Produces `RX1\r`, `RX2\r`, `RX1S\r`, `RX2S\r`, `RX1R\r`, or `RX2R\r`.
-pub fn encode_rx(radio: Radio, mode: RxMode) -> Vec<u8> {
-    let num = match radio {
+/// [`RxMode::Mixed`] has no OTRSP command and fails with
+/// [`Error::Unsupported`]; devices that support it register an encoder as
+/// [`extension::RX_MIXED`](crate::extension::RX_MIXED).
+pub fn encode_rx(radio: Radio, mode: RxMode) -> Result<Vec<u8>> {
+    let suffix = rx_suffix(mode).ok_or_else(|| {
+        Error::Unsupported("mixed RX is a vendor extension, not an OTRSP command".into())
+    })?;
+    Ok(format!("{RX}{}{suffix}{TERMINATOR}", radio_digit(radio)).into_bytes())
+}
+
+/// Wire digit for a radio.
+fn radio_digit(radio: Radio) -> char {
+    match radio {
         Radio::Radio1 => '1',
         Radio::Radio2 => '2',
-    };
-    let suffix = match mode {
-        RxMode::Mono => "",
-        RxMode::Stereo => "S",
-        RxMode::ReverseStereo => "R",
-    };
-    format!("RX{num}{suffix}\r").into_bytes()
+    }
+}
+
+/// `RX` command suffix for a mode (empty for mono, `None` without an
+/// OTRSP form).
+fn rx_suffix(mode: RxMode) -> Option<&'static str> {
+    match mode {
+        RxMode::Mono => Some(""),
+        RxMode::Stereo => Some(RX_STEREO),
+        RxMode::ReverseStereo => Some(RX_REVERSE_STEREO),
+        RxMode::Mixed => None,
+    }
 }
 
 /// Encode an AUX output command (`AUXpv\r`).
 ///
 /// `port` must be 0-9, `value` is 0-255 (decimal encoding, variable width).
 pub fn encode_aux(port: u8, value: u8) -> Result<Vec<u8>> {
+    encode_aux_wide(port, value.into())
+}
+
+/// Encode an AUX output command with a 16-bit value (`AUX11000\r`).
+///
+/// Values above 255 are only understood by band-decoder firmware that accepts
+/// wide values; the encoding is the same variable-width decimal. `port` must
+/// be 0-9.
+pub fn encode_aux_wide(port: u8, value: u16) -> Result<Vec<u8>> {
     if port > 9 {
         return Err(Error::InvalidParameter(format!(
             "AUX port must be 0-9, got {port}"
@@ -41,11 +84,262 @@ pub fn encode_aux(port: u8, value: u8) -> Result<Vec<u8>> {
     Ok(format!("AUX{port}{value}\r").into_bytes())
 }
 
+/// Standard Yaesu band-data BCD code for a band.
+///
+/// 160m is 1 through 10m at 9 and 6m at 10, as understood by most band
+/// decoders. 60m has no standard code and maps to 0 (all outputs off); use a
+/// [`BcdMap`] to assign one.
+pub fn band_to_bcd(band: Band) -> u8 {
+    match band {
+        Band::M160 => 1,
+        Band::M80 => 2,
+        Band::M60 => 0,
+        Band::M40 => 3,
+        Band::M30 => 4,
+        Band::M20 => 5,
+        Band::M17 => 6,
+        Band::M15 => 7,
+        Band::M12 => 8,
+        Band::M10 => 9,
+        Band::M6 => 10,
+    }
+}
+
+/// Band to AUX value table, starting from the Yaesu BCD codes.
+///
+/// ```
+/// use otrsp::Band;
+/// use otrsp::protocol::BcdMap;
+///
+/// let map = BcdMap::yaesu().with(Band::M60, 11);
+/// assert_eq!(map.code(Band::M60), 11);
+/// assert_eq!(map.code(Band::M20), 5);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BcdMap {
+    codes: [u8; Band::ALL.len()],
+}
+
+impl BcdMap {
+    /// The standard Yaesu table ([`band_to_bcd()`]).
+    pub fn yaesu() -> Self {
+        Self {
+            codes: Band::ALL.map(band_to_bcd),
+        }
+    }
+
+    /// Override the code sent for `band`.
+    pub fn with(mut self, band: Band, code: u8) -> Self {
+        self.codes[band as usize] = code;
+        self
+    }
+
+    /// AUX value for `band`.
+    pub fn code(&self, band: Band) -> u8 {
+        self.codes[band as usize]
+    }
+}
+
+impl Default for BcdMap {
+    fn default() -> Self {
+        Self::yaesu()
+    }
+}
+
+/// An OTRSP command in structured form.
+///
+/// Lets tools that proxy, record or display traffic build and inspect
+/// commands without handling byte strings; [`encode()`](Command::encode)
+/// produces the wire form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `TXr`: select the TX radio.
+    Tx(Radio),
+    /// `RXr[S|R]`: set RX audio routing. [`RxMode::Mixed`] is sent with the
+    /// encoder registered as [`extension::RX_MIXED`](crate::extension::RX_MIXED).
+    Rx(Radio, RxMode),
+    /// `AUXpv`: set an AUX output (values above 255 need wide-AUX firmware).
+    Aux { port: u8, value: u16 },
+    /// `CRr`: route computer keying to a radio.
+    Keying(Radio),
+    /// `EVENT1` / `EVENT0`: turn unsolicited reporting on or off.
+    Event(bool),
+    /// `?NAME`: query the device name.
+    QueryName,
+    /// `?VERSION`: query the firmware version.
+    QueryVersion,
+    /// `?TX`: query TX focus.
+    QueryTx,
+    /// `?RX`: query RX routing.
+    QueryRx,
+    /// `?AUXp`: query an AUX output.
+    QueryAux(u8),
+    /// `?CR`: query keying routing.
+    QueryKeying,
+    /// `?EVENT`: probe for event reporting support.
+    QueryEvent,
+    /// `?PTT`: probe for PTT reporting support.
+    QueryPtt,
+    /// Any other command, sent verbatim (validated like [`encode_raw()`]).
+    Raw(String),
+}
+
+impl Command {
+    /// Encode the command for the wire, CR terminator included.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        match self {
+            Command::Tx(radio) => Ok(encode_tx(*radio)),
+            Command::Rx(radio, mode) => encode_rx(*radio, *mode),
+            Command::Aux { port, value } => encode_aux_wide(*port, *value),
+            Command::Keying(radio) => Ok(encode_cr(*radio)),
+            Command::Event(enabled) => Ok(encode_event(*enabled)),
+            Command::QueryName => Ok(encode_query_name()),
+            Command::QueryVersion => Ok(encode_query_version()),
+            Command::QueryTx => Ok(encode_query_tx()),
+            Command::QueryRx => Ok(encode_query_rx()),
+            Command::QueryAux(port) => encode_query_aux(*port),
+            Command::QueryKeying => Ok(encode_query_cr()),
+            Command::QueryEvent => Ok(encode_query_event()),
+            Command::QueryPtt => Ok(encode_query_ptt()),
+            Command::Raw(cmd) => encode_raw(cmd),
+        }
+    }
+
+    /// Whether the device answers this command with a response line.
+    ///
+    /// Raw commands count as queries when they start with `?`.
+    pub fn is_query(&self) -> bool {
+        match self {
+            Command::QueryName
+            | Command::QueryVersion
+            | Command::QueryTx
+            | Command::QueryRx
+            | Command::QueryAux(_)
+            | Command::QueryKeying
+            | Command::QueryEvent
+            | Command::QueryPtt => true,
+            Command::Raw(cmd) => cmd.starts_with('?'),
+            _ => false,
+        }
+    }
+}
+
+/// The command's wire text, without the CR terminator.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Tx(radio) => write!(f, "{TX}{}", radio_digit(*radio)),
+            Command::Rx(radio, mode) => match rx_suffix(*mode) {
+                Some(suffix) => write!(f, "{RX}{}{suffix}", radio_digit(*radio)),
+                // No wire form; named for logs only.
+                None => write!(f, "{RX}{} ({mode:?})", radio_digit(*radio)),
+            },
+            Command::Aux { port, value } => write!(f, "{AUX}{port}{value}"),
+            Command::Keying(radio) => write!(f, "{CR}{}", radio_digit(*radio)),
+            Command::Event(enabled) => write!(f, "{EVENT}{}", u8::from(*enabled)),
+            Command::QueryName => f.write_str(QUERY_NAME),
+            Command::QueryVersion => f.write_str(QUERY_VERSION),
+            Command::QueryTx => f.write_str(QUERY_TX),
+            Command::QueryRx => f.write_str(QUERY_RX),
+            Command::QueryAux(port) => write!(f, "{QUERY_AUX}{port}"),
+            Command::QueryKeying => f.write_str(QUERY_CR),
+            Command::QueryEvent => f.write_str(QUERY_EVENT),
+            Command::QueryPtt => f.write_str(QUERY_PTT),
+            Command::Raw(cmd) => f.write_str(cmd),
+        }
+    }
+}
+
+/// Parses the wire text of a command, with or without the CR terminator.
+///
+/// Text that is not the canonical form of a known command becomes
+/// [`Command::Raw`], validated like [`encode_raw()`].
+impl FromStr for Command {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim_end_matches(['\r', '\n']);
+        if let Some(command) = parse_command(s) {
+            return Ok(command);
+        }
+        encode_raw(s)?;
+        Ok(Command::Raw(s.to_string()))
+    }
+}
+
+/// Recognize the canonical text of a known command.
+fn parse_command(s: &str) -> Option<Command> {
+    let radio = |r: &str| parse_radio(r.as_bytes());
+    let digit = |p: &str| match p.as_bytes() {
+        [d @ b'0'..=b'9'] => Some(d - b'0'),
+        _ => None,
+    };
+    let command = match s {
+        QUERY_NAME => Command::QueryName,
+        QUERY_VERSION => Command::QueryVersion,
+        QUERY_TX => Command::QueryTx,
+        QUERY_RX => Command::QueryRx,
+        QUERY_CR => Command::QueryKeying,
+        QUERY_EVENT => Command::QueryEvent,
+        QUERY_PTT => Command::QueryPtt,
+        _ if s.starts_with(QUERY_AUX) => Command::QueryAux(digit(&s[QUERY_AUX.len()..])?),
+        _ if s.starts_with(TX) => Command::Tx(radio(&s[TX.len()..])?),
+        _ if s.starts_with(CR) => Command::Keying(radio(&s[CR.len()..])?),
+        _ if s.starts_with(RX) => {
+            let (r, suffix) = s[RX.len()..].split_at_checked(1)?;
+            let mode = match suffix {
+                "" => RxMode::Mono,
+                RX_STEREO => RxMode::Stereo,
+                RX_REVERSE_STEREO => RxMode::ReverseStereo,
+                _ => return None,
+            };
+            Command::Rx(radio(r)?, mode)
+        }
+        _ if s.starts_with(EVENT) => match &s[EVENT.len()..] {
+            "0" => Command::Event(false),
+            "1" => Command::Event(true),
+            _ => return None,
+        },
+        _ if s.starts_with(AUX) => {
+            let (p, value) = s[AUX.len()..].split_at_checked(1)?;
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            Command::Aux {
+                port: digit(p)?,
+                value: value.parse().ok()?,
+            }
+        }
+        _ => return None,
+    };
+    Some(command)
+}
+
 /// Encode a `?NAME` query command.
 pub fn encode_query_name() -> Vec<u8> {
     b"?NAME\r".to_vec()
 }
 
+/// Encode a `?TX` query command.
+pub fn encode_query_tx() -> Vec<u8> {
+    b"?TX\r".to_vec()
+}
+
+/// Encode a `?RX` query command.
+pub fn encode_query_rx() -> Vec<u8> {
+    b"?RX\r".to_vec()
+}
+
+/// Encode a `?CR` query command.
+pub fn encode_query_cr() -> Vec<u8> {
+    b"?CR\r".to_vec()
+}
+
+/// Encode a `?VERSION` query command.
+pub fn encode_query_version() -> Vec<u8> {
+    b"?VERSION\r".to_vec()
+}
+
 /// Encode a `?AUXp` query command.
 ///
 /// `port` must be 0-9.
@@ -58,62 +352,631 @@ pub fn encode_query_aux(port: u8) -> Result<Vec<u8>> {
     Ok(format!("?AUX{port}\r").into_bytes())
 }
 
+/// Encode an event reporting command (`EVENT1\r` to enable, `EVENT0\r` to disable).
+pub fn encode_event(enabled: bool) -> Vec<u8> {
+    if enabled {
+        b"EVENT1\r".to_vec()
+    } else {
+        b"EVENT0\r".to_vec()
+    }
+}
+
+/// Encode a `?EVENT` probe (used during feature negotiation).
+pub fn encode_query_event() -> Vec<u8> {
+    b"?EVENT\r".to_vec()
+}
+
+/// Encode a `?PTT` probe (used during feature negotiation).
+pub fn encode_query_ptt() -> Vec<u8> {
+    b"?PTT\r".to_vec()
+}
+
 /// Encode a raw command string with CR terminator appended.
-pub fn encode_raw(cmd: &str) -> Vec<u8> {
+///
+/// Rejects control characters (an embedded CR or LF would silently send two
+/// commands) and commands longer than [`MAX_COMMAND_LEN`].
+pub fn encode_raw(cmd: &str) -> Result<Vec<u8>> {
+    if let Some(c) = cmd.chars().find(|c| c.is_control()) {
+        return Err(Error::InvalidParameter(format!(
+            "raw command contains control character {c:?}"
+        )));
+    }
+    if cmd.len() > MAX_COMMAND_LEN {
+        return Err(Error::InvalidParameter(format!(
+            "raw command is {} bytes, maximum is {MAX_COMMAND_LEN}",
+            cmd.len()
+        )));
+    }
+    Ok(encode_raw_unchecked(cmd))
+}
+
+/// Encode a raw command string with CR terminator appended, without validation.
+pub fn encode_raw_unchecked(cmd: &str) -> Vec<u8> {
     format!("{cmd}\r").into_bytes()
 }
 
+/// Encode several commands back to back, for a single write.
+///
+/// Fails if any command fails to encode.
+pub fn encode_batch(commands: &[Command]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    for command in commands {
+        data.extend(command.encode()?);
+    }
+    Ok(data)
+}
+
+/// How tolerant response parsing is of deviations from the OTRSP grammar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Exact uppercase prefixes; surrounding whitespace and CR/LF are ignored.
+    #[default]
+    Standard,
+    /// Also accept lowercase prefixes and stray whitespace inside the line
+    /// (`aux1 4 ` reads as `AUX14`).
+    Permissive,
+    /// Reject query answers that deviate from the published grammar; see
+    /// [`validate_response()`].
+    Strict,
+}
+
+/// Prefixes whose body is text and keeps its case and inner spacing.
+const TEXT_PREFIXES: [&str; 2] = ["VERSION", "NAME"];
+
+/// Prefixes whose body is digits and mode letters.
+const CODE_PREFIXES: [&str; 7] = ["EVENT", "AUX", "PTT", "TX", "RX", "CR", "FS"];
+
+/// Rewrite a response line into canonical form for the given mode.
+///
+/// In [`ParseMode::Permissive`], known prefixes are matched
+/// case-insensitively and uppercased, and whitespace is dropped from numeric
+/// bodies; `NAME` and `VERSION` text is only trimmed. Unknown lines are
+/// trimmed and otherwise left alone. [`ParseMode::Standard`] returns the
+/// line unchanged, as does [`ParseMode::Strict`]. The result can be fed to
+/// any of the `parse_*` functions.
+pub fn normalize_response(bytes: &[u8], mode: ParseMode) -> Vec<u8> {
+    if mode != ParseMode::Permissive {
+        return bytes.to_vec();
+    }
+    let line = bytes.trim_ascii();
+    let (marker, body): (&[u8], &[u8]) = match line.strip_prefix(b"$") {
+        Some(rest) => (b"$", rest.trim_ascii_start()),
+        None => (b"", line),
+    };
+    let starts_with = |prefix: &str| {
+        body.get(..prefix.len())
+            .is_some_and(|head| head.eq_ignore_ascii_case(prefix.as_bytes()))
+    };
+    let mut out = marker.to_vec();
+    if let Some(prefix) = TEXT_PREFIXES.into_iter().find(|p| starts_with(p)) {
+        out.extend_from_slice(prefix.as_bytes());
+        out.extend_from_slice(body[prefix.len()..].trim_ascii());
+    } else if let Some(prefix) = CODE_PREFIXES.into_iter().find(|p| starts_with(p)) {
+        out.extend_from_slice(prefix.as_bytes());
+        out.extend(
+            body[prefix.len()..]
+                .iter()
+                .filter(|b| !b.is_ascii_whitespace())
+                .map(u8::to_ascii_uppercase),
+        );
+    } else {
+        return line.to_vec();
+    }
+    out
+}
+
+/// Check a response line against the published OTRSP grammar.
+///
+/// The line must end in a single CR, contain only printable ASCII, and be
+/// one of `NAME<text>`, `VERSION<text>`, `AUXpv`, `TXr`, `RXr[S|R]`,
+/// `CRr`, `EVENTn`, `PTTn` or `FSn` (optionally `$`-prefixed), with nothing
+/// after the value, or the unknown-command reply `?`. Prefixes are
+/// case-sensitive and numbers carry no leading zeros or padding. Mixed RX
+/// is a vendor extension ([`extension::RX_MIXED`](crate::extension::RX_MIXED)),
+/// so an `M` suffix is rejected.
+pub fn validate_response(bytes: &[u8]) -> Result<()> {
+    let fail = |reason: String| {
+        Err(Error::Protocol(format!(
+            "{reason} in response \"{}\"",
+            bytes.escape_ascii()
+        )))
+    };
+
+    let Some(body) = bytes.strip_suffix(b"\r") else {
+        return fail("missing CR terminator".into());
+    };
+    if let Some(pos) = body.iter().position(|b| !(0x20..0x7f).contains(b)) {
+        return fail(format!(
+            "unexpected byte 0x{:02X} at offset {pos}",
+            body[pos]
+        ));
+    }
+    if body == b"?" {
+        return Ok(());
+    }
+    let line = body.strip_prefix(b"$").unwrap_or(body);
+
+    let is_number = |s: &[u8]| {
+        !s.is_empty() && s.iter().all(u8::is_ascii_digit) && (s == b"0" || !s.starts_with(b"0"))
+    };
+    let is_radio = |s: &[u8]| s == b"1" || s == b"2";
+
+    let Some((prefix, rest)) = [
+        "VERSION", "NAME", "EVENT", "AUX", "PTT", "TX", "RX", "CR", "FS",
+    ]
+    .into_iter()
+    .find_map(|p| line.strip_prefix(p.as_bytes()).map(|rest| (p, rest))) else {
+        return fail("unrecognized prefix".into());
+    };
+    let valid = match prefix {
+        "NAME" | "VERSION" => !rest.is_empty() && rest.trim_ascii() == rest,
+        "AUX" => rest.split_first().is_some_and(|(port, value)| {
+            // The value must also fit the 16 bits parse_aux_response() reads.
+            port.is_ascii_digit()
+                && is_number(value)
+                && std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|v| v.parse::<u16>().ok())
+                    .is_some()
+        }),
+        "TX" | "CR" => is_radio(rest),
+        "RX" => rest
+            .split_at_checked(1)
+            .is_some_and(|(radio, mode)| is_radio(radio) && matches!(mode, b"" | b"S" | b"R")),
+        "PTT" => rest == b"0" || is_radio(rest),
+        _ => rest == b"0" || rest == b"1",
+    };
+    if valid {
+        Ok(())
+    } else {
+        fail(format!(
+            "malformed {prefix} value \"{}\"",
+            rest.escape_ascii()
+        ))
+    }
+}
+
+/// Strip CR/LF terminators and surrounding whitespace from a response.
+fn trim_response(bytes: &[u8]) -> &[u8] {
+    bytes.trim_ascii()
+}
+
+/// Convert response text to a `String` for the public API.
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
 /// Parse a `?NAME` response, stripping the `NAME` prefix and CR/LF terminators.
 ///
 /// Real OTRSP devices respond with `NAME<devicename>\r` (e.g. `NAMESO2Rduino\r`).
 pub fn parse_name_response(bytes: &[u8]) -> String {
-    let s = String::from_utf8_lossy(bytes);
-    let s = s.trim_end_matches(['\r', '\n']).trim();
-    s.strip_prefix("NAME")
-        .map(|s| s.trim())
-        .unwrap_or(s)
-        .to_string()
+    let s = trim_response(bytes);
+    text(s.strip_prefix(b"NAME").map_or(s, <[u8]>::trim_ascii))
+}
+
+/// Parse a `?TX` response (`TX1` or `TX2`) into the radio with TX focus.
+pub fn parse_tx_response(bytes: &[u8]) -> Result<Radio> {
+    let s = trim_response(bytes);
+    let rest = s
+        .strip_prefix(b"TX")
+        .ok_or_else(|| Error::Protocol(format!("expected TX prefix, got: {}", s.escape_ascii())))?;
+    parse_radio(rest)
+        .ok_or_else(|| Error::Protocol(format!("invalid TX radio: {}", rest.escape_ascii())))
+}
+
+/// Parse a `?RX` response (`RX1`, `RX2S`, `RX1R`, ...) into radio and mode.
+pub fn parse_rx_response(bytes: &[u8]) -> Result<(Radio, RxMode)> {
+    let s = trim_response(bytes);
+    let rest = s
+        .strip_prefix(b"RX")
+        .ok_or_else(|| Error::Protocol(format!("expected RX prefix, got: {}", s.escape_ascii())))?;
+    parse_rx_routing(rest)
+        .ok_or_else(|| Error::Protocol(format!("invalid RX routing: {}", rest.escape_ascii())))
+}
+
+/// Parse a `?CR` response (`CR1` or `CR2`) into the radio receiving keying.
+pub fn parse_cr_response(bytes: &[u8]) -> Result<Radio> {
+    let s = trim_response(bytes);
+    let rest = s
+        .strip_prefix(b"CR")
+        .ok_or_else(|| Error::Protocol(format!("expected CR prefix, got: {}", s.escape_ascii())))?;
+    parse_radio(rest)
+        .ok_or_else(|| Error::Protocol(format!("invalid CR radio: {}", rest.escape_ascii())))
+}
+
+/// Parse RX routing after the `RX` prefix: a radio number and optional mode suffix.
+fn parse_rx_routing(s: &[u8]) -> Option<(Radio, RxMode)> {
+    let (num, suffix) = s.split_at_checked(1)?;
+    let mode = match suffix {
+        b"" => RxMode::Mono,
+        b"S" => RxMode::Stereo,
+        b"R" => RxMode::ReverseStereo,
+        _ => return None,
+    };
+    Some((parse_radio(num)?, mode))
+}
+
+/// Parse a radio number (`1` or `2`).
+fn parse_radio(s: &[u8]) -> Option<Radio> {
+    match s {
+        b"1" => Some(Radio::Radio1),
+        b"2" => Some(Radio::Radio2),
+        _ => None,
+    }
+}
+
+/// Parse a `?VERSION` response, stripping the `VERSION` prefix and CR/LF terminators.
+pub fn parse_version_response(bytes: &[u8]) -> String {
+    let s = trim_response(bytes);
+    text(s.strip_prefix(b"VERSION").map_or(s, <[u8]>::trim_ascii))
 }
 
 /// Parse a `?AUXpv` response into `(port, value)`.
 ///
-/// Expected format: `AUX<port><value>` possibly followed by CR/LF.
-pub fn parse_aux_response(bytes: &[u8]) -> Result<(u8, u8)> {
-    let s = String::from_utf8_lossy(bytes);
-    let s = s.trim_end_matches(['\r', '\n']).trim();
+/// Expected format: `AUX<port><value>` possibly followed by CR/LF. Values
+/// above 255 from band-decoder firmware with wide AUX outputs are accepted.
+pub fn parse_aux_response(bytes: &[u8]) -> Result<(u8, u16)> {
+    let s = trim_response(bytes);
 
-    let rest = s
-        .strip_prefix("AUX")
-        .ok_or_else(|| Error::Protocol(format!("expected AUX prefix, got: {s}")))?;
+    let rest = s.strip_prefix(b"AUX").ok_or_else(|| {
+        Error::Protocol(format!("expected AUX prefix, got: {}", s.escape_ascii()))
+    })?;
 
-    if rest.is_empty() {
+    let Some((&digit, value)) = rest.split_first() else {
         return Err(Error::Protocol(
             "AUX response missing port and value".into(),
         ));
-    }
+    };
 
-    let port = rest.as_bytes()[0]
-        .checked_sub(b'0')
-        .filter(|&p| p <= 9)
-        .ok_or_else(|| {
-            Error::Protocol(format!(
-                "invalid AUX port digit: {}",
-                rest.as_bytes()[0] as char
-            ))
-        })?;
+    let port = digit.checked_sub(b'0').filter(|&p| p <= 9).ok_or_else(|| {
+        Error::Protocol(format!(
+            "invalid AUX port digit: {}",
+            [digit].escape_ascii()
+        ))
+    })?;
 
-    let value_str = &rest[1..];
-    let value: u8 = value_str
-        .parse()
-        .map_err(|_| Error::Protocol(format!("invalid AUX value: {value_str}")))?;
+    let value: u16 = std::str::from_utf8(value)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| Error::Protocol(format!("invalid AUX value: {}", value.escape_ascii())))?;
 
     Ok((port, value))
 }
 
+/// A state change reported unsolicited by a device with events enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notification {
+    /// `$TXr`: TX moved to a radio.
+    Tx(Radio),
+    /// `$RXr[S|R]`: RX routing changed.
+    Rx(Radio, RxMode),
+    /// `$AUXpv`: an AUX output changed.
+    Aux { port: u8, value: u16 },
+    /// `$FS1` / `$FS0`: footswitch pressed or released.
+    Footswitch(bool),
+    /// `$PTTr` / `$PTT0`: PTT keyed on a radio, or released.
+    Ptt(Option<Radio>),
+    /// `$CRr`: keying routing moved to a radio.
+    Keying(Radio),
+}
+
+/// Parse an unsolicited `$` notification line.
+///
+/// Returns `None` for anything that is not a well-formed `$TX`, `$RX`,
+/// `$AUX`, `$CR`, `$FS` or `$PTT` notification.
+pub fn parse_notification(bytes: &[u8]) -> Option<Notification> {
+    let body = trim_response(bytes).strip_prefix(b"$")?;
+    let radio = parse_radio;
+
+    if let Some(rest) = body.strip_prefix(b"TX") {
+        return radio(rest).map(Notification::Tx);
+    }
+    if let Some(rest) = body.strip_prefix(b"RX") {
+        let (radio, mode) = parse_rx_routing(rest)?;
+        return Some(Notification::Rx(radio, mode));
+    }
+    if body.starts_with(b"AUX") {
+        let (port, value) = parse_aux_response(body).ok()?;
+        return Some(Notification::Aux { port, value });
+    }
+    if let Some(rest) = body.strip_prefix(b"CR") {
+        return radio(rest).map(Notification::Keying);
+    }
+    if let Some(rest) = body.strip_prefix(b"FS") {
+        return match rest {
+            b"0" => Some(Notification::Footswitch(false)),
+            b"1" => Some(Notification::Footswitch(true)),
+            _ => None,
+        };
+    }
+    if let Some(rest) = body.strip_prefix(b"PTT") {
+        return match rest {
+            b"0" => Some(Notification::Ptt(None)),
+            _ => radio(rest).map(|r| Notification::Ptt(Some(r))),
+        };
+    }
+    None
+}
+
+/// A line received from the device, classified by its prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// `NAME<name>`: answer to `?NAME`.
+    Name(String),
+    /// `VERSION<version>`: answer to `?VERSION`.
+    Version(String),
+    /// `AUXpv`: answer to `?AUXp`.
+    Aux { port: u8, value: u16 },
+    /// `TXr`: answer to `?TX`.
+    Tx(Radio),
+    /// `RXr[S|R]`: answer to `?RX`.
+    Rx(Radio, RxMode),
+    /// `CRr`: answer to `?CR`.
+    Keying(Radio),
+    /// An unsolicited `$` notification.
+    Notification(Notification),
+    /// The device rejected a command (`?` or a line starting with `ERR`).
+    Error(String),
+    /// Anything else, terminators stripped. Kept as bytes since it need not
+    /// be text.
+    Unknown(Vec<u8>),
+}
+
+/// Classify a line received from the device.
+///
+/// Lines with a known prefix but a malformed body (`TX3`, `$FOO`) are a
+/// [`Error::Protocol`]; lines with no known prefix are [`Response::Unknown`].
+pub fn parse_response(bytes: &[u8]) -> Result<Response> {
+    let s = trim_response(bytes);
+
+    if s.starts_with(b"$") {
+        return parse_notification(s)
+            .map(Response::Notification)
+            .ok_or_else(|| {
+                Error::Protocol(format!("malformed notification: {}", s.escape_ascii()))
+            });
+    }
+    if s == b"?" || s.starts_with(b"ERR") {
+        return Ok(Response::Error(text(s)));
+    }
+    if s.starts_with(b"NAME") {
+        return Ok(Response::Name(parse_name_response(s)));
+    }
+    if s.starts_with(b"VERSION") {
+        return Ok(Response::Version(parse_version_response(s)));
+    }
+    if s.starts_with(b"AUX") {
+        let (port, value) = parse_aux_response(s)?;
+        return Ok(Response::Aux { port, value });
+    }
+    if s.starts_with(b"TX") {
+        return parse_tx_response(s).map(Response::Tx);
+    }
+    if s.starts_with(b"RX") {
+        let (radio, mode) = parse_rx_response(s)?;
+        return Ok(Response::Rx(radio, mode));
+    }
+    if s.starts_with(b"CR") {
+        return parse_cr_response(s).map(Response::Keying);
+    }
+    Ok(Response::Unknown(s.to_vec()))
+}
+
+/// Check whether a probe response answers the given query prefix.
+///
+/// Devices that do not implement a query either stay silent (timeout) or
+/// answer with something else; only a line starting with `prefix` counts.
+pub fn is_probe_answer(bytes: &[u8], prefix: &str) -> bool {
+    bytes.trim_ascii().starts_with(prefix.as_bytes())
+}
+
+/// Prefix that starts the answer to a standard query (`AUX1` for `?AUX1\r`).
+///
+/// `None` for anything else, including raw queries, whose answers are not
+/// known in advance.
+pub fn answer_prefix(command: &[u8]) -> Option<&[u8]> {
+    const QUERIES: [&[u8]; 7] = [b"NAME", b"VERSION", b"TX", b"RX", b"CR", b"EVENT", b"PTT"];
+    let query = trim_response(command).strip_prefix(b"?")?;
+    (QUERIES.contains(&query) || matches!(query, [b'A', b'U', b'X', b'0'..=b'9'])).then_some(query)
+}
+
+/// Device family and version, recognized from the `?NAME` answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    /// The name exactly as reported.
+    pub raw: String,
+    /// Designer or maker, for recognized devices.
+    pub vendor: Option<String>,
+    /// Product, for recognized devices.
+    pub model: Option<String>,
+    /// Version embedded in the name (`SO2Rduino 1.3`, `YCCC SO2R+ v2.1`).
+    pub version: Option<String>,
+}
+
+/// Identify a device by its `?NAME` answer (prefix already stripped).
+///
+/// Recognizes the SO2Rduino and YCCC SO2R box families; any other name keeps
+/// `vendor` and `model` empty. A trailing token such as `1.3` or `v2` is
+/// taken as the version for every device.
+pub fn parse_identity(name: &str) -> DeviceIdentity {
+    let name = name.trim();
+    let (base, version) = match name.rsplit_once(char::is_whitespace) {
+        Some((base, last)) if is_version(last) => (base.trim_end(), Some(last.to_string())),
+        _ => (name, None),
+    };
+    let upper = base.to_ascii_uppercase();
+    let (vendor, model) = if upper.starts_with("SO2RDUINO") {
+        (Some("K1XM"), Some("SO2Rduino"))
+    } else if upper.starts_with("YCCC") {
+        let model = base[4..].trim();
+        (
+            Some("YCCC"),
+            Some(if model.is_empty() { "SO2R" } else { model }),
+        )
+    } else {
+        (None, None)
+    };
+    DeviceIdentity {
+        raw: name.to_string(),
+        vendor: vendor.map(str::to_string),
+        model: model.map(str::to_string),
+        version,
+    }
+}
+
+/// Whether a name token looks like a version number (`1.3`, `v2`, `V1.0b`).
+fn is_version(token: &str) -> bool {
+    let digits = token.strip_prefix(['v', 'V']).unwrap_or(token);
+    digits.starts_with(|c: char| c.is_ascii_digit())
+        && (digits.len() < token.len() || digits.contains('.'))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_notification() {
+        assert_eq!(
+            parse_notification(b"$TX2\r"),
+            Some(Notification::Tx(Radio::Radio2))
+        );
+        assert_eq!(
+            parse_notification(b"$RX1R\r"),
+            Some(Notification::Rx(Radio::Radio1, RxMode::ReverseStereo))
+        );
+        assert_eq!(
+            parse_notification(b"$RX2\r"),
+            Some(Notification::Rx(Radio::Radio2, RxMode::Mono))
+        );
+        assert_eq!(
+            parse_notification(b"$AUX112\r"),
+            Some(Notification::Aux { port: 1, value: 12 })
+        );
+        assert_eq!(
+            parse_notification(b"$FS1\r"),
+            Some(Notification::Footswitch(true))
+        );
+        assert_eq!(
+            parse_notification(b"$PTT2\r"),
+            Some(Notification::Ptt(Some(Radio::Radio2)))
+        );
+        assert_eq!(
+            parse_notification(b"$PTT0\r"),
+            Some(Notification::Ptt(None))
+        );
+        assert_eq!(
+            parse_notification(b"$CR2\r"),
+            Some(Notification::Keying(Radio::Radio2))
+        );
+        assert_eq!(parse_notification(b"$FS2\r"), None);
+        assert_eq!(parse_notification(b"TX1\r"), None);
+        assert_eq!(parse_notification(b"$TX3\r"), None);
+        assert_eq!(parse_notification(b"$RX1X\r"), None);
+        assert_eq!(parse_notification(b"$FOO\r"), None);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response(b"NAMESO2Rduino\r").unwrap(),
+            Response::Name("SO2Rduino".into())
+        );
+        assert_eq!(
+            parse_response(b"VERSION1.2\r").unwrap(),
+            Response::Version("1.2".into())
+        );
+        assert_eq!(
+            parse_response(b"AUX14\r").unwrap(),
+            Response::Aux { port: 1, value: 4 }
+        );
+        assert_eq!(
+            parse_response(b"TX2\r").unwrap(),
+            Response::Tx(Radio::Radio2)
+        );
+        assert_eq!(
+            parse_response(b"RX1S\r\n").unwrap(),
+            Response::Rx(Radio::Radio1, RxMode::Stereo)
+        );
+        assert_eq!(
+            parse_response(b"CR1\r").unwrap(),
+            Response::Keying(Radio::Radio1)
+        );
+        assert_eq!(
+            parse_response(b"$FS1\r").unwrap(),
+            Response::Notification(Notification::Footswitch(true))
+        );
+        assert_eq!(parse_response(b"?\r").unwrap(), Response::Error("?".into()));
+        assert_eq!(
+            parse_response(b"ERR BADCMD\r").unwrap(),
+            Response::Error("ERR BADCMD".into())
+        );
+        assert_eq!(
+            parse_response(b"HELLO\r").unwrap(),
+            Response::Unknown(b"HELLO".to_vec())
+        );
+        assert!(parse_response(b"TX3\r").is_err());
+        assert!(parse_response(b"$FOO\r").is_err());
+    }
+
+    #[test]
+    fn test_normalize_permissive() {
+        let permissive = |line: &[u8]| normalize_response(line, ParseMode::Permissive);
+        assert_eq!(permissive(b"aux14  \r"), b"AUX14");
+        assert_eq!(permissive(b" Aux 1 4\r\n"), b"AUX14");
+        assert_eq!(permissive(b"rx2s\r"), b"RX2S");
+        assert_eq!(permissive(b"$tx 2\r"), b"$TX2");
+        assert_eq!(permissive(b"name SO2Rduino Mk2 \r"), b"NAMESO2Rduino Mk2");
+        assert_eq!(permissive(b"hello\r"), b"hello");
+        assert_eq!(
+            parse_aux_response(&permissive(b"aux2 255 \r")).unwrap(),
+            (2, 255)
+        );
+        assert_eq!(
+            normalize_response(b"aux14\r", ParseMode::Standard),
+            b"aux14\r"
+        );
+    }
+
+    #[test]
+    fn test_validate_response() {
+        for line in [
+            &b"NAMESO2Rduino\r"[..],
+            b"VERSION1.2\r",
+            b"AUX14\r",
+            b"AUX2255\r",
+            b"AUX00\r",
+            b"TX2\r",
+            b"RX1S\r",
+            b"CR1\r",
+            b"$PTT0\r",
+            b"EVENT1\r",
+            b"?\r",
+        ] {
+            assert!(validate_response(line).is_ok(), "{line:?}");
+        }
+        for line in [
+            &b"AUX14\r\n"[..],
+            b"AUX14",
+            b"aux14\r",
+            b"AUX14 \r",
+            b"AUX14x\r",
+            b"AUX104\r",
+            b"AUX170000\r",
+            b"AUX1\r",
+            b"TX3\r",
+            b"RX1X\r",
+            b"NAME\r",
+            b"NAME SO2R\r",
+            b"HELLO\r",
+            b"TX\x001\r",
+        ] {
+            assert!(validate_response(line).is_err(), "{line:?}");
+        }
+        let err = validate_response(b"AUX14x\r").unwrap_err().to_string();
+        assert!(err.contains("malformed AUX value"), "{err}");
+    }
+
     #[test]
     fn test_encode_tx() {
         assert_eq!(encode_tx(Radio::Radio1), b"TX1\r");
@@ -122,20 +985,36 @@ mod tests {
 
     #[test]
     fn test_encode_rx_mono() {
-        assert_eq!(encode_rx(Radio::Radio1, RxMode::Mono), b"RX1\r");
-        assert_eq!(encode_rx(Radio::Radio2, RxMode::Mono), b"RX2\r");
+        assert_eq!(encode_rx(Radio::Radio1, RxMode::Mono).unwrap(), b"RX1\r");
+        assert_eq!(encode_rx(Radio::Radio2, RxMode::Mono).unwrap(), b"RX2\r");
     }
 
     #[test]
     fn test_encode_rx_stereo() {
-        assert_eq!(encode_rx(Radio::Radio1, RxMode::Stereo), b"RX1S\r");
-        assert_eq!(encode_rx(Radio::Radio2, RxMode::Stereo), b"RX2S\r");
+        assert_eq!(encode_rx(Radio::Radio1, RxMode::Stereo).unwrap(), b"RX1S\r");
+        assert_eq!(encode_rx(Radio::Radio2, RxMode::Stereo).unwrap(), b"RX2S\r");
     }
 
     #[test]
     fn test_encode_rx_reverse_stereo() {
-        assert_eq!(encode_rx(Radio::Radio1, RxMode::ReverseStereo), b"RX1R\r");
-        assert_eq!(encode_rx(Radio::Radio2, RxMode::ReverseStereo), b"RX2R\r");
+        assert_eq!(
+            encode_rx(Radio::Radio1, RxMode::ReverseStereo).unwrap(),
+            b"RX1R\r"
+        );
+        assert_eq!(
+            encode_rx(Radio::Radio2, RxMode::ReverseStereo).unwrap(),
+            b"RX2R\r"
+        );
+    }
+
+    #[test]
+    fn test_encode_rx_mixed_unsupported() {
+        assert!(
+            encode_rx(Radio::Radio1, RxMode::Mixed)
+                .unwrap_err()
+                .is_unsupported()
+        );
+        assert!(parse_rx_response(b"RX1M\r").is_err());
     }
 
     #[test]
@@ -151,6 +1030,28 @@ mod tests {
         assert!(encode_aux(10, 0).is_err());
     }
 
+    #[test]
+    fn test_encode_aux_wide() {
+        assert_eq!(encode_aux_wide(1, 1000).unwrap(), b"AUX11000\r");
+        assert_eq!(encode_aux_wide(2, 65535).unwrap(), b"AUX265535\r");
+        assert_eq!(encode_aux_wide(1, 4).unwrap(), encode_aux(1, 4).unwrap());
+        assert!(encode_aux_wide(10, 300).is_err());
+    }
+
+    #[test]
+    fn test_band_to_bcd() {
+        assert_eq!(band_to_bcd(Band::M160), 1);
+        assert_eq!(band_to_bcd(Band::M20), 5);
+        assert_eq!(band_to_bcd(Band::M6), 10);
+        assert_eq!(band_to_bcd(Band::M60), 0);
+        let map = BcdMap::default().with(Band::M20, 12);
+        assert_eq!(map.code(Band::M20), 12);
+        assert_eq!(map.code(Band::M40), 3);
+        for band in Band::ALL {
+            assert_eq!(BcdMap::yaesu().code(band), band_to_bcd(band));
+        }
+    }
+
     #[test]
     fn test_encode_query_name() {
         assert_eq!(encode_query_name(), b"?NAME\r");
@@ -163,28 +1064,238 @@ mod tests {
         assert!(encode_query_aux(10).is_err());
     }
 
+    #[test]
+    fn test_encode_probes() {
+        assert_eq!(encode_query_event(), b"?EVENT\r");
+        assert_eq!(encode_query_ptt(), b"?PTT\r");
+    }
+
+    #[test]
+    fn test_is_probe_answer() {
+        assert!(is_probe_answer(b"EVENT1\r", "EVENT"));
+        assert!(is_probe_answer(b"PTT0\r\n", "PTT"));
+        assert!(!is_probe_answer(b"?\r", "EVENT"));
+        assert!(!is_probe_answer(b"\r", "PTT"));
+    }
+
+    #[test]
+    fn test_parse_identity() {
+        let id = parse_identity("SO2RDUINO");
+        assert_eq!(id.vendor.as_deref(), Some("K1XM"));
+        assert_eq!(id.model.as_deref(), Some("SO2Rduino"));
+        assert_eq!(id.version, None);
+
+        let id = parse_identity("SO2Rduino 1.3");
+        assert_eq!(id.raw, "SO2Rduino 1.3");
+        assert_eq!(id.model.as_deref(), Some("SO2Rduino"));
+        assert_eq!(id.version.as_deref(), Some("1.3"));
+
+        let id = parse_identity("YCCC SO2R+ v2");
+        assert_eq!(id.vendor.as_deref(), Some("YCCC"));
+        assert_eq!(id.model.as_deref(), Some("SO2R+"));
+        assert_eq!(id.version.as_deref(), Some("v2"));
+        assert_eq!(parse_identity("YCCC").model.as_deref(), Some("SO2R"));
+
+        let id = parse_identity("RigSelect Pro");
+        assert_eq!((id.vendor, id.model, id.version), (None, None, None));
+        assert_eq!(parse_identity("Box 2").version, None);
+        assert_eq!(parse_identity("Box 2.0").version.as_deref(), Some("2.0"));
+    }
+
+    #[test]
+    fn test_answer_prefix() {
+        assert_eq!(answer_prefix(b"?NAME\r"), Some(&b"NAME"[..]));
+        assert_eq!(answer_prefix(b"?AUX2\r"), Some(&b"AUX2"[..]));
+        assert_eq!(answer_prefix(&encode_query_cr()), Some(&b"CR"[..]));
+        assert_eq!(answer_prefix(b"?AUX\r"), None);
+        assert_eq!(answer_prefix(b"?TEMP\r"), None);
+        assert_eq!(answer_prefix(b"TX1\r"), None);
+    }
+
     #[test]
     fn test_encode_raw() {
-        assert_eq!(encode_raw("HELLO"), b"HELLO\r");
-        assert_eq!(encode_raw("TX1"), b"TX1\r");
+        assert_eq!(encode_raw("HELLO").unwrap(), b"HELLO\r");
+        assert_eq!(encode_raw("TX1").unwrap(), b"TX1\r");
+    }
+
+    #[test]
+    fn test_encode_raw_rejects_control_and_length() {
+        assert!(encode_raw("TX1\rTX2").is_err());
+        assert!(encode_raw("TX1\n").is_err());
+        assert!(encode_raw("\x1b").is_err());
+        assert!(encode_raw(&"A".repeat(MAX_COMMAND_LEN)).is_ok());
+        assert!(encode_raw(&"A".repeat(MAX_COMMAND_LEN + 1)).is_err());
+        assert_eq!(encode_raw_unchecked("TX1\rTX2"), b"TX1\rTX2\r");
     }
 
     #[test]
     fn test_parse_name_response() {
         // Real devices respond with NAME prefix
         assert_eq!(parse_name_response(b"NAMESO2RDUINO\r"), "SO2RDUINO");
-        assert_eq!(parse_name_response(b"NAMERigSelect Pro\r\n"), "RigSelect Pro");
+        assert_eq!(
+            parse_name_response(b"NAMERigSelect Pro\r\n"),
+            "RigSelect Pro"
+        );
         assert_eq!(parse_name_response(b"NAME  YCCC SO2R  \r"), "YCCC SO2R");
         assert_eq!(parse_name_response(b"NAMEDeviceName"), "DeviceName");
         // Graceful handling of responses without NAME prefix
         assert_eq!(parse_name_response(b"SO2RDUINO\r"), "SO2RDUINO");
     }
 
+    #[test]
+    fn test_encode_event() {
+        assert_eq!(encode_event(true), b"EVENT1\r");
+        assert_eq!(encode_event(false), b"EVENT0\r");
+    }
+
+    #[test]
+    fn test_tx_query() {
+        assert_eq!(encode_query_tx(), b"?TX\r");
+        assert_eq!(parse_tx_response(b"TX1\r").unwrap(), Radio::Radio1);
+        assert_eq!(parse_tx_response(b"TX2\r\n").unwrap(), Radio::Radio2);
+        assert!(parse_tx_response(b"TX3\r").is_err());
+        assert!(parse_tx_response(b"RX1\r").is_err());
+    }
+
+    #[test]
+    fn test_command_encode() {
+        let cases: [(Command, &[u8]); 9] = [
+            (Command::Tx(Radio::Radio1), b"TX1\r"),
+            (Command::Rx(Radio::Radio2, RxMode::Stereo), b"RX2S\r"),
+            (Command::Aux { port: 3, value: 12 }, b"AUX312\r"),
+            (Command::Event(false), b"EVENT0\r"),
+            (Command::QueryName, b"?NAME\r"),
+            (Command::QueryAux(2), b"?AUX2\r"),
+            (Command::QueryKeying, b"?CR\r"),
+            (Command::QueryPtt, b"?PTT\r"),
+            (Command::Raw("SETUP".into()), b"SETUP\r"),
+        ];
+        for (command, wire) in cases {
+            assert_eq!(command.encode().unwrap(), wire, "{command:?}");
+        }
+        assert!(Command::QueryAux(10).encode().is_err());
+        assert!(Command::Raw("TX1\rTX2".into()).encode().is_err());
+    }
+
+    #[test]
+    fn test_command_is_query() {
+        assert!(Command::QueryTx.is_query());
+        assert!(Command::QueryAux(1).is_query());
+        assert!(Command::Raw("?FOO".into()).is_query());
+        assert!(!Command::Raw("FOO".into()).is_query());
+        assert!(!Command::Tx(Radio::Radio1).is_query());
+        assert!(!Command::Event(true).is_query());
+    }
+
+    #[test]
+    fn test_command_text_round_trip() {
+        let commands = [
+            Command::Tx(Radio::Radio2),
+            Command::Rx(Radio::Radio1, RxMode::Mono),
+            Command::Rx(Radio::Radio2, RxMode::ReverseStereo),
+            Command::Aux { port: 1, value: 0 },
+            Command::Aux {
+                port: 2,
+                value: 1000,
+            },
+            Command::Keying(Radio::Radio1),
+            Command::Event(true),
+            Command::QueryName,
+            Command::QueryVersion,
+            Command::QueryTx,
+            Command::QueryRx,
+            Command::QueryAux(3),
+            Command::QueryKeying,
+            Command::QueryEvent,
+            Command::QueryPtt,
+            Command::Raw("LED1".into()),
+        ];
+        for command in commands {
+            let text = command.to_string();
+            assert_eq!(format!("{text}\r").into_bytes(), command.encode().unwrap());
+            assert_eq!(text.parse::<Command>().unwrap(), command, "{text}");
+        }
+        assert_eq!(
+            "AUX14\r".parse::<Command>().unwrap(),
+            Command::Aux { port: 1, value: 4 }
+        );
+        for raw in ["TX3", "RX1X", "AUX1", "AUX1+5", "?AUX", "EVENT2"] {
+            assert_eq!(raw.parse::<Command>().unwrap(), Command::Raw(raw.into()));
+        }
+        assert!("TX1\rTX2".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn test_encode_batch() {
+        let batch = [
+            Command::Tx(Radio::Radio2),
+            Command::Rx(Radio::Radio2, RxMode::Stereo),
+            Command::Aux { port: 1, value: 5 },
+        ];
+        assert_eq!(encode_batch(&batch).unwrap(), b"TX2\rRX2S\rAUX15\r");
+        assert_eq!(encode_batch(&[]).unwrap(), b"");
+        assert!(encode_batch(&[Command::Tx(Radio::Radio1), Command::QueryAux(10)]).is_err());
+    }
+
+    #[test]
+    fn test_cr_keying() {
+        assert_eq!(encode_cr(Radio::Radio1), b"CR1\r");
+        assert_eq!(encode_cr(Radio::Radio2), b"CR2\r");
+        assert_eq!(Command::Keying(Radio::Radio2).encode().unwrap(), b"CR2\r");
+        assert_eq!(encode_query_cr(), b"?CR\r");
+        assert_eq!(parse_cr_response(b"CR1\r").unwrap(), Radio::Radio1);
+        assert_eq!(parse_cr_response(b"CR2\r\n").unwrap(), Radio::Radio2);
+        assert!(parse_cr_response(b"CR0\r").is_err());
+        assert!(parse_cr_response(b"TX1\r").is_err());
+    }
+
+    #[test]
+    fn test_rx_query() {
+        assert_eq!(encode_query_rx(), b"?RX\r");
+        assert_eq!(
+            parse_rx_response(b"RX1\r").unwrap(),
+            (Radio::Radio1, RxMode::Mono)
+        );
+        assert_eq!(
+            parse_rx_response(b"RX2S\r").unwrap(),
+            (Radio::Radio2, RxMode::Stereo)
+        );
+        assert_eq!(
+            parse_rx_response(b"RX1R\r\n").unwrap(),
+            (Radio::Radio1, RxMode::ReverseStereo)
+        );
+        assert!(parse_rx_response(b"RX1X\r").is_err());
+        assert!(parse_rx_response(b"RX\r").is_err());
+        assert!(parse_rx_response(b"TX1\r").is_err());
+    }
+
+    #[test]
+    fn test_version_query() {
+        assert_eq!(encode_query_version(), b"?VERSION\r");
+        assert_eq!(parse_version_response(b"VERSION1.4.2\r"), "1.4.2");
+        assert_eq!(parse_version_response(b"VERSION 2.0b\r\n"), "2.0b");
+        assert_eq!(parse_version_response(b"3.1\r"), "3.1");
+    }
+
     #[test]
     fn test_parse_aux_response() {
         assert_eq!(parse_aux_response(b"AUX14\r").unwrap(), (1, 4));
         assert_eq!(parse_aux_response(b"AUX2255\r\n").unwrap(), (2, 255));
         assert_eq!(parse_aux_response(b"AUX00\r").unwrap(), (0, 0));
+        assert_eq!(parse_aux_response(b"AUX11000\r").unwrap(), (1, 1000));
+    }
+
+    #[test]
+    fn test_non_utf8_bytes_reported_raw() {
+        let err = parse_aux_response(b"AUX1\xff\r").unwrap_err().to_string();
+        assert!(err.contains("\\xff"), "{err}");
+        let err = parse_tx_response(b"\x80TX1\r").unwrap_err().to_string();
+        assert!(err.contains("\\x80TX1"), "{err}");
+        assert_eq!(
+            parse_response(b"\xfe\xff\r").unwrap(),
+            Response::Unknown(b"\xfe\xff".to_vec())
+        );
+        assert_eq!(parse_name_response(b"NAMEBox\xe9\r"), "Box\u{fffd}");
     }
 
     #[test]
@@ -192,5 +1303,6 @@ mod tests {
         assert!(parse_aux_response(b"NOTAUX\r").is_err());
         assert!(parse_aux_response(b"AUX\r").is_err());
         assert!(parse_aux_response(b"AUXabc\r").is_err());
+        assert!(parse_aux_response(b"AUX170000\r").is_err());
     }
 }
@@ -0,0 +1,34 @@
+//! The request queue between a device and its IO task.
+//!
+//! Commands wait in a bounded queue while the IO task sends them one at a
+//! time. [`OtrspBuilder::queue_capacity()`](crate::OtrspBuilder::queue_capacity)
+//! sizes it, and [`QueuePolicy`] decides what a new command does when a
+//! burst has filled it. [`OtrspBuilder::routing_deadline()`](crate::OtrspBuilder::routing_deadline)
+//! drops RX updates that went stale while a newer one waited behind them.
+//! [`OtrspDevice::pending_commands()`](crate::OtrspDevice::pending_commands)
+//! shows how far the queue has backed up.
+
+use std::time::Duration;
+
+/// What a command does when the request queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum QueuePolicy {
+    /// Wait for room in the queue.
+    #[default]
+    Block,
+    /// Fail right away with [`Error::Busy`](crate::Error::Busy).
+    FailFast,
+    /// Drop the oldest queued RX routing update, which the new one makes
+    /// redundant, then wait for room. Other commands wait as with `Block`.
+    DropOldest,
+}
+
+/// Commands submitted but not yet completed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PendingCommands {
+    /// Commands waiting in the queue or for their answer.
+    pub count: usize,
+    /// How long the oldest of them has been waiting.
+    pub oldest: Option<Duration>,
+}
@@ -0,0 +1,241 @@
+//! A transport that reopens its link after IO errors.
+//!
+//! USB serial adapters drop off the bus when a cable is knocked or a hub
+//! browns out, and TCP bridges reboot. [`ReconnectingPort`] wraps the link
+//! opened by a [`Connector`] and, when a read or write fails, reconnects
+//! through the same connector and carries on. Reads and writes wait while the
+//! link is down, so the IO task above never sees the glitch; commands sent
+//! meanwhile time out as usual. Enable it with
+//! [`OtrspBuilder::reconnect()`](crate::OtrspBuilder::reconnect).
+//!
+//! A link can also look healthy while the device behind it has hung; a
+//! [`ResetHandle`] lets whoever notices force a reconnect.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker, ready};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+use crate::error::Result;
+use crate::event::SwitchEvent;
+use crate::transport::{BoxedTransport, Connector};
+
+type Connecting = Pin<Box<dyn Future<Output = Result<BoxedTransport>> + Send>>;
+
+enum Link {
+    Up(BoxedTransport),
+    Down(Connecting),
+    /// Reconnecting gave up; the error is reported on every call.
+    Failed(String),
+}
+
+/// A port that transparently reopens its link through a [`Connector`].
+///
+/// Emits [`SwitchEvent::Reconnecting`] when the link drops and
+/// [`SwitchEvent::Reconnected`] once it is back.
+pub struct ReconnectingPort {
+    connector: Arc<dyn Connector>,
+    link: Link,
+    events: Option<broadcast::Sender<SwitchEvent>>,
+    retry_delay: Duration,
+    max_attempts: Option<u32>,
+    reset: ResetHandle,
+}
+
+/// Forces a [`ReconnectingPort`] to drop its link and reconnect.
+#[derive(Debug, Clone, Default)]
+pub struct ResetHandle(Arc<ResetState>);
+
+#[derive(Debug, Default)]
+struct ResetState {
+    requested: AtomicBool,
+    /// The port's pending read, woken so the reset takes effect at once.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl ResetHandle {
+    /// Drop the link and reconnect, e.g. because the device stopped answering.
+    pub fn reset(&self) {
+        self.0.requested.store(true, Ordering::Release);
+        if let Some(waker) = self.0.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    fn take_request(&self) -> bool {
+        self.0.requested.swap(false, Ordering::AcqRel)
+    }
+}
+
+impl ReconnectingPort {
+    /// Open the first link through `connector`.
+    pub async fn connect(connector: Arc<dyn Connector>) -> Result<Self> {
+        let link = connector.connect().await?;
+        Ok(Self::new(connector, link))
+    }
+
+    /// Wrap a link already opened through `connector`.
+    pub fn new(connector: Arc<dyn Connector>, link: BoxedTransport) -> Self {
+        Self {
+            connector,
+            link: Link::Up(link),
+            events: None,
+            retry_delay: Duration::from_secs(1),
+            max_attempts: None,
+            reset: ResetHandle::default(),
+        }
+    }
+
+    /// Send reconnection events on `events`, e.g. a device's event channel.
+    pub fn with_events(mut self, events: broadcast::Sender<SwitchEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Time to wait before each reconnection attempt (default: 1s).
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Give up after this many failed attempts in a row (default: never).
+    ///
+    /// Once given up, every read and write fails with the last error.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// A handle that forces this port to reconnect.
+    pub fn reset_handle(&self) -> ResetHandle {
+        self.reset.clone()
+    }
+
+    /// Whether the link is currently up.
+    pub fn is_connected(&self) -> bool {
+        matches!(self.link, Link::Up(_))
+    }
+
+    /// Drop the current link and start reconnecting.
+    fn link_lost(&mut self, reason: &str) {
+        warn!("link lost ({reason}), reconnecting");
+        self.emit(SwitchEvent::Reconnecting);
+        let connector = self.connector.clone();
+        let delay = self.retry_delay;
+        let max_attempts = self.max_attempts;
+        self.link = Link::Down(Box::pin(async move {
+            let mut attempt = 0;
+            loop {
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                match connector.connect().await {
+                    Ok(link) => return Ok(link),
+                    Err(e) if max_attempts.is_none_or(|max| attempt < max) => {
+                        debug!(attempt, "reconnect failed: {e}");
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }));
+    }
+
+    /// Wait until the link is up.
+    fn poll_link(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<&mut BoxedTransport>> {
+        if self.reset.take_request() && self.is_connected() {
+            self.link_lost("reset requested");
+        }
+        if let Link::Down(connecting) = &mut self.link {
+            match ready!(connecting.as_mut().poll(cx)) {
+                Ok(link) => {
+                    info!("link re-established");
+                    self.link = Link::Up(link);
+                    self.emit(SwitchEvent::Reconnected);
+                }
+                Err(e) => {
+                    warn!("giving up reconnecting: {e}");
+                    self.link = Link::Failed(e.to_string());
+                }
+            }
+        }
+        match &mut self.link {
+            Link::Up(link) => Poll::Ready(Ok(link)),
+            Link::Failed(reason) => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                format!("reconnect failed: {reason}"),
+            ))),
+            Link::Down(_) => unreachable!("link polled to completion above"),
+        }
+    }
+
+    fn emit(&self, event: SwitchEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+}
+
+impl AsyncRead for ReconnectingPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        *this.reset.0.waker.lock().unwrap() = Some(cx.waker().clone());
+        loop {
+            let link = ready!(this.poll_link(cx))?;
+            let before = buf.filled().len();
+            match ready!(Pin::new(link).poll_read(cx, buf)) {
+                Err(e) => this.link_lost(&e.to_string()),
+                // End of stream: the far end closed the connection.
+                Ok(()) if buf.filled().len() == before && buf.remaining() > 0 => {
+                    this.link_lost("closed by peer");
+                }
+                Ok(()) => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for ReconnectingPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            let link = ready!(this.poll_link(cx))?;
+            match ready!(Pin::new(link).poll_write(cx, buf)) {
+                Err(e) => this.link_lost(&e.to_string()),
+                Ok(0) if !buf.is_empty() => this.link_lost("closed by peer"),
+                Ok(n) => return Poll::Ready(Ok(n)),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            let link = ready!(this.poll_link(cx))?;
+            match ready!(Pin::new(link).poll_flush(cx)) {
+                Err(e) => this.link_lost(&e.to_string()),
+                Ok(()) => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().link {
+            Link::Up(link) => Pin::new(link).poll_shutdown(cx),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! RFC 2217 (Telnet COM port control) client transport.
+//!
+//! Networked serial servers that speak RFC 2217 (ser2net in `telnet`
+//! mode, Moxa NPort, ESP-Link) let the client set the baud rate and modem
+//! lines of the far-end port, not just stream bytes. [`Rfc2217Port`]
+//! negotiates the COM-PORT-OPTION on connect, applies [`Rfc2217Settings`],
+//! and then behaves as a plain byte stream: Telnet commands from the server
+//! are answered or dropped, and `0xFF` data bytes are escaped both ways.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+use tracing::{debug, trace};
+
+use crate::error::Result;
+use crate::transport::{self, BoxedTransport, Connector};
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const OPT_BINARY: u8 = 0;
+const OPT_SGA: u8 = 3;
+const OPT_COM_PORT: u8 = 44;
+
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const SET_CONTROL: u8 = 5;
+
+const PARITY_NONE: u8 = 1;
+const STOPSIZE_1: u8 = 1;
+const CONTROL_NO_FLOW: u8 = 1;
+const CONTROL_DTR_ON: u8 = 8;
+const CONTROL_DTR_OFF: u8 = 9;
+const CONTROL_RTS_ON: u8 = 11;
+const CONTROL_RTS_OFF: u8 = 12;
+
+/// Options this client offers to perform (answers `DO` with `WILL`).
+const LOCAL_OPTIONS: [u8; 2] = [OPT_BINARY, OPT_COM_PORT];
+/// Options this client asks the server to perform (answers `WILL` with `DO`).
+const REMOTE_OPTIONS: [u8; 2] = [OPT_BINARY, OPT_SGA];
+
+/// Far-end serial settings applied on connect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rfc2217Settings {
+    /// Baud rate (default: 9600).
+    pub baud_rate: u32,
+    /// DTR line state (default: low, per the OTRSP spec).
+    pub dtr: bool,
+    /// RTS line state (default: low, per the OTRSP spec).
+    pub rts: bool,
+}
+
+impl Default for Rfc2217Settings {
+    fn default() -> Self {
+        Self {
+            baud_rate: 9600,
+            dtr: false,
+            rts: false,
+        }
+    }
+}
+
+/// Connects with [`Rfc2217Port::connect()`].
+#[derive(Debug, Clone)]
+pub struct Rfc2217Connector {
+    host: String,
+    port: u16,
+    settings: Rfc2217Settings,
+}
+
+impl Rfc2217Connector {
+    /// Connect to `port` on `host` and apply `settings`.
+    pub fn new(host: &str, port: u16, settings: Rfc2217Settings) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            settings,
+        }
+    }
+}
+
+#[async_trait]
+impl Connector for Rfc2217Connector {
+    async fn connect(&self) -> Result<BoxedTransport> {
+        let port = Rfc2217Port::connect(&self.host, self.port, self.settings).await?;
+        Ok(Box::new(port))
+    }
+}
+
+/// Telnet parser state for bytes read from the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReadState {
+    Data,
+    Iac,
+    Verb(u8),
+    Sub,
+    SubIac,
+}
+
+/// A byte stream to a serial port behind an RFC 2217 server.
+///
+/// Works over any stream, so it can be layered on TLS as well as TCP.
+#[derive(Debug)]
+pub struct Rfc2217Port<S = TcpStream> {
+    inner: S,
+    state: ReadState,
+    /// Escaped data and Telnet replies not yet accepted by `inner`.
+    out: Vec<u8>,
+    /// Options currently enabled on our side / requested of the server.
+    local: Vec<u8>,
+    remote: Vec<u8>,
+}
+
+impl Rfc2217Port<TcpStream> {
+    /// Connect to `host:port` and configure the far-end port.
+    pub async fn connect(host: &str, port: u16, settings: Rfc2217Settings) -> Result<Self> {
+        let stream = transport::open_tcp(&transport::tcp_addr(host, port)).await?;
+        Self::new(stream, settings).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Rfc2217Port<S> {
+    /// Negotiate COM port control over an open stream and apply `settings`.
+    pub async fn new(inner: S, settings: Rfc2217Settings) -> Result<Self> {
+        let mut port = Self {
+            inner,
+            state: ReadState::Data,
+            out: Vec::new(),
+            local: LOCAL_OPTIONS.to_vec(),
+            remote: REMOTE_OPTIONS.to_vec(),
+        };
+        let mut setup = Vec::new();
+        for opt in LOCAL_OPTIONS {
+            setup.extend([IAC, WILL, opt]);
+        }
+        for opt in REMOTE_OPTIONS {
+            setup.extend([IAC, DO, opt]);
+        }
+        setup.extend(com_port_command(
+            SET_BAUDRATE,
+            &settings.baud_rate.to_be_bytes(),
+        ));
+        setup.extend(com_port_command(SET_DATASIZE, &[8]));
+        setup.extend(com_port_command(SET_PARITY, &[PARITY_NONE]));
+        setup.extend(com_port_command(SET_STOPSIZE, &[STOPSIZE_1]));
+        setup.extend(com_port_command(SET_CONTROL, &[CONTROL_NO_FLOW]));
+        let dtr = if settings.dtr {
+            CONTROL_DTR_ON
+        } else {
+            CONTROL_DTR_OFF
+        };
+        setup.extend(com_port_command(SET_CONTROL, &[dtr]));
+        let rts = if settings.rts {
+            CONTROL_RTS_ON
+        } else {
+            CONTROL_RTS_OFF
+        };
+        setup.extend(com_port_command(SET_CONTROL, &[rts]));
+        debug!(?settings, "configuring RFC 2217 port");
+        port.inner.write_all(&setup).await?;
+        port.inner.flush().await?;
+        Ok(port)
+    }
+
+    /// Handle one byte from the server; returns it if it is data.
+    fn receive(&mut self, byte: u8) -> Option<u8> {
+        match (self.state, byte) {
+            (ReadState::Data, IAC) => self.state = ReadState::Iac,
+            (ReadState::Data, _) => return Some(byte),
+            (ReadState::Iac, IAC) => {
+                self.state = ReadState::Data;
+                return Some(IAC);
+            }
+            (ReadState::Iac, DO | DONT | WILL | WONT) => self.state = ReadState::Verb(byte),
+            (ReadState::Iac, SB) => self.state = ReadState::Sub,
+            (ReadState::Iac, _) => self.state = ReadState::Data,
+            (ReadState::Verb(verb), opt) => {
+                self.negotiate(verb, opt);
+                self.state = ReadState::Data;
+            }
+            // Subnegotiations are the server's acknowledgements of our COM
+            // port settings; nothing depends on them.
+            (ReadState::Sub, IAC) => self.state = ReadState::SubIac,
+            (ReadState::Sub, _) => {}
+            (ReadState::SubIac, SE) => self.state = ReadState::Data,
+            (ReadState::SubIac, _) => self.state = ReadState::Sub,
+        }
+        None
+    }
+
+    /// Answer an option request, acknowledging only changes of state.
+    fn negotiate(&mut self, verb: u8, opt: u8) {
+        trace!(verb, opt, "telnet negotiation");
+        let (enabled, supported, yes, no) = match verb {
+            DO | DONT => (&mut self.local, LOCAL_OPTIONS.contains(&opt), WILL, WONT),
+            _ => (&mut self.remote, REMOTE_OPTIONS.contains(&opt), DO, DONT),
+        };
+        let is_enabled = enabled.contains(&opt);
+        let reply = match verb {
+            DO | WILL if is_enabled => None,
+            DO | WILL if supported => {
+                enabled.push(opt);
+                Some(yes)
+            }
+            DO | WILL => Some(no),
+            _ if is_enabled => {
+                enabled.retain(|&o| o != opt);
+                Some(no)
+            }
+            _ => None,
+        };
+        if let Some(reply) = reply {
+            self.out.extend([IAC, reply, opt]);
+        }
+    }
+
+    /// Write out everything queued in `out`.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.out.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.out))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.out.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// `IAC SB COM-PORT-OPTION <command> <value> IAC SE`, with `IAC` escaped.
+fn com_port_command(command: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![IAC, SB, OPT_COM_PORT, command];
+    out.extend(escape(value));
+    out.extend([IAC, SE]);
+    out
+}
+
+/// Double every `IAC` byte in outgoing data.
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for &byte in data {
+        out.push(byte);
+        if byte == IAC {
+            out.push(IAC);
+        }
+    }
+    out
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for Rfc2217Port<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            // Negotiation replies go out whenever the stream is polled.
+            if let Poll::Ready(Err(e)) = this.poll_drain(cx) {
+                return Poll::Ready(Err(e));
+            }
+            let mut raw = [0u8; 256];
+            let space = buf.remaining().min(raw.len());
+            let mut raw_buf = ReadBuf::new(&mut raw[..space]);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut raw_buf))?;
+            let raw = raw_buf.filled();
+            if raw.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            let before = buf.filled().len();
+            for &byte in raw {
+                if let Some(data) = this.receive(byte) {
+                    buf.put_slice(&[data]);
+                }
+            }
+            // A read made only of Telnet commands yields no data; keep going
+            // rather than report end of stream.
+            if buf.filled().len() > before {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for Rfc2217Port<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        this.out.extend(escape(buf));
+        // Whatever `inner` does not take now is written by the next flush.
+        if let Poll::Ready(Err(e)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockPort;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_setup_and_escaping() {
+        let mock = MockPort::new();
+        let settings = Rfc2217Settings {
+            baud_rate: 19200,
+            ..Default::default()
+        };
+        let mut port = Rfc2217Port::new(mock.clone(), settings).await.unwrap();
+        let setup = mock.written_data();
+        assert!(setup.starts_with(&[IAC, WILL, OPT_BINARY, IAC, WILL, OPT_COM_PORT]));
+        let baud = [IAC, SB, OPT_COM_PORT, SET_BAUDRATE, 0, 0, 0x4B, 0, IAC, SE];
+        assert!(setup.windows(baud.len()).any(|w| w == baud));
+        let dtr_off = [IAC, SB, OPT_COM_PORT, SET_CONTROL, CONTROL_DTR_OFF, IAC, SE];
+        assert!(setup.windows(dtr_off.len()).any(|w| w == dtr_off));
+
+        port.write_all(b"A\xFFB").await.unwrap();
+        port.flush().await.unwrap();
+        assert!(mock.written_data().ends_with(b"A\xFF\xFFB"));
+    }
+
+    #[tokio::test]
+    async fn test_read_strips_telnet_commands() {
+        let mock = MockPort::new();
+        let mut port = Rfc2217Port::new(mock.clone(), Rfc2217Settings::default())
+            .await
+            .unwrap();
+        let setup_len = mock.written_data().len();
+
+        // An acknowledgement, a COM port reply, an unsupported request and
+        // an escaped data byte around the actual data.
+        mock.queue_read(&[IAC, DO, OPT_COM_PORT]);
+        mock.queue_read(&[IAC, SB, OPT_COM_PORT, 101, 0, 0, 0x25, 0x80, IAC, SE]);
+        mock.queue_read(&[IAC, DO, 1]);
+        mock.queue_read(b"TX1\xFF\xFF\r");
+        let mut data = [0u8; 5];
+        port.read_exact(&mut data).await.unwrap();
+        assert_eq!(&data, b"TX1\xFF\r");
+
+        port.flush().await.unwrap();
+        assert_eq!(&mock.written_data()[setup_len..], &[IAC, WONT, 1]);
+    }
+}
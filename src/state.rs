@@ -0,0 +1,154 @@
+//! Cached switch state as last commanded or reported.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+
+use crate::event::{Origin, SwitchEvent};
+use crate::protocol::Command;
+use crate::types::{Radio, RxMode};
+
+/// Snapshot of the switch routing known to the library.
+///
+/// Fields are `None`/absent until the corresponding command has been sent
+/// (or the state reported by the device), since OTRSP devices do not
+/// announce their power-on state.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SwitchState {
+    /// Device name from the last `?NAME` response.
+    pub name: Option<String>,
+    /// Radio with transmit focus.
+    pub tx: Option<Radio>,
+    /// Receive audio routing.
+    pub rx: Option<(Radio, RxMode)>,
+    /// AUX output values by port.
+    pub aux: BTreeMap<u8, u16>,
+    /// Radio receiving computer keying (CR routing).
+    pub keying: Option<Radio>,
+    /// Whether unsolicited event reporting has been enabled on the device.
+    pub events: bool,
+    /// Whether the link to the device is up. Ignored by [`diff()`](Self::diff).
+    pub connected: bool,
+}
+
+impl SwitchState {
+    /// Record a state command the host sent, returning the event that
+    /// announces it (`None` for commands that change no routing).
+    pub(crate) fn apply(&mut self, command: &Command) -> Option<SwitchEvent> {
+        let origin = Origin::Host;
+        match *command {
+            Command::Tx(radio) => {
+                self.tx = Some(radio);
+                Some(SwitchEvent::TxChanged { radio, origin })
+            }
+            Command::Rx(radio, mode) => {
+                self.rx = Some((radio, mode));
+                Some(SwitchEvent::RxChanged {
+                    radio,
+                    mode,
+                    origin,
+                })
+            }
+            Command::Aux { port, value } => {
+                self.aux.insert(port, value);
+                Some(SwitchEvent::AuxChanged {
+                    port,
+                    value,
+                    origin,
+                })
+            }
+            Command::Keying(radio) => {
+                self.keying = Some(radio);
+                Some(SwitchEvent::KeyingChanged { radio, origin })
+            }
+            _ => None,
+        }
+    }
+
+    /// Put back a state command that could not be sent, unless a newer
+    /// command for the same output has been recorded since.
+    pub(crate) fn restore(&mut self, command: &Command) {
+        match *command {
+            Command::Tx(radio) => {
+                self.tx.get_or_insert(radio);
+            }
+            Command::Rx(radio, mode) => {
+                self.rx.get_or_insert((radio, mode));
+            }
+            Command::Aux { port, value } => {
+                self.aux.entry(port).or_insert(value);
+            }
+            Command::Keying(radio) => {
+                self.keying.get_or_insert(radio);
+            }
+            _ => {}
+        }
+    }
+
+    /// Commands that take a switch from `current` to `target`.
+    ///
+    /// Only fields set in `target` are considered, and only those that differ
+    /// from `current` produce a command. Commands are ordered TX, RX, AUX by
+    /// port, then keying routing. Diffing against [`SwitchState::default()`] yields the full
+    /// set needed to restore `target` on a device in an unknown state.
+    pub fn diff(current: &SwitchState, target: &SwitchState) -> Vec<Command> {
+        let mut commands = Vec::new();
+        if let Some(radio) = target.tx
+            && current.tx != target.tx
+        {
+            commands.push(Command::Tx(radio));
+        }
+        if let Some((radio, mode)) = target.rx
+            && current.rx != target.rx
+        {
+            commands.push(Command::Rx(radio, mode));
+        }
+        for (&port, &value) in &target.aux {
+            if current.aux.get(&port) != Some(&value) {
+                commands.push(Command::Aux { port, value });
+            }
+        }
+        if let Some(radio) = target.keying
+            && current.keying != target.keying
+        {
+            commands.push(Command::Keying(radio));
+        }
+        commands
+    }
+}
+
+/// Publish the state in `state` on a watch channel, refreshed on every event.
+///
+/// Also tracks [`SwitchState::connected`] from the connection events. Runs
+/// until the event channel closes.
+pub(crate) fn spawn_watch(
+    state: Arc<Mutex<SwitchState>>,
+    mut events: broadcast::Receiver<SwitchEvent>,
+) -> watch::Receiver<SwitchState> {
+    let (watch_tx, watch_rx) = watch::channel(state.lock().unwrap().clone());
+    tokio::spawn(async move {
+        loop {
+            let connected = match events.recv().await {
+                Ok(SwitchEvent::Connected | SwitchEvent::Reconnected) => Some(true),
+                Ok(SwitchEvent::Disconnected | SwitchEvent::Reconnecting) => Some(false),
+                Ok(_) | Err(RecvError::Lagged(_)) => None,
+                Err(RecvError::Closed) => break,
+            };
+            let snapshot = {
+                let mut state = state.lock().unwrap();
+                if let Some(connected) = connected {
+                    state.connected = connected;
+                }
+                state.clone()
+            };
+            watch_tx.send_if_modified(|current| {
+                let changed = *current != snapshot;
+                *current = snapshot;
+                changed
+            });
+        }
+    });
+    watch_rx
+}
@@ -0,0 +1,138 @@
+//! Link activity counters and latency metrics.
+//!
+//! Every device counts the bytes and commands crossing its link, readable
+//! with [`OtrspDevice::stats()`](crate::OtrspDevice::stats), so a dashboard
+//! can show link activity without a wire tap. The IO task also times each
+//! exchange; [`OtrspDevice::metrics()`](crate::OtrspDevice::metrics) shows
+//! how a remote link holds up over a contest weekend.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Counts of link activity since the device was built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    /// Bytes written to the device.
+    pub bytes_sent: u64,
+    /// Bytes read from the device, including stale and unsolicited lines.
+    pub bytes_received: u64,
+    /// Commands sent, queries included.
+    pub commands: u64,
+    /// Commands that expected an answer.
+    pub queries: u64,
+    /// Commands that failed, for any reason.
+    pub errors: u64,
+    /// Failed commands that timed out.
+    pub timeouts: u64,
+}
+
+/// Live counters shared by the IO handle and the port wrapper.
+#[derive(Debug, Default)]
+pub(crate) struct StatsCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    commands: AtomicU64,
+    queries: AtomicU64,
+    errors: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+impl StatsCounters {
+    pub(crate) fn sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count a completed command and its outcome.
+    pub(crate) fn command<T>(&self, query: bool, result: &Result<T, Error>) {
+        self.commands.fetch_add(1, Ordering::Relaxed);
+        if query {
+            self.queries.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Err(e) = result {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            if e.is_timeout() {
+                self.timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> LinkStats {
+        LinkStats {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            commands: self.commands.load(Ordering::Relaxed),
+            queries: self.queries.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Latency of one kind of exchange.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Exchanges timed.
+    pub count: u64,
+    /// Sum of their latencies.
+    pub total: Duration,
+    /// Fastest exchange.
+    pub min: Option<Duration>,
+    /// Slowest exchange.
+    pub max: Option<Duration>,
+    /// Most recent exchange.
+    pub last: Option<Duration>,
+}
+
+impl LatencyStats {
+    /// Average latency, if anything was timed.
+    pub fn mean(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count).ok().filter(|&n| n > 0)?;
+        Some(self.total / count)
+    }
+
+    fn record(&mut self, latency: Duration) {
+        self.count += 1;
+        self.total += latency;
+        self.min = Some(self.min.map_or(latency, |min| min.min(latency)));
+        self.max = Some(self.max.map_or(latency, |max| max.max(latency)));
+        self.last = Some(latency);
+    }
+}
+
+/// Outcome and latency of the exchanges the IO task has made.
+///
+/// Keepalive probes count as queries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkMetrics {
+    /// Exchanges that completed.
+    pub succeeded: u64,
+    /// Exchanges that failed to write, timed out or lost the link.
+    pub failed: u64,
+    /// Time to put a command without an answer on the wire.
+    pub writes: LatencyStats,
+    /// Round trip from writing a query to reading its answer.
+    pub queries: LatencyStats,
+}
+
+impl LinkMetrics {
+    pub(crate) fn write_done(&mut self, latency: Duration) {
+        self.succeeded += 1;
+        self.writes.record(latency);
+    }
+
+    pub(crate) fn query_done(&mut self, latency: Duration) {
+        self.succeeded += 1;
+        self.queries.record(latency);
+    }
+
+    pub(crate) fn failed(&mut self) {
+        self.failed += 1;
+    }
+}
@@ -1,9 +1,12 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use async_trait::async_trait;
 use tokio::sync::broadcast;
 
-use crate::error::Result;
-use crate::event::SwitchEvent;
-use crate::types::{Radio, RxMode};
+use crate::error::{Error, Result};
+use crate::event::{FilteredReceiver, SwitchEvent};
+use crate::protocol::{Command, DeviceIdentity};
+use crate::types::{AudioRoute, Radio, RxMode};
 
 /// Information about a connected SO2R switch device.
 pub struct SwitchInfo {
@@ -11,22 +14,157 @@ pub struct SwitchInfo {
     pub name: String,
     /// Serial port path, if connected via serial.
     pub port: Option<String>,
+    /// Serial baud rate in use, if connected via a real serial port.
+    pub baud_rate: Option<u32>,
+    /// Firmware version from the `?VERSION` query, if requested and answered.
+    pub firmware: Option<String>,
+    /// Device family parsed from the `?NAME` answer, if queried and answered.
+    pub identity: Option<DeviceIdentity>,
 }
 
 /// Capabilities of the SO2R switch device.
+#[derive(Debug, Clone)]
 pub struct SwitchCapabilities {
     /// Whether the device supports stereo RX mode.
     pub stereo: bool,
     /// Whether the device supports reverse stereo RX mode.
     pub reverse_stereo: bool,
-    /// Number of AUX ports (typically 2).
+    /// Number of AUX ports (typically 2), numbered from 1.
     pub aux_ports: u8,
+    /// Largest value the device's AUX outputs accept.
+    ///
+    /// Raise above 255 for band-decoder firmware that takes wide values via
+    /// [`So2rSwitch::set_aux_wide()`].
+    pub aux_value_max: u16,
+    /// Whether the device can sum both radios into both ears ([`RxMode::Mixed`]).
+    pub mixed: bool,
+    /// Narrower value constraints for individual AUX ports, keyed by port.
+    ///
+    /// Declare what is wired to each port (e.g. a BCD band decoder taking
+    /// codes 0-10) so wiring mistakes fail before anything is sent.
+    pub aux_limits: BTreeMap<u8, AuxLimit>,
+}
+
+impl SwitchCapabilities {
+    /// Whether the device supports the given RX mode.
+    pub fn supports_rx(&self, mode: RxMode) -> bool {
+        match mode {
+            RxMode::Mono => true,
+            RxMode::Stereo => self.stereo,
+            RxMode::ReverseStereo => self.reverse_stereo,
+            RxMode::Mixed => self.mixed,
+        }
+    }
+
+    /// Constrain the values accepted by AUX `port`.
+    pub fn with_aux_limit(mut self, port: u8, limit: AuxLimit) -> Self {
+        self.aux_limits.insert(port, limit);
+        self
+    }
+
+    /// Check an AUX value against [`aux_value_max`](Self::aux_value_max) and
+    /// any limit declared for `port`.
+    pub fn check_aux_value(&self, port: u8, value: u16) -> Result<()> {
+        if value > self.aux_value_max {
+            return Err(Error::InvalidParameter(format!(
+                "AUX value {value} exceeds device maximum {}",
+                self.aux_value_max
+            )));
+        }
+        let Some(limit) = self.aux_limits.get(&port) else {
+            return Ok(());
+        };
+        if let Some(max) = limit.max
+            && value > max
+        {
+            return Err(Error::InvalidParameter(format!(
+                "AUX value {value} exceeds port {port} maximum {max}"
+            )));
+        }
+        if let Some(allowed) = &limit.allowed
+            && !allowed.contains(&value)
+        {
+            return Err(Error::InvalidParameter(format!(
+                "AUX value {value} not allowed on port {port}, expected one of {allowed:?}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Values accepted by one AUX port.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuxLimit {
+    /// Largest value the port accepts.
+    pub max: Option<u16>,
+    /// The only values the port accepts.
+    pub allowed: Option<BTreeSet<u16>>,
+}
+
+impl AuxLimit {
+    /// Accept values up to `max`.
+    pub fn max(max: u16) -> Self {
+        Self {
+            max: Some(max),
+            allowed: None,
+        }
+    }
+
+    /// Accept only the listed values.
+    pub fn allowed(values: impl IntoIterator<Item = u16>) -> Self {
+        Self {
+            max: None,
+            allowed: Some(values.into_iter().collect()),
+        }
+    }
+}
+
+impl Default for SwitchCapabilities {
+    fn default() -> Self {
+        Self {
+            stereo: true,
+            reverse_stereo: true,
+            aux_ports: 2,
+            aux_value_max: 255,
+            mixed: false,
+            aux_limits: BTreeMap::new(),
+        }
+    }
+}
+
+/// Protocol extensions detected by post-connect negotiation.
+///
+/// Populated by [`OtrspBuilder::negotiate`](crate::OtrspBuilder::negotiate).
+/// When negotiation is disabled every extension is reported as unsupported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolFeatures {
+    /// Device answers `?EVENT` and can report unsolicited events.
+    pub events: bool,
+    /// Device answers `?PTT` and can report PTT/keying state.
+    pub ptt: bool,
+    /// Number of radios the device can switch.
+    ///
+    /// Always 2, the radios OTRSP can address; no probe reports more.
+    pub radios: u8,
+}
+
+impl Default for ProtocolFeatures {
+    fn default() -> Self {
+        Self {
+            events: false,
+            ptt: false,
+            radios: 2,
+        }
+    }
 }
 
 /// Backend-agnostic trait for SO2R switch control.
 ///
 /// Implemented by [`OtrspDevice`](crate::OtrspDevice) for serial OTRSP devices.
 /// Future backends (microHAM, FlexRadio) can implement this trait as well.
+///
+/// Also implemented for `&T`, `Box<T>` and `Arc<T>` where `T: So2rSwitch`,
+/// so shared handles can be passed wherever an `impl So2rSwitch` is expected.
 #[async_trait]
 pub trait So2rSwitch: Send + Sync {
     /// Get device info.
@@ -41,21 +179,294 @@ pub trait So2rSwitch: Send + Sync {
     /// Set receive audio routing.
     async fn set_rx(&self, radio: Radio, mode: RxMode) -> Result<()>;
 
+    /// The radio without transmit focus.
+    ///
+    /// The default implementation asks the device with
+    /// [`query_tx()`](So2rSwitch::query_tx).
+    async fn other_radio(&self) -> Result<Radio> {
+        Ok(self.query_tx().await?.other())
+    }
+
+    /// Move transmit focus to the other radio, returning the radio now
+    /// holding it.
+    async fn toggle_tx(&self) -> Result<Radio> {
+        let radio = self.other_radio().await?;
+        self.set_tx(radio).await?;
+        Ok(radio)
+    }
+
+    /// Move transmit focus to the other radio and RX audio along with it,
+    /// keeping the RX mode. Returns the radio now holding focus.
+    ///
+    /// The default implementation reads the RX mode with
+    /// [`query_rx()`](So2rSwitch::query_rx), falling back to mono when the
+    /// backend cannot answer.
+    async fn swap(&self) -> Result<Radio> {
+        let mode = match self.query_rx().await {
+            Ok((_, mode)) => mode,
+            Err(e) if e.is_unsupported() => RxMode::Mono,
+            Err(e) => return Err(e),
+        };
+        let radio = self.toggle_tx().await?;
+        self.set_rx(radio, mode).await?;
+        Ok(radio)
+    }
+
+    /// Set headphone audio from an operator-level [`AudioRoute`].
+    ///
+    /// Translates to the best RX command allowed by [`capabilities()`](So2rSwitch::capabilities).
+    async fn set_audio(&self, route: AudioRoute) -> Result<()> {
+        let (radio, mode) = route.rx_command(self.capabilities());
+        self.set_rx(radio, mode).await
+    }
+
     /// Set an auxiliary BCD output value (band decoder).
     async fn set_aux(&self, port: u8, value: u8) -> Result<()>;
 
+    /// Set an AUX output to a value that may exceed 255.
+    ///
+    /// Values above [`SwitchCapabilities::aux_value_max`] are rejected with
+    /// [`Error::InvalidParameter`](crate::Error::InvalidParameter) before
+    /// anything is sent. The default implementation forwards values that fit
+    /// in a `u8` to [`set_aux()`](So2rSwitch::set_aux) and returns
+    /// [`Error::Unsupported`](crate::Error::Unsupported) for the rest.
+    async fn set_aux_wide(&self, port: u8, value: u16) -> Result<()> {
+        match u8::try_from(value) {
+            Ok(value) => self.set_aux(port, value).await,
+            Err(_) => Err(Error::Unsupported(
+                "wide AUX values not supported by this backend".into(),
+            )),
+        }
+    }
+
+    /// Apply one state command from [`SwitchState::diff()`](crate::SwitchState::diff).
+    ///
+    /// Used to replay saved routing, e.g. onto a failover backup. Only the
+    /// field the command names changes, so conveniences such as
+    /// [`rx_follows_tx`](crate::OtrspBuilder::rx_follows_tx) are skipped.
+    /// Commands that are not TX, RX, AUX or keying routing fail with
+    /// [`Error::InvalidParameter`](crate::Error::InvalidParameter).
+    async fn apply_command(&self, command: &Command) -> Result<()> {
+        match *command {
+            Command::Tx(radio) => self.set_tx(radio).await,
+            Command::Rx(radio, mode) => self.set_rx(radio, mode).await,
+            Command::Aux { port, value } => self.set_aux_wide(port, value).await,
+            Command::Keying(radio) => self.set_keying(radio).await,
+            _ => Err(Error::InvalidParameter(format!(
+                "{command:?} is not a state command"
+            ))),
+        }
+    }
+
     /// Query the device name.
     async fn device_name(&self) -> Result<String>;
 
     /// Query the current value of an auxiliary port.
     async fn query_aux(&self, port: u8) -> Result<u8>;
 
+    /// Query an AUX output that may hold a value above 255, as set with
+    /// [`set_aux_wide()`](So2rSwitch::set_aux_wide).
+    ///
+    /// The default implementation widens [`query_aux()`](So2rSwitch::query_aux).
+    async fn query_aux_wide(&self, port: u8) -> Result<u16> {
+        self.query_aux(port).await.map(u16::from)
+    }
+
+    /// Query the current RX audio routing (`?RX`).
+    ///
+    /// Backends without the query return [`Error::Unsupported`](crate::Error::Unsupported).
+    async fn query_rx(&self) -> Result<(Radio, RxMode)> {
+        Err(Error::Unsupported(
+            "RX query not supported by this backend".into(),
+        ))
+    }
+
+    /// Query which radio has transmit focus (`?TX`).
+    ///
+    /// Backends without the query return [`Error::Unsupported`](crate::Error::Unsupported).
+    async fn query_tx(&self) -> Result<Radio> {
+        Err(Error::Unsupported(
+            "TX query not supported by this backend".into(),
+        ))
+    }
+
+    /// Route computer keying to a radio (`CRr`), independently of TX focus.
+    ///
+    /// Backends without CR support return [`Error::Unsupported`](crate::Error::Unsupported).
+    async fn set_keying(&self, radio: Radio) -> Result<()> {
+        let _ = radio;
+        Err(Error::Unsupported(
+            "keying routing not supported by this backend".into(),
+        ))
+    }
+
+    /// Query which radio receives computer keying (`?CR`).
+    ///
+    /// Backends without CR support return [`Error::Unsupported`](crate::Error::Unsupported).
+    async fn query_keying(&self) -> Result<Radio> {
+        Err(Error::Unsupported(
+            "keying routing not supported by this backend".into(),
+        ))
+    }
+
     /// Send a raw OTRSP command (CR terminator appended automatically).
+    ///
+    /// Fails with [`Error::InvalidParameter`](crate::Error::InvalidParameter)
+    /// if the command contains control characters or is too long.
     async fn send_raw(&self, command: &str) -> Result<()>;
 
+    /// Send a raw command and return the device's response line, without the
+    /// CR/LF terminator.
+    ///
+    /// Meant for experimenting with vendor-specific `?` queries. Validated
+    /// like [`send_raw()`](So2rSwitch::send_raw). Backends without a
+    /// request/response channel return [`Error::Unsupported`](crate::Error::Unsupported).
+    async fn query_raw(&self, command: &str) -> Result<String> {
+        let _ = command;
+        Err(Error::Unsupported(
+            "raw queries not supported by this backend".into(),
+        ))
+    }
+
     /// Subscribe to switch events.
+    ///
+    /// The receiver only sees events from now on; the initial `Connected`
+    /// is not among them. Use
+    /// [`subscribe_connection()`](So2rSwitch::subscribe_connection) to start
+    /// with the link status, or
+    /// [`OtrspDevice::watch_state()`](crate::OtrspDevice::watch_state) to
+    /// read it at any time.
     fn subscribe(&self) -> broadcast::Receiver<SwitchEvent>;
 
+    /// Subscribe to connection-lifecycle events only, those for which
+    /// [`SwitchEvent::is_connection_event()`] holds.
+    ///
+    /// On an [`OtrspDevice`](crate::OtrspDevice), each receiver starts with
+    /// `Connected` while the link is up.
+    fn subscribe_connection(&self) -> FilteredReceiver {
+        FilteredReceiver::new(self.subscribe(), SwitchEvent::is_connection_event)
+    }
+
+    /// Subscribe to switch state changes only (TX, RX, AUX, keying routing).
+    fn subscribe_state(&self) -> FilteredReceiver {
+        FilteredReceiver::new(self.subscribe(), SwitchEvent::is_state_change)
+    }
+
+    /// Subscribe to events matching a custom filter.
+    fn subscribe_filtered(&self, filter: fn(&SwitchEvent) -> bool) -> FilteredReceiver {
+        FilteredReceiver::new(self.subscribe(), filter)
+    }
+
     /// Close the connection.
     async fn close(&self) -> Result<()>;
 }
+
+/// Forward every [`So2rSwitch`] method through a pointer type.
+macro_rules! forward_so2r_switch {
+    ($($ptr:ty),+) => {$(
+        #[async_trait]
+        impl<T: So2rSwitch + ?Sized> So2rSwitch for $ptr {
+            fn info(&self) -> &SwitchInfo {
+                (**self).info()
+            }
+
+            fn capabilities(&self) -> &SwitchCapabilities {
+                (**self).capabilities()
+            }
+
+            async fn set_tx(&self, radio: Radio) -> Result<()> {
+                (**self).set_tx(radio).await
+            }
+
+            async fn set_rx(&self, radio: Radio, mode: RxMode) -> Result<()> {
+                (**self).set_rx(radio, mode).await
+            }
+
+            async fn other_radio(&self) -> Result<Radio> {
+                (**self).other_radio().await
+            }
+
+            async fn toggle_tx(&self) -> Result<Radio> {
+                (**self).toggle_tx().await
+            }
+
+            async fn swap(&self) -> Result<Radio> {
+                (**self).swap().await
+            }
+
+            async fn set_audio(&self, route: AudioRoute) -> Result<()> {
+                (**self).set_audio(route).await
+            }
+
+            async fn set_aux(&self, port: u8, value: u8) -> Result<()> {
+                (**self).set_aux(port, value).await
+            }
+
+            async fn set_aux_wide(&self, port: u8, value: u16) -> Result<()> {
+                (**self).set_aux_wide(port, value).await
+            }
+
+            async fn apply_command(&self, command: &Command) -> Result<()> {
+                (**self).apply_command(command).await
+            }
+
+            async fn device_name(&self) -> Result<String> {
+                (**self).device_name().await
+            }
+
+            async fn query_aux(&self, port: u8) -> Result<u8> {
+                (**self).query_aux(port).await
+            }
+
+            async fn query_aux_wide(&self, port: u8) -> Result<u16> {
+                (**self).query_aux_wide(port).await
+            }
+
+            async fn query_tx(&self) -> Result<Radio> {
+                (**self).query_tx().await
+            }
+
+            async fn query_rx(&self) -> Result<(Radio, RxMode)> {
+                (**self).query_rx().await
+            }
+
+            async fn set_keying(&self, radio: Radio) -> Result<()> {
+                (**self).set_keying(radio).await
+            }
+
+            async fn query_keying(&self) -> Result<Radio> {
+                (**self).query_keying().await
+            }
+
+            async fn send_raw(&self, command: &str) -> Result<()> {
+                (**self).send_raw(command).await
+            }
+
+            async fn query_raw(&self, command: &str) -> Result<String> {
+                (**self).query_raw(command).await
+            }
+
+            fn subscribe(&self) -> broadcast::Receiver<SwitchEvent> {
+                (**self).subscribe()
+            }
+
+            fn subscribe_connection(&self) -> FilteredReceiver {
+                (**self).subscribe_connection()
+            }
+
+            fn subscribe_state(&self) -> FilteredReceiver {
+                (**self).subscribe_state()
+            }
+
+            fn subscribe_filtered(&self, filter: fn(&SwitchEvent) -> bool) -> FilteredReceiver {
+                (**self).subscribe_filtered(filter)
+            }
+
+            async fn close(&self) -> Result<()> {
+                (**self).close().await
+            }
+        }
+    )+};
+}
+
+forward_so2r_switch!(&T, Box<T>, std::sync::Arc<T>);
@@ -0,0 +1,101 @@
+//! Raw byte tap on the link to the switch.
+//!
+//! [`OtrspBuilder::on_wire()`](crate::OtrspBuilder::on_wire) installs a hook
+//! that sees every byte the IO task writes or reads, before any line
+//! splitting, echo filtering or parsing. Useful for debugging a misbehaving
+//! box in the field without rebuilding with trace logging.
+
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::stats::StatsCounters;
+
+/// Which way bytes crossed the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WireDirection {
+    /// Host to switch.
+    Sent,
+    /// Switch to host.
+    Received,
+}
+
+impl fmt::Display for WireDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireDirection::Sent => write!(f, ">>"),
+            WireDirection::Received => write!(f, "<<"),
+        }
+    }
+}
+
+/// Hook called with each chunk of bytes as it crosses the wire.
+pub(crate) type WireTap = Arc<dyn Fn(WireDirection, &[u8]) + Send + Sync>;
+
+/// A port that counts its traffic and reports it to an optional [`WireTap`].
+pub(crate) struct TapPort<P> {
+    inner: P,
+    tap: Option<WireTap>,
+    stats: Arc<StatsCounters>,
+}
+
+impl<P> TapPort<P> {
+    pub(crate) fn new(inner: P, tap: Option<WireTap>, stats: Arc<StatsCounters>) -> Self {
+        Self { inner, tap, stats }
+    }
+
+    fn report(&self, direction: WireDirection, bytes: &[u8]) {
+        match direction {
+            WireDirection::Sent => self.stats.sent(bytes.len()),
+            WireDirection::Received => self.stats.received(bytes.len()),
+        }
+        if let Some(tap) = &self.tap
+            && !bytes.is_empty()
+        {
+            tap(direction, bytes);
+        }
+    }
+}
+
+impl<P: AsyncRead + Unpin> AsyncRead for TapPort<P> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            this.report(WireDirection::Received, &buf.filled()[before..]);
+        }
+        result
+    }
+}
+
+impl<P: AsyncWrite + Unpin> AsyncWrite for TapPort<P> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            this.report(WireDirection::Sent, &buf[..n]);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
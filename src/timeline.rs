@@ -0,0 +1,289 @@
+//! Event timelines for post-contest analysis.
+//!
+//! A [`Timeline`] is a time-ordered list of switch events, either captured
+//! live by a [`TimelineRecorder`] ring buffer or loaded from an
+//! [`audit`](crate::audit) journal. It can be exported as JSONL or CSV for
+//! correlation with a Cabrillo log, and summarized directly (TX focus time
+//! per radio, TX switches per hour).
+
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+
+use crate::audit::{event_fields, mode_name, now_ms, origin_name, radio_number};
+use crate::error::{Error, Result};
+use crate::event::{Origin, SwitchEvent};
+use crate::types::{Radio, RxMode};
+
+/// One timestamped event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineEntry {
+    /// Milliseconds since the Unix epoch.
+    pub ts_ms: u64,
+    /// The event itself.
+    pub event: SwitchEvent,
+}
+
+/// A time-ordered sequence of switch events.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timeline {
+    entries: Vec<TimelineEntry>,
+}
+
+impl Timeline {
+    /// Build a timeline from entries, sorting them by timestamp.
+    pub fn new(mut entries: Vec<TimelineEntry>) -> Self {
+        entries.sort_by_key(|e| e.ts_ms);
+        Self { entries }
+    }
+
+    /// Load a timeline from an audit journal written by
+    /// [`OtrspBuilder::audit_log()`](crate::OtrspBuilder::audit_log).
+    ///
+    /// Bookkeeping records (`open`, `lagged`) are skipped.
+    pub fn from_journal(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let mut entries = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_journal_line(line) {
+                Some(entry) => entries.extend(entry),
+                None => {
+                    return Err(Error::Protocol(format!(
+                        "malformed journal line {}: {line}",
+                        n + 1
+                    )));
+                }
+            }
+        }
+        Ok(Self::new(entries))
+    }
+
+    /// The entries in timestamp order.
+    pub fn entries(&self) -> &[TimelineEntry] {
+        &self.entries
+    }
+
+    /// Export as JSONL, one object per event (same shape as the audit journal).
+    pub fn to_jsonl(&self) -> String {
+        self.entries
+            .iter()
+            .map(|e| format!("{{\"ts\":{},{}}}\n", e.ts_ms, event_fields(&e.event)))
+            .collect()
+    }
+
+    /// Export as CSV with columns `ts_ms,event,radio,mode,port,value,origin`.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("ts_ms,event,radio,mode,port,value,origin\n");
+        for e in &self.entries {
+            let row = match &e.event {
+                SwitchEvent::TxChanged { radio, origin } => {
+                    format!("tx,{},,,,{}", radio_number(*radio), origin_name(*origin))
+                }
+                SwitchEvent::RxChanged {
+                    radio,
+                    mode,
+                    origin,
+                } => format!(
+                    "rx,{},{},,,{}",
+                    radio_number(*radio),
+                    mode_name(*mode),
+                    origin_name(*origin)
+                ),
+                SwitchEvent::AuxChanged {
+                    port,
+                    value,
+                    origin,
+                } => format!("aux,,,{port},{value},{}", origin_name(*origin)),
+                SwitchEvent::KeyingChanged { radio, origin } => {
+                    format!(
+                        "keying,{},,,,{}",
+                        radio_number(*radio),
+                        origin_name(*origin)
+                    )
+                }
+                SwitchEvent::Connected => "connected,,,,,".to_string(),
+                SwitchEvent::Disconnected => "disconnected,,,,,".to_string(),
+                SwitchEvent::DeviceReset => "device_reset,,,,,".to_string(),
+                SwitchEvent::FootswitchChanged { pressed } => {
+                    format!("footswitch,,,,{},device", u8::from(*pressed))
+                }
+                SwitchEvent::PttChanged { radio } => {
+                    format!("ptt,{},,,,device", radio.map_or(0, radio_number))
+                }
+                SwitchEvent::FailedOver => "failed_over,,,,,".to_string(),
+                SwitchEvent::Reconnecting => "reconnecting,,,,,".to_string(),
+                SwitchEvent::Reconnected => "reconnected,,,,,".to_string(),
+                SwitchEvent::IoTaskFailed => "io_task_failed,,,,,".to_string(),
+            };
+            out.push_str(&format!("{},{row}\n", e.ts_ms));
+        }
+        out
+    }
+
+    /// Total time TX was focused on `radio`.
+    ///
+    /// Each TX change counts until the next one; the last runs until the
+    /// final entry of the timeline.
+    pub fn focus_time(&self, radio: Radio) -> Duration {
+        let end = self.entries.last().map_or(0, |e| e.ts_ms);
+        let mut total = 0;
+        let mut current: Option<(Radio, u64)> = None;
+        for e in &self.entries {
+            if let SwitchEvent::TxChanged { radio: r, .. } = e.event {
+                if let Some((prev, since)) = current
+                    && prev == radio
+                {
+                    total += e.ts_ms - since;
+                }
+                current = Some((r, e.ts_ms));
+            }
+        }
+        if let Some((prev, since)) = current
+            && prev == radio
+        {
+            total += end - since;
+        }
+        Duration::from_millis(total)
+    }
+
+    /// Number of TX switches to a different radio, keyed by the start of
+    /// each hour (seconds since the Unix epoch).
+    pub fn tx_switches_per_hour(&self) -> BTreeMap<u64, u32> {
+        let mut counts = BTreeMap::new();
+        let mut last = None;
+        for e in &self.entries {
+            if let SwitchEvent::TxChanged { radio, .. } = e.event {
+                if last.is_some_and(|prev| prev != radio) {
+                    let hour = e.ts_ms / 1000 / 3600 * 3600;
+                    *counts.entry(hour).or_insert(0) += 1;
+                }
+                last = Some(radio);
+            }
+        }
+        counts
+    }
+}
+
+/// Parse one journal line; `Some(None)` for bookkeeping records.
+fn parse_journal_line(line: &str) -> Option<Option<TimelineEntry>> {
+    let ts_ms = field(line, "ts")?.parse().ok()?;
+    let radio = || match field(line, "radio")? {
+        "1" => Some(Radio::Radio1),
+        "2" => Some(Radio::Radio2),
+        _ => None,
+    };
+    let origin = match field(line, "origin") {
+        Some("device") => Origin::Device,
+        _ => Origin::Host,
+    };
+    let event = match field(line, "event")? {
+        "open" | "lagged" => return Some(None),
+        "tx" => SwitchEvent::TxChanged {
+            radio: radio()?,
+            origin,
+        },
+        "rx" => SwitchEvent::RxChanged {
+            radio: radio()?,
+            origin,
+            mode: match field(line, "mode")? {
+                "mono" => RxMode::Mono,
+                "stereo" => RxMode::Stereo,
+                "reverse_stereo" => RxMode::ReverseStereo,
+                "mixed" => RxMode::Mixed,
+                _ => return None,
+            },
+        },
+        "aux" => SwitchEvent::AuxChanged {
+            port: field(line, "port")?.parse().ok()?,
+            value: field(line, "value")?.parse().ok()?,
+            origin,
+        },
+        "keying" => SwitchEvent::KeyingChanged {
+            radio: radio()?,
+            origin,
+        },
+        "connected" => SwitchEvent::Connected,
+        "disconnected" => SwitchEvent::Disconnected,
+        "device_reset" => SwitchEvent::DeviceReset,
+        "footswitch" => SwitchEvent::FootswitchChanged {
+            pressed: field(line, "pressed")? == "true",
+        },
+        "ptt" => SwitchEvent::PttChanged {
+            radio: match field(line, "radio")? {
+                "0" => None,
+                _ => Some(radio()?),
+            },
+        },
+        "failed_over" => SwitchEvent::FailedOver,
+        "reconnecting" => SwitchEvent::Reconnecting,
+        "reconnected" => SwitchEvent::Reconnected,
+        "io_task_failed" => SwitchEvent::IoTaskFailed,
+        _ => return None,
+    };
+    Some(Some(TimelineEntry { ts_ms, event }))
+}
+
+/// Value of a flat JSON field, with string quotes stripped.
+fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let pat = format!("\"{key}\":");
+    let rest = &line[line.find(&pat)? + pat.len()..];
+    match rest.strip_prefix('"') {
+        Some(s) => s.split('"').next(),
+        None => rest.split([',', '}']).next(),
+    }
+}
+
+/// Ring buffer of the most recent events from a switch.
+///
+/// The recording task ends when the event channel closes; the buffer stays
+/// readable afterwards.
+pub struct TimelineRecorder {
+    buffer: Arc<Mutex<VecDeque<TimelineEntry>>>,
+    _task: JoinHandle<()>,
+}
+
+impl TimelineRecorder {
+    /// Record events from `events`, keeping at most `capacity` entries.
+    pub fn spawn(mut events: broadcast::Receiver<SwitchEvent>, capacity: usize) -> Self {
+        let buffer = Arc::new(Mutex::new(VecDeque::with_capacity(capacity)));
+        let sink = buffer.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        let mut buf = sink.lock().unwrap();
+                        if buf.len() == capacity {
+                            buf.pop_front();
+                        }
+                        if capacity > 0 {
+                            buf.push_back(TimelineEntry {
+                                ts_ms: now_ms(),
+                                event,
+                            });
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        Self {
+            buffer,
+            _task: task,
+        }
+    }
+
+    /// Snapshot of the buffered events.
+    pub fn timeline(&self) -> Timeline {
+        Timeline {
+            entries: self.buffer.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+}
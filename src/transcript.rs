@@ -0,0 +1,142 @@
+//! Protocol transcript: every command and its answer, with timestamps.
+//!
+//! Enabled with [`OtrspBuilder::transcript()`](crate::OtrspBuilder::transcript)
+//! and read back through [`OtrspDevice::transcript()`](crate::OtrspDevice::transcript).
+//! With [`OtrspBuilder::transcript_file()`](crate::OtrspBuilder::transcript_file)
+//! each exchange is also appended to a JSONL file, ready to attach to a bug
+//! report:
+//!
+//! ```text
+//! {"ts":1760601600000,"command":"?NAME\u000d","response":"NAMESO2Rduino\u000d","elapsed_ms":4}
+//! {"ts":1760601600010,"command":"TX2\u000d","elapsed_ms":0}
+//! {"ts":1760601600020,"command":"?AUX1\u000d","error":"timeout waiting for response","elapsed_ms":1001}
+//! ```
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::audit::json_string;
+
+/// One command sent to the device and what came of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptEntry {
+    /// When the command was sent, in milliseconds since the Unix epoch.
+    pub ts: u64,
+    /// The command as written, terminator included.
+    pub command: Vec<u8>,
+    /// The answer line, for queries that received one.
+    pub response: Option<Vec<u8>>,
+    /// Why the exchange failed, if it did.
+    pub error: Option<String>,
+    /// Time from sending the command to its completion.
+    pub elapsed: Duration,
+}
+
+impl TranscriptEntry {
+    /// The entry as one JSON object, without a trailing newline.
+    ///
+    /// Bytes are decoded lossily; CR, LF and other control characters are
+    /// escaped.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            r#"{{"ts":{},"command":{}"#,
+            self.ts,
+            json_string(&String::from_utf8_lossy(&self.command))
+        );
+        if let Some(response) = &self.response {
+            json.push_str(&format!(
+                r#","response":{}"#,
+                json_string(&String::from_utf8_lossy(response))
+            ));
+        }
+        if let Some(error) = &self.error {
+            json.push_str(&format!(r#","error":{}"#, json_string(error)));
+        }
+        json.push_str(&format!(r#","elapsed_ms":{}}}"#, self.elapsed.as_millis()));
+        json
+    }
+}
+
+/// Recorder shared by the device and its IO handle.
+///
+/// Keeps the most recent entries in memory and optionally appends every
+/// entry to a JSONL file.
+#[derive(Clone)]
+pub struct Transcript {
+    inner: Arc<Mutex<Inner>>,
+    /// Lines for the file writer, which runs on the blocking pool.
+    file: Option<mpsc::UnboundedSender<String>>,
+}
+
+struct Inner {
+    entries: VecDeque<TranscriptEntry>,
+    capacity: usize,
+}
+
+impl Transcript {
+    /// Keep up to `capacity` entries in memory, and append to `file` if given.
+    ///
+    /// The file is written by a task on the blocking pool, which stops once
+    /// every clone of the transcript is dropped.
+    pub(crate) fn new(capacity: usize, file: Option<File>) -> Self {
+        let file = file.map(|mut file| {
+            let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+            tokio::task::spawn_blocking(move || {
+                while let Some(line) = rx.blocking_recv() {
+                    if let Err(e) = file.write_all(line.as_bytes()) {
+                        warn!("transcript write failed: {e}");
+                    }
+                }
+            });
+            tx
+        });
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                entries: VecDeque::with_capacity(capacity),
+                capacity,
+            })),
+            file,
+        }
+    }
+
+    /// The entries held in memory, oldest first.
+    pub fn entries(&self) -> Vec<TranscriptEntry> {
+        self.inner.lock().unwrap().entries.iter().cloned().collect()
+    }
+
+    /// Drop the entries held in memory (the file is left alone).
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+
+    pub(crate) fn record(&self, entry: TranscriptEntry) {
+        if let Some(file) = &self.file {
+            let _ = file.send(format!("{}\n", entry.to_json()));
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if inner.capacity == 0 {
+            return;
+        }
+        if inner.entries.len() == inner.capacity {
+            inner.entries.pop_front();
+        }
+        inner.entries.push_back(entry);
+    }
+}
+
+impl std::fmt::Debug for Transcript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        f.debug_struct("Transcript")
+            .field("entries", &inner.entries.len())
+            .field("capacity", &inner.capacity)
+            .field("file", &self.file.is_some())
+            .finish()
+    }
+}
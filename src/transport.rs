@@ -1,28 +1,633 @@
-//! Serial port transport and MockPort for testing.
+//! Serial, network and pseudo-terminal transports, and MockPort for testing.
 
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::future::Future;
 use std::io;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll, Waker};
+use std::time::Duration;
 
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::time::{Instant, Sleep};
+
+#[cfg(feature = "tls")]
+pub use tokio_rustls::rustls;
+pub use tokio_serial::SerialPortBuilder;
+
+/// A duplex byte stream to a switch: anything the IO task can drive.
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static> Transport for T {}
+
+/// A [`Transport`] of any concrete type.
+pub type BoxedTransport = Box<dyn Transport>;
+
+/// Opens the link to a switch.
+///
+/// [`OtrspBuilder::from_connector()`](crate::OtrspBuilder::from_connector)
+/// accepts any connector, so links the crate does not know about (a vendor
+/// SDK, a USB HID bridge) plug in the same way as the built-in ones. A
+/// connector can be called more than once to re-establish a dropped link.
+#[async_trait]
+pub trait Connector: Send + Sync {
+    /// Open a fresh connection.
+    async fn connect(&self) -> crate::Result<BoxedTransport>;
+}
+
+/// Hook applied to the serial port settings before opening.
+pub(crate) type ConfigureSerial = Arc<dyn Fn(SerialPortBuilder) -> SerialPortBuilder + Send + Sync>;
+
+/// Connects to a serial port with [`open_serial_with()`].
+#[derive(Clone)]
+pub struct SerialConnector {
+    path: String,
+    baud_rate: u32,
+    exclusive: bool,
+    /// The builder's settings hook, re-applied on every open.
+    configure: Option<ConfigureSerial>,
+    /// Shared with the device, so every reopened port answers DTR requests.
+    dtr: Option<DtrControl>,
+}
+
+impl std::fmt::Debug for SerialConnector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SerialConnector")
+            .field("path", &self.path)
+            .field("baud_rate", &self.baud_rate)
+            .field("exclusive", &self.exclusive)
+            .field("configure", &self.configure.is_some())
+            .field("dtr", &self.dtr)
+            .finish()
+    }
+}
+
+impl SerialConnector {
+    /// Connect to the serial port at `path`.
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            baud_rate: 9600,
+            exclusive: true,
+            configure: None,
+            dtr: None,
+        }
+    }
+
+    /// Baud rate to open the port at (default: 9600).
+    pub fn baud_rate(mut self, rate: u32) -> Self {
+        self.baud_rate = rate;
+        self
+    }
+
+    /// Whether to open the port for exclusive use (default: true). See
+    /// [`OtrspBuilder::exclusive()`](crate::OtrspBuilder::exclusive).
+    pub fn exclusive(mut self, enabled: bool) -> Self {
+        self.exclusive = enabled;
+        self
+    }
+
+    /// Apply `configure` to the settings on every open, after the exclusive
+    /// flag and before the baud rate.
+    pub(crate) fn configure(mut self, configure: ConfigureSerial) -> Self {
+        self.configure = Some(configure);
+        self
+    }
+
+    /// Wrap each opened port so `dtr` can drive its DTR line.
+    pub(crate) fn dtr_control(mut self, dtr: DtrControl) -> Self {
+        self.dtr = Some(dtr);
+        self
+    }
+}
+
+#[async_trait]
+impl Connector for SerialConnector {
+    async fn connect(&self) -> crate::Result<BoxedTransport> {
+        let port = open_serial_with(&self.path, |serial| {
+            #[cfg(unix)]
+            let serial = serial.exclusive(self.exclusive);
+            let serial = match &self.configure {
+                Some(configure) => configure(serial),
+                None => serial,
+            };
+            serial.baud_rate(self.baud_rate)
+        })?;
+        match &self.dtr {
+            Some(dtr) => Ok(Box::new(DtrPort::new(port, dtr.clone()))),
+            None => Ok(Box::new(port)),
+        }
+    }
+}
+
+/// Drives the DTR line of a serial port owned by the IO task.
+///
+/// The request is carried out by the [`DtrPort`] on its next poll; the IO
+/// task reads continuously, so that is right away.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DtrControl(Arc<Mutex<DtrState>>);
+
+#[derive(Debug, Default)]
+struct DtrState {
+    request: Option<(bool, tokio::sync::oneshot::Sender<io::Result<()>>)>,
+    /// The port's pending read, woken so a request is carried out at once.
+    waker: Option<Waker>,
+}
+
+impl DtrControl {
+    /// Assert (`true`) or clear DTR.
+    pub(crate) async fn set(&self, level: bool) -> crate::Result<()> {
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+        {
+            let mut state = self.0.lock().unwrap();
+            state.request = Some((level, reply_tx));
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+        match tokio::time::timeout(Duration::from_secs(1), reply_rx).await {
+            Ok(Ok(result)) => result.map_err(crate::Error::Io),
+            Ok(Err(_)) => Err(crate::Error::NotConnected),
+            Err(_) => Err(crate::Error::Timeout),
+        }
+    }
+}
+
+/// A serial port whose DTR line a [`DtrControl`] can drive.
+pub(crate) struct DtrPort {
+    inner: tokio_serial::SerialStream,
+    control: DtrControl,
+}
+
+impl DtrPort {
+    pub(crate) fn new(inner: tokio_serial::SerialStream, control: DtrControl) -> Self {
+        Self { inner, control }
+    }
+
+    /// Carry out a pending DTR request and remember who to wake for the next.
+    fn poll_control(&mut self, cx: &mut Context<'_>) {
+        let mut state = self.control.0.lock().unwrap();
+        state.waker = Some(cx.waker().clone());
+        if let Some((level, reply)) = state.request.take() {
+            let result =
+                tokio_serial::SerialPort::write_data_terminal_ready(&mut self.inner, level);
+            let _ = reply.send(result.map_err(io::Error::from));
+        }
+    }
+}
+
+impl AsyncRead for DtrPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.poll_control(cx);
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for DtrPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Connects to `host:port` with [`open_tcp()`].
+#[derive(Debug, Clone)]
+pub struct TcpConnector {
+    addr: String,
+}
+
+impl TcpConnector {
+    /// Connect to `port` on `host`.
+    pub fn new(host: &str, port: u16) -> Self {
+        Self::with_addr(&tcp_addr(host, port))
+    }
+
+    /// Connect to `addr`, already in `host:port` form.
+    pub(crate) fn with_addr(addr: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Connector for TcpConnector {
+    async fn connect(&self) -> crate::Result<BoxedTransport> {
+        Ok(Box::new(open_tcp(&self.addr).await?))
+    }
+}
+
+/// Baud rates tried by [`OtrspBuilder::auto_baud`](crate::OtrspBuilder::auto_baud), in order.
+pub const COMMON_BAUD_RATES: [u32; 3] = [9600, 19200, 38400];
 
 /// Open a serial port for OTRSP communication.
 ///
 /// Parameters: 9600 baud, 8N1, no flow control. RTS and DTR set low per spec.
+///
+/// The path is validated first so that common first-run failures (wrong
+/// device name, missing permissions, port held by another program) come back
+/// as [`Error::Transport`](crate::Error::Transport) messages explaining the fix.
 pub fn open_serial(path: &str) -> crate::Result<tokio_serial::SerialStream> {
-    let builder = tokio_serial::new(path, 9600)
-        .data_bits(tokio_serial::DataBits::Eight)
-        .parity(tokio_serial::Parity::None)
-        .stop_bits(tokio_serial::StopBits::One)
-        .flow_control(tokio_serial::FlowControl::None);
+    open_serial_with(path, |builder| builder)
+}
 
-    let port = tokio_serial::SerialStream::open(&builder)
-        .map_err(|e| crate::Error::Transport(format!("failed to open {path}: {e}")))?;
+/// Open a serial port, letting `configure` adjust the default settings first.
+///
+/// `configure` receives a [`SerialPortBuilder`] preset as in [`open_serial()`]
+/// and may change any tokio-serial option (baud rate, timeouts, DTR on open,
+/// exclusive mode) before the port is opened.
+pub fn open_serial_with<F>(path: &str, configure: F) -> crate::Result<tokio_serial::SerialStream>
+where
+    F: FnOnce(SerialPortBuilder) -> SerialPortBuilder,
+{
+    validate_port_path(path)?;
+
+    let builder = configure(
+        tokio_serial::new(path, 9600)
+            .data_bits(tokio_serial::DataBits::Eight)
+            .parity(tokio_serial::Parity::None)
+            .stop_bits(tokio_serial::StopBits::One)
+            .flow_control(tokio_serial::FlowControl::None),
+    );
+
+    let port = tokio_serial::SerialStream::open(&builder).map_err(|e| open_error(path, e))?;
 
     Ok(port)
 }
 
+/// Check that the port exists before opening it.
+///
+/// Unix device nodes live in the filesystem and can be checked without
+/// opening them (opening toggles DTR, which resets Arduino-based boxes).
+/// Windows COM names are not filesystem paths, so they are left to the open.
+#[cfg(unix)]
+fn validate_port_path(path: &str) -> crate::Result<()> {
+    if !std::path::Path::new(path).exists() {
+        return Err(crate::Error::Transport(format!(
+            "device not found: {path} ({})",
+            available_ports_hint()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn validate_port_path(_path: &str) -> crate::Result<()> {
+    Ok(())
+}
+
+/// Turn a serial open failure into an actionable transport error.
+fn open_error(path: &str, e: tokio_serial::Error) -> crate::Error {
+    use std::io::ErrorKind as Io;
+    use tokio_serial::ErrorKind;
+
+    let hint = match e.kind {
+        ErrorKind::Io(Io::NotFound) => available_ports_hint(),
+        ErrorKind::Io(Io::PermissionDenied) => permission_hint().to_string(),
+        ErrorKind::NoDevice if cfg!(windows) => format!(
+            "port missing or in use by another program; {}",
+            available_ports_hint()
+        ),
+        ErrorKind::NoDevice => {
+            "port is in use by another program, such as a logger or another SO2R application"
+                .to_string()
+        }
+        _ => return crate::Error::Transport(format!("failed to open {path}: {e}")),
+    };
+    crate::Error::Transport(format!("failed to open {path}: {e} ({hint})"))
+}
+
+/// List the serial ports the OS reports, for "not found" diagnostics.
+fn available_ports_hint() -> String {
+    match tokio_serial::available_ports() {
+        Ok(ports) if !ports.is_empty() => {
+            let names: Vec<&str> = ports.iter().map(|p| p.port_name.as_str()).collect();
+            format!("available ports: {}", names.join(", "))
+        }
+        _ => "no serial ports detected".to_string(),
+    }
+}
+
+/// A serial port reported by the OS, with USB details where available.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortInfo {
+    /// Path or name to open (`/dev/ttyUSB0`, `COM3`).
+    pub name: String,
+    /// USB vendor ID, for USB adapters.
+    pub vid: Option<u16>,
+    /// USB product ID, for USB adapters.
+    pub pid: Option<u16>,
+    /// USB manufacturer string (`FTDI`).
+    pub manufacturer: Option<String>,
+    /// USB product string (`FT232R USB UART`).
+    pub product: Option<String>,
+    /// USB serial number, which tells identical adapters apart.
+    pub serial_number: Option<String>,
+    /// Whether the port is a Bluetooth serial (SPP) link.
+    pub bluetooth: bool,
+}
+
+impl PortInfo {
+    /// Whether the port is a USB adapter.
+    pub fn is_usb(&self) -> bool {
+        self.vid.is_some()
+    }
+
+    /// Whether the port is a Bluetooth serial (SPP) link; see
+    /// [`OtrspBuilder::new_rfcomm()`](crate::OtrspBuilder::new_rfcomm).
+    pub fn is_bluetooth(&self) -> bool {
+        self.bluetooth
+    }
+}
+
+impl From<tokio_serial::SerialPortInfo> for PortInfo {
+    fn from(info: tokio_serial::SerialPortInfo) -> Self {
+        let mut port = PortInfo {
+            name: info.port_name,
+            vid: None,
+            pid: None,
+            manufacturer: None,
+            product: None,
+            serial_number: None,
+            bluetooth: false,
+        };
+        match info.port_type {
+            tokio_serial::SerialPortType::UsbPort(usb) => {
+                port.vid = Some(usb.vid);
+                port.pid = Some(usb.pid);
+                port.manufacturer = usb.manufacturer;
+                port.product = usb.product;
+                port.serial_number = usb.serial_number;
+            }
+            tokio_serial::SerialPortType::BluetoothPort => port.bluetooth = true,
+            // Linux RFCOMM TTYs are not always classified.
+            _ => port.bluetooth = port.name.starts_with("/dev/rfcomm"),
+        }
+        port
+    }
+}
+
+/// List the serial ports an OTRSP switch could be on, for a port picker.
+///
+/// USB adapters come first, since SO2R boxes almost always attach through
+/// one, then Bluetooth links; ports are sorted by name within each group.
+pub fn list_ports() -> crate::Result<Vec<PortInfo>> {
+    let ports = tokio_serial::available_ports()
+        .map_err(|e| crate::Error::Transport(format!("failed to list serial ports: {e}")))?;
+    let mut ports: Vec<PortInfo> = ports.into_iter().map(PortInfo::from).collect();
+    ports.sort_by(|a, b| {
+        let group = |p: &PortInfo| (!p.is_usb(), !p.is_bluetooth());
+        group(a).cmp(&group(b)).then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(ports)
+}
+
+/// OS-specific advice for a permission-denied open.
+fn permission_hint() -> &'static str {
+    if cfg!(target_os = "linux") {
+        "permission denied; add your user to the dialout group (uucp on Arch) and log in again"
+    } else if cfg!(target_os = "macos") {
+        "permission denied; use the /dev/cu.* device and check no other program holds it"
+    } else {
+        "permission denied; check the port is not in use by another program"
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TCP transport
+// ---------------------------------------------------------------------------
+
+/// Connect to an OTRSP device exposed over TCP, e.g. by ser2net or an ESP32
+/// Wi-Fi bridge.
+///
+/// `addr` is `host:port`. Nagle's algorithm is disabled so that short
+/// commands go out immediately.
+pub async fn open_tcp(addr: &str) -> crate::Result<TcpStream> {
+    let stream = TcpStream::connect(addr)
+        .await
+        .map_err(|e| crate::Error::Transport(format!("failed to connect to {addr}: {e}")))?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// `host:port`, with IPv6 literals bracketed.
+pub(crate) fn tcp_addr(host: &str, port: u16) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+/// Connect over TLS, layered on [`open_tcp()`].
+///
+/// The server certificate must be valid for `host`. `config` supplies the
+/// trusted roots and client settings; `None` trusts the Mozilla root set.
+#[cfg(feature = "tls")]
+pub async fn open_tls(
+    host: &str,
+    port: u16,
+    config: Option<Arc<rustls::ClientConfig>>,
+) -> crate::Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    let server_name = rustls::pki_types::ServerName::try_from(host.to_string()).map_err(|e| {
+        crate::Error::InvalidParameter(format!("invalid TLS server name {host:?}: {e}"))
+    })?;
+    let config = match config {
+        Some(config) => config,
+        None => default_tls_config()?,
+    };
+    let tcp = open_tcp(&tcp_addr(host, port)).await?;
+    tokio_rustls::TlsConnector::from(config)
+        .connect(server_name, tcp)
+        .await
+        .map_err(|e| crate::Error::Transport(format!("TLS handshake with {host} failed: {e}")))
+}
+
+/// Connects with [`open_tls()`].
+#[cfg(feature = "tls")]
+#[derive(Debug, Clone)]
+pub struct TlsConnector {
+    host: String,
+    port: u16,
+    config: Option<Arc<rustls::ClientConfig>>,
+}
+
+#[cfg(feature = "tls")]
+impl TlsConnector {
+    /// Connect to `port` on `host`; `config` as for [`open_tls()`].
+    pub fn new(host: &str, port: u16, config: Option<Arc<rustls::ClientConfig>>) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            config,
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+#[async_trait]
+impl Connector for TlsConnector {
+    async fn connect(&self) -> crate::Result<BoxedTransport> {
+        let stream = open_tls(&self.host, self.port, self.config.clone()).await?;
+        Ok(Box::new(stream))
+    }
+}
+
+/// Client settings trusting the Mozilla root set, using the ring provider.
+#[cfg(feature = "tls")]
+fn default_tls_config() -> crate::Result<Arc<rustls::ClientConfig>> {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| crate::Error::Transport(format!("TLS setup failed: {e}")))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+// ---------------------------------------------------------------------------
+// Pseudo-terminals and in-memory pairs
+// ---------------------------------------------------------------------------
+
+/// A pseudo-terminal pair standing in for a serial-attached switch.
+///
+/// Bytes written to one end are read from the other after passing through
+/// the kernel's tty layer, as with a USB serial adapter. Hand `port` to
+/// [`build_with_port()`](crate::OtrspBuilder::build_with_port) and drive
+/// `switch` from a simulator to exercise the full stack without hardware.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct Pty {
+    /// The application's end, as it would open a real port.
+    pub port: tokio_serial::SerialStream,
+    /// The simulated device's end.
+    pub switch: tokio_serial::SerialStream,
+    /// Filesystem path of the application's end (`/dev/pts/N`), for
+    /// programs that open the port by name.
+    pub path: String,
+}
+
+/// Create a pseudo-terminal pair in raw mode.
+#[cfg(unix)]
+pub fn open_pty() -> crate::Result<Pty> {
+    use tokio_serial::SerialPort;
+
+    let (switch, port) = tokio_serial::SerialStream::pair()
+        .map_err(|e| crate::Error::Transport(format!("failed to open pseudo-terminal: {e}")))?;
+    let path = port.name().unwrap_or_default();
+    Ok(Pty { port, switch, path })
+}
+
+/// Create two connected in-memory streams: `(port, switch)`.
+///
+/// Bytes written to one are read from the other. Hand `port` to
+/// [`build_with_port()`](crate::OtrspBuilder::build_with_port) and run an
+/// emulator on `switch` for end-to-end tests on any platform. Unlike
+/// [`MockPort`], the far end is a real stream an emulator reads and answers.
+pub fn pair() -> (DuplexStream, DuplexStream) {
+    tokio::io::duplex(PAIR_BUFFER)
+}
+
+/// Bytes each direction of a [`pair()`] holds before writes wait.
+const PAIR_BUFFER: usize = 4096;
+
+// ---------------------------------------------------------------------------
+// Advisory port locking
+// ---------------------------------------------------------------------------
+
+/// An advisory lock on a serial port, released when dropped.
+///
+/// Cooperating processes (two instances of an application, or a daemon and a
+/// stray CLI invocation) take the lock before opening the port so they cannot
+/// interleave commands on the same box. The lock file is never deleted, since
+/// removing it would let a third process lock a fresh file while a second one
+/// still waits on the old one.
+#[derive(Debug)]
+pub struct PortLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl PortLock {
+    /// Lock the file for `port` in `dir`, failing if another process holds it.
+    ///
+    /// The lock file is `<dir>/otrsp-<port name>.lock`, see [`lock_path()`](Self::lock_path).
+    pub fn acquire(dir: &Path, port: &str) -> crate::Result<Self> {
+        let path = Self::lock_path(dir, port);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| {
+                crate::Error::Transport(format!("cannot create lock file {}: {e}", path.display()))
+            })?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(Self { _file: file, path }),
+            Err(std::fs::TryLockError::WouldBlock) => Err(crate::Error::Transport(format!(
+                "{port} is in use by another process (lock held on {})",
+                path.display()
+            ))),
+            Err(std::fs::TryLockError::Error(e)) => Err(crate::Error::Transport(format!(
+                "cannot lock {}: {e}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Lock file path for `port` in `dir`.
+    ///
+    /// Uses the last path component of the port (`/dev/ttyUSB0` becomes
+    /// `otrsp-ttyUSB0.lock`), with anything but ASCII letters, digits, `-`
+    /// and `_` replaced by `_`.
+    pub fn lock_path(dir: &Path, port: &str) -> PathBuf {
+        let name = Path::new(port)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| port.to_string());
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        dir.join(format!("otrsp-{name}.lock"))
+    }
+
+    /// Path of the held lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
 // ---------------------------------------------------------------------------
 // MockPort for testing
 // ---------------------------------------------------------------------------
@@ -38,12 +643,152 @@ struct MockState {
     read_closed: bool,
     /// Waker to notify when new data is queued.
     read_waker: Option<Waker>,
+    /// Scripted requests and their responses.
+    script: Script,
+    /// Delay before queued bytes become readable.
+    latency: Duration,
+    /// Deliver queued bytes this many at a time, this far apart.
+    chunking: Option<(usize, Duration)>,
+    /// Bytes waiting for their delivery time, in order.
+    scheduled: VecDeque<(Instant, Vec<u8>)>,
+    /// Timer for the next scheduled delivery.
+    delivery: Option<Pin<Box<Sleep>>>,
+    /// Writes still to fail, and the error they fail with.
+    write_failures: (usize, io::ErrorKind),
+    /// Bytes the next write accepts before the link breaks, and the error
+    /// the write after it fails with.
+    write_break: Option<(usize, io::ErrorKind)>,
+}
+
+impl MockState {
+    /// Queue bytes for the reader and wake it.
+    fn push_read(&mut self, data: &[u8]) {
+        if self.latency.is_zero() && self.chunking.is_none() {
+            self.read_buf.extend_from_slice(data);
+        } else {
+            let mut at = Instant::now() + self.latency;
+            if let Some((last, _)) = self.scheduled.back() {
+                at = at.max(*last);
+            }
+            let (size, interval) = self.chunking.unwrap_or((data.len().max(1), Duration::ZERO));
+            for (i, chunk) in data.chunks(size.max(1)).enumerate() {
+                self.scheduled
+                    .push_back((at + interval * i as u32, chunk.to_vec()));
+            }
+        }
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Move scheduled bytes that are due into the read buffer.
+    ///
+    /// Returns `Pending` (with the timer armed) if nothing is readable yet
+    /// but a delivery is scheduled.
+    fn poll_deliveries(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let now = Instant::now();
+        while let Some((at, _)) = self.scheduled.front()
+            && *at <= now
+        {
+            let (_, chunk) = self.scheduled.pop_front().unwrap();
+            self.read_buf.extend_from_slice(&chunk);
+        }
+        match self.scheduled.front() {
+            Some(&(at, _)) if self.read_buf.is_empty() => {
+                let delivery = self
+                    .delivery
+                    .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(at)));
+                delivery.as_mut().reset(at);
+                if delivery.as_mut().poll(cx).is_ready() {
+                    cx.waker().wake_by_ref();
+                }
+                Poll::Pending
+            }
+            _ => Poll::Ready(()),
+        }
+    }
+}
+
+/// Expectations set with [`MockPort::expect()`].
+#[derive(Default)]
+struct Script {
+    expectations: Vec<Expectation>,
+    ordered: bool,
+    /// Written bytes not yet matched against an expectation.
+    pending: Vec<u8>,
+    /// Writes that matched no expectation.
+    unexpected: Vec<Vec<u8>>,
+}
+
+struct Expectation {
+    request: Vec<u8>,
+    response: Vec<u8>,
+    met: bool,
+}
+
+impl Script {
+    /// Match newly written bytes, returning the responses to queue.
+    fn write(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut responses = Vec::new();
+        if self.expectations.is_empty() {
+            return responses;
+        }
+        self.pending.extend_from_slice(data);
+        while !self.pending.is_empty() {
+            let candidates: Vec<usize> = match self.expectations.iter().position(|e| !e.met) {
+                Some(next) if self.ordered => vec![next],
+                _ => (0..self.expectations.len())
+                    .filter(|&i| !self.expectations[i].met)
+                    .collect(),
+            };
+            let matched = candidates
+                .iter()
+                .copied()
+                .find(|&i| self.pending.starts_with(&self.expectations[i].request));
+            if let Some(i) = matched {
+                let expectation = &mut self.expectations[i];
+                expectation.met = true;
+                self.pending.drain(..expectation.request.len());
+                responses.extend_from_slice(&expectation.response);
+                continue;
+            }
+            let partial = candidates
+                .iter()
+                .any(|&i| self.expectations[i].request.starts_with(&self.pending));
+            if partial {
+                break;
+            }
+            // Give up on the first command (through CR) and try the rest.
+            let end = self
+                .pending
+                .iter()
+                .position(|&b| b == b'\r')
+                .map_or(self.pending.len(), |i| i + 1);
+            self.unexpected.push(self.pending.drain(..end).collect());
+        }
+        responses
+    }
+}
+
+/// Handle returned by [`MockPort::expect()`] to script the answer.
+pub struct MockExpectation<'a> {
+    port: &'a MockPort,
+    index: usize,
+}
+
+impl MockExpectation<'_> {
+    /// Queue `response` for the reader when the expected bytes are written.
+    pub fn respond(self, response: &[u8]) {
+        let mut state = self.port.state.lock().unwrap();
+        state.script.expectations[self.index].response = response.to_vec();
+    }
 }
 
 /// A mock serial port implementing `AsyncRead + AsyncWrite` for testing.
 ///
 /// Pre-load response bytes with [`queue_read()`](MockPort::queue_read), then
-/// inspect what was written with [`written_data()`](MockPort::written_data).
+/// inspect what was written with [`written_data()`](MockPort::written_data),
+/// or script request/response pairs with [`expect()`](MockPort::expect).
 #[derive(Clone)]
 pub struct MockPort {
     state: Arc<Mutex<MockState>>,
@@ -59,6 +804,13 @@ impl MockPort {
                 closed: false,
                 read_closed: false,
                 read_waker: None,
+                script: Script::default(),
+                latency: Duration::ZERO,
+                chunking: None,
+                scheduled: VecDeque::new(),
+                delivery: None,
+                write_failures: (0, io::ErrorKind::Other),
+                write_break: None,
             })),
         }
     }
@@ -66,13 +818,107 @@ impl MockPort {
     /// Queue bytes that will be returned by reads (simulating device → host).
     /// Wakes any pending readers.
     pub fn queue_read(&self, data: &[u8]) {
+        self.state.lock().unwrap().push_read(data);
+    }
+
+    /// Expect the host to write `request`; chain
+    /// [`respond()`](MockExpectation::respond) to answer it.
+    ///
+    /// Once any expectation is set the port is scripted: each answer is
+    /// queued only when its request has been written, and writes matching no
+    /// expectation are recorded for [`verify()`](Self::verify). Expectations
+    /// may be met in any order unless [`set_ordered()`](Self::set_ordered)
+    /// is used.
+    ///
+    /// ```
+    /// # use otrsp::{MockPort, OtrspBuilder, So2rSwitch};
+    /// # async fn example() -> otrsp::Result<()> {
+    /// let mock = MockPort::new();
+    /// mock.expect(b"?NAME\r").respond(b"NAMESO2RDUINO\r");
+    /// mock.expect(b"?AUX1\r").respond(b"AUX14\r");
+    /// let device = OtrspBuilder::new("/dev/mock")
+    ///     .build_with_port(mock.clone())
+    ///     .await?;
+    /// assert_eq!(device.query_aux(1).await?, 4);
+    /// mock.verify();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn expect(&self, request: &[u8]) -> MockExpectation<'_> {
         let mut state = self.state.lock().unwrap();
-        state.read_buf.extend_from_slice(data);
-        if let Some(waker) = state.read_waker.take() {
-            waker.wake();
+        state.script.expectations.push(Expectation {
+            request: request.to_vec(),
+            response: Vec::new(),
+            met: false,
+        });
+        MockExpectation {
+            port: self,
+            index: state.script.expectations.len() - 1,
         }
     }
 
+    /// Make queued bytes readable only `latency` after they are queued
+    /// (default: immediately).
+    ///
+    /// Applies to [`queue_read()`](Self::queue_read) and scripted responses
+    /// queued from then on, so a response to a command arrives `latency`
+    /// after the command is written.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.lock().unwrap().latency = latency;
+    }
+
+    /// Deliver queued bytes `size` at a time, `interval` apart (default: all
+    /// at once), like a slow serial line.
+    ///
+    /// `set_chunking(1, Duration::from_millis(10))` delivers one byte every
+    /// 10ms, exercising partial reads. Applies to bytes queued from then on.
+    pub fn set_chunking(&self, size: usize, interval: Duration) {
+        self.state.lock().unwrap().chunking = Some((size, interval));
+    }
+
+    /// Fail the next `count` writes with `kind`, e.g.
+    /// [`WouldBlock`](io::ErrorKind::WouldBlock) for a busy USB adapter.
+    pub fn fail_writes(&self, count: usize, kind: io::ErrorKind) {
+        self.state.lock().unwrap().write_failures = (count, kind);
+    }
+
+    /// Accept only the first `accepted` bytes of the next write, then fail
+    /// the write after it with `kind`, like a link that stalls mid-write.
+    pub fn break_write(&self, accepted: usize, kind: io::ErrorKind) {
+        self.state.lock().unwrap().write_break = Some((accepted, kind));
+    }
+
+    /// Require expectations to be met in the order they were set
+    /// (default: any order).
+    pub fn set_ordered(&self, ordered: bool) {
+        self.state.lock().unwrap().script.ordered = ordered;
+    }
+
+    /// Panic unless every expectation was met and nothing else was written.
+    #[track_caller]
+    pub fn verify(&self) {
+        let state = self.state.lock().unwrap();
+        let script = &state.script;
+        let unmet: Vec<String> = script
+            .expectations
+            .iter()
+            .filter(|e| !e.met)
+            .map(|e| e.request.escape_ascii().to_string())
+            .collect();
+        let mut unexpected: Vec<String> = script
+            .unexpected
+            .iter()
+            .map(|w| w.escape_ascii().to_string())
+            .collect();
+        if !script.pending.is_empty() {
+            unexpected.push(script.pending.escape_ascii().to_string());
+        }
+        assert!(
+            unmet.is_empty() && unexpected.is_empty(),
+            "mock expectations not satisfied: unmet {unmet:?}, unexpected writes {unexpected:?}"
+        );
+    }
+
     /// Get all bytes written to the port (host → device).
     pub fn written_data(&self) -> Vec<u8> {
         self.state.lock().unwrap().write_log.clone()
@@ -80,7 +926,8 @@ impl MockPort {
 
     /// Check if there are pending read bytes.
     pub fn has_pending_reads(&self) -> bool {
-        !self.state.lock().unwrap().read_buf.is_empty()
+        let state = self.state.lock().unwrap();
+        !state.read_buf.is_empty() || !state.scheduled.is_empty()
     }
 
     /// Mark the port as closed (subsequent reads/writes return error).
@@ -92,6 +939,14 @@ impl MockPort {
         }
     }
 
+    /// Undo [`close()`](Self::close) and [`close_read()`](Self::close_read),
+    /// simulating a link that recovers after a glitch.
+    pub fn reopen(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = false;
+        state.read_closed = false;
+    }
+
     /// Close only the read side (writes still succeed).
     ///
     /// This simulates a half-broken connection where the host can still
@@ -106,6 +961,15 @@ impl MockPort {
     }
 }
 
+/// Each connection is a handle to the same mock, so a test can script one
+/// port across reconnects.
+#[async_trait]
+impl Connector for MockPort {
+    async fn connect(&self) -> crate::Result<BoxedTransport> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
 impl Default for MockPort {
     fn default() -> Self {
         Self::new()
@@ -126,6 +990,10 @@ impl AsyncRead for MockPort {
             )));
         }
 
+        if state.poll_deliveries(cx).is_pending() {
+            state.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
         if state.read_buf.is_empty() {
             state.read_waker = Some(cx.waker().clone());
             return Poll::Pending;
@@ -151,8 +1019,23 @@ impl AsyncWrite for MockPort {
                 "mock port closed",
             )));
         }
+        if let (count @ 1.., kind) = state.write_failures {
+            state.write_failures.0 = count - 1;
+            return Poll::Ready(Err(io::Error::new(kind, "mock write failure")));
+        }
+        let buf = match state.write_break.take() {
+            Some((accepted, kind)) => {
+                state.write_failures = (1, kind);
+                &buf[..accepted.min(buf.len())]
+            }
+            None => buf,
+        };
 
         state.write_log.extend_from_slice(buf);
+        let responses = state.script.write(buf);
+        if !responses.is_empty() {
+            state.push_read(&responses);
+        }
         Poll::Ready(Ok(buf.len()))
     }
 
@@ -1,3 +1,5 @@
+use crate::switch::SwitchCapabilities;
+
 /// Which radio (1 or 2).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Radio {
@@ -5,6 +7,49 @@ pub enum Radio {
     Radio2,
 }
 
+impl Radio {
+    /// The other radio of the pair.
+    pub fn other(self) -> Radio {
+        match self {
+            Radio::Radio1 => Radio::Radio2,
+            Radio::Radio2 => Radio::Radio1,
+        }
+    }
+}
+
+/// Amateur HF and 6m bands, as selected by a band decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Band {
+    M160,
+    M80,
+    M60,
+    M40,
+    M30,
+    M20,
+    M17,
+    M15,
+    M12,
+    M10,
+    M6,
+}
+
+impl Band {
+    /// Every band, lowest frequency first.
+    pub const ALL: [Band; 11] = [
+        Band::M160,
+        Band::M80,
+        Band::M60,
+        Band::M40,
+        Band::M30,
+        Band::M20,
+        Band::M17,
+        Band::M15,
+        Band::M12,
+        Band::M10,
+        Band::M6,
+    ];
+}
+
 /// Receive audio routing mode.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RxMode {
@@ -14,4 +59,86 @@ pub enum RxMode {
     Stereo,
     /// Radio 1 right ear, Radio 2 left ear.
     ReverseStereo,
+    /// Both radios summed into both ears.
+    ///
+    /// Not part of the OTRSP spec; only sent to devices whose capabilities
+    /// declare [`mixed`](crate::SwitchCapabilities::mixed) support, using
+    /// the vendor command registered as
+    /// [`extension::RX_MIXED`](crate::extension::RX_MIXED).
+    Mixed,
+}
+
+/// How RX audio follows TX focus, for
+/// [`OtrspBuilder::rx_follows_tx()`](crate::OtrspBuilder::rx_follows_tx).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxFollow {
+    /// The radio with TX focus in both ears.
+    Mono,
+    /// Focus RX on the radio with TX focus, keeping the current RX mode
+    /// (stereo stays stereo).
+    KeepMode,
+}
+
+impl RxFollow {
+    /// RX routing that follows TX focus to `radio`, given the current routing.
+    pub(crate) fn routing(self, radio: Radio, current: Option<(Radio, RxMode)>) -> (Radio, RxMode) {
+        let mode = match self {
+            RxFollow::Mono => RxMode::Mono,
+            RxFollow::KeepMode => current.map_or(RxMode::Mono, |(_, mode)| mode),
+        };
+        (radio, mode)
+    }
+}
+
+/// Desired headphone configuration, in operator terms.
+///
+/// An `AudioRoute` describes what the operator wants to hear rather than the
+/// wire-level [`RxMode`]; [`rx_command()`](AudioRoute::rx_command) picks the
+/// closest RX routing the device supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioRoute {
+    /// Radio the operator is focused on.
+    pub focus: Radio,
+    /// Hear both radios split across the ears instead of only the focused radio.
+    pub split: bool,
+    /// Swap the ears when split (Radio 1 right, Radio 2 left).
+    pub swap: bool,
+}
+
+impl AudioRoute {
+    /// Focused radio in both ears.
+    pub fn mono(focus: Radio) -> Self {
+        Self {
+            focus,
+            split: false,
+            swap: false,
+        }
+    }
+
+    /// Both radios split across the ears, Radio 1 left and Radio 2 right.
+    pub fn split(focus: Radio) -> Self {
+        Self {
+            focus,
+            split: true,
+            swap: false,
+        }
+    }
+
+    /// Return this route with the ears swapped.
+    pub fn swapped(self) -> Self {
+        Self { swap: true, ..self }
+    }
+
+    /// Translate to the best RX command the device supports.
+    ///
+    /// A swapped split falls back to plain stereo without reverse-stereo
+    /// support, and any split falls back to mono without stereo support.
+    pub fn rx_command(&self, caps: &SwitchCapabilities) -> (Radio, RxMode) {
+        let mode = match (self.split, self.swap) {
+            (true, true) if caps.reverse_stereo => RxMode::ReverseStereo,
+            (true, _) if caps.stereo => RxMode::Stereo,
+            _ => RxMode::Mono,
+        };
+        (self.focus, mode)
+    }
 }
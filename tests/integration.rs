@@ -1,4 +1,7 @@
-use otrsp::{Error, MockPort, OtrspBuilder, Radio, RxMode, So2rSwitch, SwitchEvent};
+use otrsp::{
+    AudioRoute, Error, MockPort, Origin, OtrspBuilder, ProtocolFeatures, Radio, RxFollow, RxMode,
+    So2rSwitch, SwitchCapabilities, SwitchEvent,
+};
 
 #[tokio::test]
 async fn build_and_query_name() {
@@ -12,6 +15,8 @@ async fn build_and_query_name() {
         .unwrap();
 
     assert_eq!(device.info().name, "SO2RDUINO");
+    let identity = device.info().identity.as_ref().unwrap();
+    assert_eq!(identity.model.as_deref(), Some("SO2Rduino"));
 
     // Verify the ?NAME query was sent
     let written = mock.written_data();
@@ -31,6 +36,7 @@ async fn build_without_name_query() {
         .unwrap();
 
     assert_eq!(device.info().name, "Unknown");
+    assert!(device.info().identity.is_none());
 
     // Nothing should have been written during build
     assert!(mock.written_data().is_empty());
@@ -176,14 +182,17 @@ async fn events_emitted_on_state_changes() {
     device.set_tx(Radio::Radio1).await.unwrap();
 
     match rx.recv().await.unwrap() {
-        SwitchEvent::TxChanged { radio } => assert_eq!(radio, Radio::Radio1),
+        SwitchEvent::TxChanged { radio, origin } => {
+            assert_eq!(radio, Radio::Radio1);
+            assert_eq!(origin, Origin::Host);
+        }
         other => panic!("expected TxChanged, got {other:?}"),
     }
 
     device.set_rx(Radio::Radio2, RxMode::Stereo).await.unwrap();
 
     match rx.recv().await.unwrap() {
-        SwitchEvent::RxChanged { radio, mode } => {
+        SwitchEvent::RxChanged { radio, mode, .. } => {
             assert_eq!(radio, Radio::Radio2);
             assert_eq!(mode, RxMode::Stereo);
         }
@@ -193,7 +202,7 @@ async fn events_emitted_on_state_changes() {
     device.set_aux(1, 42).await.unwrap();
 
     match rx.recv().await.unwrap() {
-        SwitchEvent::AuxChanged { port, value } => {
+        SwitchEvent::AuxChanged { port, value, .. } => {
             assert_eq!(port, 1);
             assert_eq!(value, 42);
         }
@@ -217,12 +226,13 @@ async fn capabilities_defaults() {
     assert!(caps.stereo);
     assert!(caps.reverse_stereo);
     assert_eq!(caps.aux_ports, 2);
+    assert!(!caps.mixed);
 
     device.close().await.unwrap();
 }
 
 #[tokio::test]
-async fn query_aux_rejects_mismatched_port() {
+async fn query_aux_discards_stale_answers() {
     let mock = MockPort::new();
 
     let device = OtrspBuilder::new("/dev/mock")
@@ -231,17 +241,17 @@ async fn query_aux_rejects_mismatched_port() {
         .await
         .unwrap();
 
-    // Request ?AUX1 but queue a response for port 2
-    mock.queue_read(b"AUX24\r");
+    // Late answers to other queries ahead of the ?AUX1 answer are discarded.
+    mock.queue_read(b"AUX24\rNAMESO2RDUINO\rAUX13\r");
+    assert_eq!(device.query_aux(1).await.unwrap(), 3);
 
-    let result = device.query_aux(1).await;
-    assert!(result.is_err());
-    match result.unwrap_err() {
-        Error::Protocol(msg) => {
-            assert!(msg.contains("mismatch"), "expected mismatch message, got: {msg}");
-        }
-        other => panic!("expected Error::Protocol, got {other:?}"),
-    }
+    // Lines that answer nothing are still returned, and fail to parse.
+    mock.queue_read(b"BOGUS\r");
+    assert!(matches!(device.query_aux(1).await, Err(Error::Protocol(_))));
+
+    // With only a stale answer buffered, the query times out.
+    mock.queue_read(b"AUX24\r");
+    assert!(matches!(device.query_aux(1).await, Err(Error::Timeout)));
 
     device.close().await.unwrap();
 }
@@ -353,9 +363,7 @@ async fn build_name_timeout_does_not_corrupt_next_query() {
     // These are now sitting in the port buffer.
     mock.queue_read(b"NAMESO2RDUINO\r");
 
-    // Queue the real AUX response with a short delay so the drain
-    // can distinguish stale data (already buffered) from the legitimate
-    // response (arrives after the command is sent).
+    // Queue the real AUX response shortly after the command is sent.
     let mock2 = mock.clone();
     tokio::spawn(async move {
         tokio::time::sleep(std::time::Duration::from_millis(50)).await;
@@ -367,3 +375,3593 @@ async fn build_name_timeout_does_not_corrupt_next_query() {
 
     device.close().await.unwrap();
 }
+
+#[tokio::test]
+async fn negotiate_detects_answered_probes() {
+    let mock = MockPort::new();
+    // ?NAME and ?EVENT are answered; ?PTT is left to time out.
+    mock.queue_read(b"NAMERigSelect Pro\rEVENT0\r");
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .negotiate(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    let features = device.features();
+    assert!(features.events);
+    assert!(!features.ptt);
+    assert_eq!(features.radios, 2);
+
+    let written = mock.written_data();
+    assert_eq!(&written[..], b"?NAME\r?EVENT\r?PTT\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn features_default_without_negotiation() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(device.features(), &ProtocolFeatures::default());
+    assert!(mock.written_data().is_empty());
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn set_audio_translates_route() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device
+        .set_audio(AudioRoute::mono(Radio::Radio2))
+        .await
+        .unwrap();
+    device
+        .set_audio(AudioRoute::split(Radio::Radio1))
+        .await
+        .unwrap();
+    device
+        .set_audio(AudioRoute::split(Radio::Radio2).swapped())
+        .await
+        .unwrap();
+
+    let written = mock.written_data();
+    assert_eq!(&written[..], b"RX2\rRX1S\rRX2R\r");
+
+    device.close().await.unwrap();
+}
+
+#[test]
+fn audio_route_falls_back_to_supported_mode() {
+    let caps = SwitchCapabilities {
+        reverse_stereo: false,
+        ..Default::default()
+    };
+    let route = AudioRoute::split(Radio::Radio1).swapped();
+    assert_eq!(route.rx_command(&caps), (Radio::Radio1, RxMode::Stereo));
+
+    let caps = SwitchCapabilities {
+        stereo: false,
+        reverse_stereo: false,
+        ..Default::default()
+    };
+    assert_eq!(route.rx_command(&caps), (Radio::Radio1, RxMode::Mono));
+}
+
+#[tokio::test]
+async fn mixed_rx_requires_capability_and_encoder() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    let result = device.set_rx(Radio::Radio1, RxMode::Mixed).await;
+    assert!(matches!(result, Err(Error::Unsupported(_))));
+    assert!(mock.written_data().is_empty());
+
+    device.close().await.unwrap();
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .capabilities(SwitchCapabilities {
+            mixed: true,
+            ..Default::default()
+        })
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    // OTRSP has no command for it; the vendor's goes through the registry.
+    let result = device.set_rx(Radio::Radio2, RxMode::Mixed).await;
+    assert!(matches!(result, Err(Error::Unsupported(_))));
+    device.register_command(otrsp::extension::RX_MIXED, |radio| {
+        Ok(format!("RX{radio}M\r").into_bytes())
+    });
+    device.set_rx(Radio::Radio2, RxMode::Mixed).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"RX2M\r");
+    assert_eq!(device.state().rx, Some((Radio::Radio2, RxMode::Mixed)));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn footswitch_latch_toggles_and_restores() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .footswitch_latch(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+
+    device.footswitch_press().await.unwrap();
+    assert!(device.is_latched());
+    device.footswitch_press().await.unwrap();
+    assert!(!device.is_latched());
+
+    let written = mock.written_data();
+    assert_eq!(&written[..], b"RX1S\rRX2\rRX1S\r");
+
+    // A device reporting the footswitch drives the latch on its own.
+    device.enable_events(true).await.unwrap();
+    let sent = mock.written_data().len();
+    let mut events = device.subscribe_state();
+    mock.queue_read(b"$FS1\r");
+    loop {
+        if let SwitchEvent::RxChanged { radio, mode, .. } = events.recv().await.unwrap() {
+            assert_eq!((radio, mode), (Radio::Radio2, RxMode::Mono));
+            break;
+        }
+    }
+    assert!(device.is_latched());
+    mock.queue_read(b"$FS0\r$FS1\r");
+    loop {
+        if let SwitchEvent::RxChanged { radio, mode, .. } = events.recv().await.unwrap() {
+            assert_eq!((radio, mode), (Radio::Radio1, RxMode::Stereo));
+            break;
+        }
+    }
+    assert!(!device.is_latched());
+    assert_eq!(&mock.written_data()[sent..], b"RX2\rRX1S\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn footswitch_latch_released_by_explicit_set_rx() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .footswitch_latch(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    // No routing known yet.
+    assert!(matches!(
+        device.footswitch_press().await,
+        Err(Error::InvalidParameter(_))
+    ));
+
+    device.set_rx(Radio::Radio2, RxMode::Mono).await.unwrap();
+    device.footswitch_press().await.unwrap();
+    device.set_rx(Radio::Radio2, RxMode::Stereo).await.unwrap();
+    assert!(!device.is_latched());
+
+    // The next press latches again from the new routing.
+    device.footswitch_press().await.unwrap();
+
+    let written = mock.written_data();
+    assert_eq!(&written[..], b"RX2\rRX1\rRX2S\rRX1\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn transmit_latch_listens_to_the_other_radio() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .transmit_latch(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_tx(Radio::Radio1).await.unwrap();
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    device.set_transmitting(true).await.unwrap();
+    assert!(device.is_transmitting());
+    assert_eq!(device.state().rx, Some((Radio::Radio2, RxMode::Mono)));
+    device.set_transmitting(false).await.unwrap();
+    assert!(!device.is_transmitting());
+    assert_eq!(device.state().rx, Some((Radio::Radio1, RxMode::Stereo)));
+    assert_eq!(&mock.written_data()[..], b"TX1\rRX1S\rRX2\rRX1S\r");
+
+    // A device reporting PTT drives the latch on its own.
+    device.enable_events(true).await.unwrap();
+    let sent = mock.written_data().len();
+    let mut events = device.subscribe_state();
+    mock.queue_read(b"$PTT2\r");
+    loop {
+        if let SwitchEvent::RxChanged { radio, .. } = events.recv().await.unwrap() {
+            assert_eq!(radio, Radio::Radio1);
+            break;
+        }
+    }
+    assert_eq!(device.state().tx, Some(Radio::Radio2));
+    mock.queue_read(b"$PTT0\r");
+    loop {
+        if let SwitchEvent::RxChanged { radio, mode, .. } = events.recv().await.unwrap() {
+            assert_eq!((radio, mode), (Radio::Radio1, RxMode::Stereo));
+            break;
+        }
+    }
+    assert_eq!(&mock.written_data()[sent..], b"RX1\rRX1S\r");
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn transmit_latch_keeps_rx_set_while_transmitting() {
+    use otrsp::protocol::Command;
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .transmit_latch(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio1).await.unwrap();
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+
+    // Through a batch.
+    device.set_transmitting(true).await.unwrap();
+    device
+        .send_batch(&[Command::Rx(Radio::Radio2, RxMode::Stereo)])
+        .await
+        .unwrap();
+    let sent = mock.written_data().len();
+    device.set_transmitting(false).await.unwrap();
+    assert_eq!(mock.written_data().len(), sent);
+    assert_eq!(device.state().rx, Some((Radio::Radio2, RxMode::Stereo)));
+
+    // Through a scheduled command.
+    device.set_transmitting(true).await.unwrap();
+    device
+        .send_after(Command::Rx(Radio::Radio1, RxMode::Stereo), Duration::ZERO)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let sent = mock.written_data().len();
+    device.set_transmitting(false).await.unwrap();
+    assert_eq!(mock.written_data().len(), sent);
+    assert_eq!(device.state().rx, Some((Radio::Radio1, RxMode::Stereo)));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn footswitch_latch_disabled_by_default() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_rx(Radio::Radio1, RxMode::Mono).await.unwrap();
+    assert!(matches!(
+        device.footswitch_press().await,
+        Err(Error::Unsupported(_))
+    ));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn report_ptt_updates_cached_tx() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_tx(Radio::Radio1).await.unwrap();
+    let mut rx = device.subscribe();
+
+    // Keying the radio that already has focus only confirms the cache.
+    assert!(!device.report_ptt(Radio::Radio1));
+    // Keying the other radio moves focus and emits TxChanged.
+    assert!(device.report_ptt(Radio::Radio2));
+
+    assert_eq!(
+        rx.try_recv().unwrap(),
+        SwitchEvent::TxChanged {
+            radio: Radio::Radio2,
+            origin: Origin::Device
+        }
+    );
+    assert!(rx.try_recv().is_err());
+
+    // Nothing beyond the explicit TX1 was written.
+    assert_eq!(&mock.written_data()[..], b"TX1\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn report_ptt_moves_rx_with_rx_follows_tx() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .rx_follows_tx(RxFollow::KeepMode)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio1).await.unwrap();
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    let sent = mock.written_data().len();
+
+    let mut events = device.subscribe_state();
+    assert!(device.report_ptt(Radio::Radio2));
+    loop {
+        if let SwitchEvent::RxChanged { radio, mode, .. } = events.recv().await.unwrap() {
+            assert_eq!((radio, mode), (Radio::Radio2, RxMode::Stereo));
+            break;
+        }
+    }
+    assert_eq!(&mock.written_data()[sent..], b"RX2S\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn ptt_notification_moves_rx_with_rx_follows_tx() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .rx_follows_tx(RxFollow::Mono)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio1).await.unwrap();
+    device.enable_events(true).await.unwrap();
+    let sent = mock.written_data().len();
+
+    let mut events = device.subscribe_state();
+    mock.queue_read(b"$PTT2\r");
+    loop {
+        if let SwitchEvent::RxChanged { radio, mode, .. } = events.recv().await.unwrap() {
+            assert_eq!((radio, mode), (Radio::Radio2, RxMode::Mono));
+            break;
+        }
+    }
+    assert_eq!(device.state().tx, Some(Radio::Radio2));
+    assert_eq!(&mock.written_data()[sent..], b"RX2\r");
+
+    // Keying the radio that already has focus leaves RX alone.
+    mock.queue_read(b"$PTT0\r$PTT2\r");
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(&mock.written_data()[sent..], b"RX2\r");
+
+    device.close().await.unwrap();
+}
+
+#[test]
+fn event_and_error_accessors() {
+    let tx = SwitchEvent::TxChanged {
+        radio: Radio::Radio2,
+        origin: Origin::Host,
+    };
+    assert!(tx.is_state_change());
+    assert!(!tx.is_connection_event());
+    assert_eq!(tx.radio(), Some(Radio::Radio2));
+    assert_eq!(tx.origin(), Some(Origin::Host));
+
+    assert!(SwitchEvent::Disconnected.is_connection_event());
+    assert_eq!(SwitchEvent::Disconnected.origin(), None);
+    let aux = SwitchEvent::AuxChanged {
+        port: 1,
+        value: 4,
+        origin: Origin::Device,
+    };
+    assert_eq!(aux.radio(), None);
+    assert_eq!(aux.origin(), Some(Origin::Device));
+
+    assert!(Error::Timeout.is_timeout());
+    assert!(Error::NotConnected.is_connection_error());
+    assert!(!Error::InvalidParameter("x".into()).is_connection_error());
+}
+
+#[cfg(unix)]
+#[test]
+fn open_missing_port_reports_not_found() {
+    match otrsp::transport::open_serial("/dev/otrsp-does-not-exist") {
+        Err(Error::Transport(msg)) => {
+            assert!(
+                msg.contains("device not found"),
+                "unexpected message: {msg}"
+            );
+        }
+        Err(other) => panic!("expected Error::Transport, got {other:?}"),
+        Ok(_) => panic!("expected open to fail"),
+    }
+}
+
+#[tokio::test]
+async fn skip_redundant_suppresses_repeated_commands() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .skip_redundant(true)
+        .emit_connected(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    let mut rx = device.subscribe();
+
+    device.set_tx(Radio::Radio1).await.unwrap();
+    device.set_tx(Radio::Radio1).await.unwrap();
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    device.set_aux(1, 4).await.unwrap();
+    device.set_aux(1, 4).await.unwrap();
+    device.set_aux(1, 5).await.unwrap();
+
+    let written = mock.written_data();
+    assert_eq!(&written[..], b"TX1\rRX1S\rAUX14\rAUX15\r");
+
+    // One event per command actually sent.
+    let mut events = 0;
+    while rx.try_recv().is_ok() {
+        events += 1;
+    }
+    assert_eq!(events, 4);
+
+    let state = device.state();
+    assert_eq!(state.tx, Some(Radio::Radio1));
+    assert_eq!(state.rx, Some((Radio::Radio1, RxMode::Stereo)));
+    assert_eq!(state.aux.get(&1), Some(&5));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn redundant_commands_sent_by_default() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_tx(Radio::Radio2).await.unwrap();
+    device.set_tx(Radio::Radio2).await.unwrap();
+
+    assert_eq!(&mock.written_data()[..], b"TX2\rTX2\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn query_all_refreshes_state() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_tx(Radio::Radio2).await.unwrap();
+    // TX was moved on the front panel since the last command.
+    mock.queue_read(b"NAMESO2RDUINO\rTX1\rRX2R\rAUX14\rAUX27\r");
+
+    let state = device.query_all().await.unwrap();
+    assert_eq!(state.name.as_deref(), Some("SO2RDUINO"));
+    assert_eq!(state.tx, Some(Radio::Radio1));
+    assert_eq!(state.rx, Some((Radio::Radio2, RxMode::ReverseStereo)));
+    assert_eq!(state.aux.get(&1), Some(&4));
+    assert_eq!(state.aux.get(&2), Some(&7));
+    assert_eq!(device.state(), state);
+
+    let written = mock.written_data();
+    assert_eq!(&written[..], b"TX2\r?NAME\r?TX\r?RX\r?AUX1\r?AUX2\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn ptt_timing_delays_tx_changes() {
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .ptt_timing(Duration::from_millis(50), Duration::from_millis(150))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    // Lead time applies after a focus change.
+    let start = Instant::now();
+    device.set_tx(Radio::Radio1).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    // Re-selecting the same radio moves no relays and is not delayed.
+    let start = Instant::now();
+    device.set_tx(Radio::Radio1).await.unwrap();
+    assert!(start.elapsed() < Duration::from_millis(50));
+
+    // Switching right after unkey waits out the tail, then the lead.
+    device.report_unkey();
+    let start = Instant::now();
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    assert_eq!(&mock.written_data()[..], b"TX1\rTX1\rTX2\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn send_raw_rejects_injection() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    let result = device.send_raw("TX1\rTX2").await;
+    assert!(matches!(result, Err(Error::InvalidParameter(_))));
+    assert!(mock.written_data().is_empty());
+
+    device.send_raw_unchecked("TX1\rTX2").await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"TX1\rTX2\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn aux_validated_against_capabilities() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .capabilities(SwitchCapabilities {
+            aux_ports: 1,
+            aux_value_max: 15,
+            ..Default::default()
+        })
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        device.set_aux(2, 4).await,
+        Err(Error::InvalidParameter(_))
+    ));
+    assert!(matches!(
+        device.set_aux(0, 4).await,
+        Err(Error::InvalidParameter(_))
+    ));
+    assert!(matches!(
+        device.set_aux(1, 16).await,
+        Err(Error::InvalidParameter(_))
+    ));
+    assert!(matches!(
+        device.query_aux(2).await,
+        Err(Error::InvalidParameter(_))
+    ));
+    assert!(mock.written_data().is_empty());
+
+    device.set_aux(1, 15).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"AUX115\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn wide_aux_values_read_back() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .strict(true)
+        .capabilities(SwitchCapabilities {
+            aux_value_max: 1023,
+            ..Default::default()
+        })
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_aux_wide(1, 1000).await.unwrap();
+    mock.queue_read(b"AUX11000\r");
+    assert_eq!(device.query_aux_wide(1).await.unwrap(), 1000);
+    mock.queue_read(b"AUX11000\r");
+    assert!(matches!(device.query_aux(1).await, Err(Error::Protocol(_))));
+
+    device.enable_events(true).await.unwrap();
+    let mut events = device.subscribe_state();
+    mock.queue_read(b"$AUX2999\r");
+    assert_eq!(
+        events.recv().await.unwrap(),
+        SwitchEvent::AuxChanged {
+            port: 2,
+            value: 999,
+            origin: Origin::Device
+        }
+    );
+    assert_eq!(device.state().aux.get(&2), Some(&999));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn rx_mode_validated_against_capabilities() {
+    let mono_only = SwitchCapabilities {
+        stereo: false,
+        reverse_stereo: false,
+        ..Default::default()
+    };
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .capabilities(mono_only.clone())
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        device.set_rx(Radio::Radio1, RxMode::Stereo).await,
+        Err(Error::Unsupported(_))
+    ));
+    assert!(matches!(
+        device.set_rx(Radio::Radio1, RxMode::ReverseStereo).await,
+        Err(Error::Unsupported(_))
+    ));
+    assert!(mock.written_data().is_empty());
+    device.close().await.unwrap();
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .capabilities(mono_only)
+        .best_effort_rx(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    let mut rx = device.subscribe();
+    device.set_rx(Radio::Radio2, RxMode::Stereo).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"RX2\r");
+    match rx.recv().await.unwrap() {
+        SwitchEvent::RxChanged { mode, .. } => assert_eq!(mode, RxMode::Mono),
+        other => panic!("expected RxChanged, got {other:?}"),
+    }
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn connected_replayed_to_each_connection_subscriber() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    let mut raw = device.subscribe();
+    let mut first = device.subscribe_connection();
+    let mut second = device.subscribe_connection();
+    let mut state = device.subscribe_state();
+
+    assert!(matches!(first.try_recv(), Ok(SwitchEvent::Connected)));
+    assert!(matches!(second.try_recv(), Ok(SwitchEvent::Connected)));
+    assert!(first.try_recv().is_err());
+    assert!(raw.try_recv().is_err());
+    assert!(state.try_recv().is_err());
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn emit_connected_disabled() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .emit_connected(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    let mut rx = device.subscribe_connection();
+    assert!(rx.try_recv().is_err());
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn filtered_subscriptions_split_lifecycle_and_state() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    let mut connection = device.subscribe_connection();
+    let mut state = device.subscribe_state();
+
+    device.set_tx(Radio::Radio2).await.unwrap();
+    device.close().await.unwrap();
+
+    assert!(matches!(
+        connection.recv().await.unwrap(),
+        SwitchEvent::Connected
+    ));
+    assert!(matches!(
+        connection.recv().await.unwrap(),
+        SwitchEvent::Disconnected
+    ));
+
+    match state.recv().await.unwrap() {
+        SwitchEvent::TxChanged { radio, .. } => assert_eq!(radio, Radio::Radio2),
+        other => panic!("expected TxChanged, got {other:?}"),
+    }
+    assert!(state.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn advisory_lock_prevents_double_open() {
+    let dir = std::env::temp_dir().join(format!("otrsp-lock-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let first = OtrspBuilder::new("/dev/ttyMOCK0")
+        .query_name(false)
+        .advisory_lock(&dir)
+        .build_with_port(MockPort::new())
+        .await
+        .unwrap();
+
+    let second = OtrspBuilder::new("/dev/ttyMOCK0")
+        .query_name(false)
+        .advisory_lock(&dir)
+        .build_with_port(MockPort::new())
+        .await;
+    match second {
+        Err(Error::Transport(msg)) => assert!(msg.contains("in use"), "unexpected: {msg}"),
+        Err(other) => panic!("expected Error::Transport, got {other:?}"),
+        Ok(_) => panic!("expected second open to fail"),
+    }
+
+    // Dropping the first device releases the lock.
+    first.close().await.unwrap();
+    drop(first);
+
+    let third = OtrspBuilder::new("/dev/ttyMOCK0")
+        .query_name(false)
+        .advisory_lock(&dir)
+        .build_with_port(MockPort::new())
+        .await
+        .unwrap();
+    third.close().await.unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn port_lock_path_convention() {
+    use std::path::Path;
+
+    assert_eq!(
+        otrsp::transport::PortLock::lock_path(Path::new("/tmp"), "/dev/ttyUSB0"),
+        Path::new("/tmp/otrsp-ttyUSB0.lock")
+    );
+    assert_eq!(
+        otrsp::transport::PortLock::lock_path(Path::new("/tmp"), "COM3"),
+        Path::new("/tmp/otrsp-COM3.lock")
+    );
+}
+
+#[tokio::test]
+async fn reboot_detected_after_unresponsive_queries() {
+    let mock = MockPort::new();
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .detect_resets(1)
+        .emit_connected(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_tx(Radio::Radio2).await.unwrap();
+    device.set_aux(1, 4).await.unwrap();
+    let mut rx = device.subscribe_connection();
+
+    // The box goes quiet...
+    assert!(device.query_aux(1).await.unwrap_err().is_timeout());
+
+    // ...then answers again (after the post-timeout drain), followed by
+    // the re-identification handshake.
+    let mock2 = mock.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        mock2.queue_read(b"AUX10\rNAMESO2RDUINO\r");
+    });
+    assert_eq!(device.query_aux(1).await.unwrap(), 0);
+
+    assert!(matches!(rx.try_recv(), Ok(SwitchEvent::DeviceReset)));
+    assert_eq!(device.state().name.as_deref(), Some("SO2RDUINO"));
+
+    let written = mock.written_data();
+    assert_eq!(
+        &written[..],
+        b"TX2\rAUX14\r?AUX1\r?AUX1\r?NAME\rTX2\rAUX14\r"
+    );
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn band_follower_writes_only_settled_values() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use otrsp::follower::BandFollower;
+
+    let mock = MockPort::new();
+    let device = Arc::new(
+        OtrspBuilder::new("/dev/mock")
+            .query_name(false)
+            .build_with_port(mock.clone())
+            .await
+            .unwrap(),
+    );
+
+    // 40m -> 3, 20m -> 5
+    let follower = BandFollower::builder(|hz| match hz {
+        7_000_000..=7_300_000 => Some(3),
+        14_000_000..=14_350_000 => Some(5),
+        _ => None,
+    })
+    .settle(Duration::from_millis(50))
+    .spawn(device.clone());
+
+    // Tuning back and forth across the 20m band edge.
+    for hz in [14_001_000, 13_999_000, 14_000_500, 13_990_000, 14_010_000] {
+        follower.update(Radio::Radio1, hz);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    follower.update(Radio::Radio2, 7_025_000);
+    tokio::time::sleep(Duration::from_millis(150)).await;
+
+    // Re-reporting a settled band writes nothing new.
+    follower.update(Radio::Radio1, 14_200_000);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(&mock.written_data()[..], b"AUX15\rAUX23\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn alternator_flips_tx_on_a_cadence() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use otrsp::alternator::{Alternator, AlternatorEvent};
+
+    let mock = MockPort::new();
+    let device = Arc::new(
+        OtrspBuilder::new("/dev/mock")
+            .query_name(false)
+            .build_with_port(mock.clone())
+            .await
+            .unwrap(),
+    );
+
+    let alternator = Alternator::builder(Duration::from_millis(40)).spawn(device.clone());
+    let mut events = alternator.subscribe();
+    let radio = |radio| AlternatorEvent::Alternated { radio };
+    assert_eq!(events.recv().await.unwrap(), radio(Radio::Radio1));
+    assert_eq!(events.recv().await.unwrap(), radio(Radio::Radio2));
+
+    alternator.pause();
+    assert_eq!(events.recv().await.unwrap(), AlternatorEvent::Paused);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(&mock.written_data()[..], b"TX1\rTX2\r");
+
+    // A manual pick while paused switches at once and stays paused.
+    alternator.select(Radio::Radio1);
+    assert_eq!(
+        events.recv().await.unwrap(),
+        AlternatorEvent::Selected {
+            radio: Radio::Radio1
+        }
+    );
+    alternator.resume();
+    assert_eq!(events.recv().await.unwrap(), AlternatorEvent::Resumed);
+    assert_eq!(events.recv().await.unwrap(), radio(Radio::Radio2));
+    assert_eq!(&mock.written_data()[..], b"TX1\rTX2\rTX1\rTX2\r");
+
+    drop(alternator);
+    device.close().await.unwrap();
+}
+
+#[test]
+fn n1mm_radio_info_parsing() {
+    use otrsp::n1mm::{RadioInfo, parse_radio_info};
+
+    let packet = r#"<?xml version="1.0" encoding="utf-8"?>
+<RadioInfo>
+  <app>N1MM</app>
+  <StationName>SO2R</StationName>
+  <RadioNr>2</RadioNr>
+  <Freq>1402500</Freq>
+  <TXFreq>1402500</TXFreq>
+  <Mode>CW</Mode>
+</RadioInfo>"#;
+    assert_eq!(
+        parse_radio_info(packet),
+        Some(RadioInfo {
+            radio: Radio::Radio2,
+            freq_hz: 14_025_000
+        })
+    );
+
+    assert_eq!(parse_radio_info("<contactinfo></contactinfo>"), None);
+    assert_eq!(
+        parse_radio_info("<RadioInfo><RadioNr>3</RadioNr><Freq>700000</Freq></RadioInfo>"),
+        None
+    );
+}
+
+#[tokio::test]
+async fn n1mm_listener_drives_band_follower() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use otrsp::follower::BandFollower;
+    use otrsp::n1mm::N1mmListener;
+
+    let mock = MockPort::new();
+    let device = Arc::new(
+        OtrspBuilder::new("/dev/mock")
+            .query_name(false)
+            .build_with_port(mock.clone())
+            .await
+            .unwrap(),
+    );
+
+    let follower = BandFollower::builder(|hz| (hz / 1_000_000 == 7).then_some(3))
+        .settle(Duration::from_millis(10))
+        .spawn(device.clone());
+    let listener = N1mmListener::bind("127.0.0.1:0", follower).await.unwrap();
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket
+        .send_to(
+            b"<RadioInfo><RadioNr>1</RadioNr><Freq>702500</Freq></RadioInfo>",
+            listener.local_addr(),
+        )
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(&mock.written_data()[..], b"AUX13\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn audit_log_records_changes() {
+    use std::time::Duration;
+
+    let path = std::env::temp_dir().join(format!("otrsp-audit-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .audit_log(&path)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_tx(Radio::Radio2).await.unwrap();
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    device.set_aux(1, 4).await.unwrap();
+    device.close().await.unwrap();
+    drop(device);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let journal = std::fs::read_to_string(&path).unwrap();
+    let events: Vec<&str> = journal
+        .lines()
+        .map(|line| {
+            assert!(line.starts_with(r#"{"ts":"#), "{line}");
+            &line[line.find(r#""event""#).unwrap()..]
+        })
+        .collect();
+    assert_eq!(
+        &events[..5],
+        [
+            r#""event":"open","port":"/dev/mock"}"#,
+            r#""event":"connected"}"#,
+            r#""event":"tx","radio":2,"origin":"host"}"#,
+            r#""event":"rx","radio":1,"mode":"stereo","origin":"host"}"#,
+            r#""event":"aux","port":1,"value":4,"origin":"host"}"#,
+        ]
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn timeline_summaries_and_exports() {
+    use std::time::Duration;
+
+    use otrsp::timeline::{Timeline, TimelineEntry};
+
+    let hour = 3_600_000;
+    let tx = |ts_ms, radio| TimelineEntry {
+        ts_ms,
+        event: SwitchEvent::TxChanged {
+            radio,
+            origin: Origin::Host,
+        },
+    };
+    let timeline = Timeline::new(vec![
+        tx(hour + 60_000, Radio::Radio2),
+        tx(hour, Radio::Radio1),
+        tx(hour + 90_000, Radio::Radio1),
+        tx(2 * hour + 1_000, Radio::Radio2),
+        TimelineEntry {
+            ts_ms: 2 * hour + 11_000,
+            event: SwitchEvent::Disconnected,
+        },
+    ]);
+
+    assert_eq!(timeline.entries()[0].ts_ms, hour);
+    assert_eq!(
+        timeline.focus_time(Radio::Radio2),
+        Duration::from_millis(30_000 + 10_000)
+    );
+    assert_eq!(
+        timeline.focus_time(Radio::Radio1),
+        Duration::from_millis(60_000 + hour - 89_000)
+    );
+
+    let per_hour: Vec<_> = timeline.tx_switches_per_hour().into_iter().collect();
+    assert_eq!(per_hour, [(3600, 2), (7200, 1)]);
+
+    let csv = timeline.to_csv();
+    assert!(csv.starts_with("ts_ms,event,radio,mode,port,value,origin\n3600000,tx,1,,,,host\n"));
+    assert!(csv.ends_with("7211000,disconnected,,,,,\n"));
+    assert!(
+        timeline
+            .to_jsonl()
+            .starts_with("{\"ts\":3600000,\"event\":\"tx\",\"radio\":1,\"origin\":\"host\"}\n")
+    );
+}
+
+#[tokio::test]
+async fn timeline_from_journal_and_recorder() {
+    use std::time::Duration;
+
+    use otrsp::timeline::{Timeline, TimelineRecorder};
+
+    let path = std::env::temp_dir().join(format!("otrsp-timeline-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .emit_connected(false)
+        .audit_log(&path)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    let recorder = TimelineRecorder::spawn(device.subscribe(), 2);
+
+    device.set_tx(Radio::Radio2).await.unwrap();
+    device
+        .set_rx(Radio::Radio1, RxMode::ReverseStereo)
+        .await
+        .unwrap();
+    device.set_aux(2, 9).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let recent: Vec<_> = recorder
+        .timeline()
+        .entries()
+        .iter()
+        .map(|e| e.event.clone())
+        .collect();
+    assert_eq!(
+        recent,
+        [
+            SwitchEvent::RxChanged {
+                radio: Radio::Radio1,
+                mode: RxMode::ReverseStereo,
+                origin: Origin::Host
+            },
+            SwitchEvent::AuxChanged {
+                port: 2,
+                value: 9,
+                origin: Origin::Host
+            },
+        ]
+    );
+
+    let loaded = Timeline::from_journal(&path).unwrap();
+    let events: Vec<_> = loaded.entries().iter().map(|e| e.event.clone()).collect();
+    assert_eq!(
+        events[0],
+        SwitchEvent::TxChanged {
+            radio: Radio::Radio2,
+            origin: Origin::Host
+        }
+    );
+    assert_eq!(events.len(), 3);
+
+    device.close().await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn failover_replays_state_onto_backup() {
+    use std::time::Duration;
+
+    use otrsp::failover::FailoverSwitch;
+
+    let primary_port = MockPort::new();
+    let backup_port = MockPort::new();
+    let connect = |port: MockPort| async move {
+        OtrspBuilder::new("/dev/mock")
+            .query_name(false)
+            .emit_connected(false)
+            .build_with_port(port)
+            .await
+            .unwrap()
+    };
+    let switch = FailoverSwitch::new(
+        Box::new(connect(primary_port.clone()).await),
+        Box::new(connect(backup_port.clone()).await),
+    );
+    let mut events = switch.subscribe_connection();
+
+    switch.set_tx(Radio::Radio2).await.unwrap();
+    switch.set_aux(1, 5).await.unwrap();
+    assert!(!switch.is_failed_over());
+    assert_eq!(&primary_port.written_data()[..], b"TX2\rAUX15\r");
+
+    primary_port.close();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    switch.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    assert!(switch.is_failed_over());
+    assert_eq!(&backup_port.written_data()[..], b"TX2\rAUX15\rRX1S\r");
+    assert_eq!(switch.state().rx, Some((Radio::Radio1, RxMode::Stereo)));
+
+    // The primary's disconnect is still forwarded, then the failover.
+    for expected in [SwitchEvent::Disconnected, SwitchEvent::FailedOver] {
+        let event = tokio::time::timeout(Duration::from_secs(1), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event, expected);
+    }
+
+    switch.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn failover_toggles_from_cached_state() {
+    use otrsp::failover::FailoverSwitch;
+
+    let primary_port = MockPort::new();
+    let connect = |port: MockPort| async move {
+        OtrspBuilder::new("/dev/mock")
+            .query_name(false)
+            .emit_connected(false)
+            .build_with_port(port)
+            .await
+            .unwrap()
+    };
+    let switch = FailoverSwitch::new(
+        Box::new(connect(primary_port.clone()).await),
+        Box::new(connect(MockPort::new()).await),
+    );
+
+    switch.set_tx(Radio::Radio1).await.unwrap();
+    switch.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    assert_eq!(switch.other_radio().await.unwrap(), Radio::Radio2);
+    assert_eq!(switch.toggle_tx().await.unwrap(), Radio::Radio2);
+    assert_eq!(switch.swap().await.unwrap(), Radio::Radio1);
+    assert_eq!(switch.state().tx, Some(Radio::Radio1));
+
+    // No ?TX or ?RX queries went to the primary.
+    assert_eq!(
+        &primary_port.written_data()[..],
+        b"TX1\rRX1S\rTX2\rTX1\rRX1S\r"
+    );
+    assert!(!switch.is_failed_over());
+    switch.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn device_notifications_update_state() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    mock.queue_read(b"NAMESO2Rduino\rEVENT1\rPTT0\r");
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .negotiate(true)
+        .emit_connected(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    assert!(device.features().events);
+    let mut rx = device.subscribe();
+
+    device.set_tx(Radio::Radio1).await.unwrap();
+    // Front-panel changes; the repeated $TX2 is not a change.
+    mock.queue_read(b"$TX2\r$AUX13\r$TX2\r");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let events: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+    assert_eq!(
+        events,
+        [
+            SwitchEvent::TxChanged {
+                radio: Radio::Radio1,
+                origin: Origin::Host
+            },
+            SwitchEvent::TxChanged {
+                radio: Radio::Radio2,
+                origin: Origin::Device
+            },
+            SwitchEvent::AuxChanged {
+                port: 1,
+                value: 3,
+                origin: Origin::Device
+            },
+        ]
+    );
+    let state = device.state();
+    assert_eq!(state.tx, Some(Radio::Radio2));
+    assert_eq!(state.aux.get(&1), Some(&3));
+
+    device.close().await.unwrap();
+}
+
+#[test]
+fn state_diff_emits_only_changes() {
+    use otrsp::protocol::Command;
+    use otrsp::state::SwitchState;
+
+    let mut current = SwitchState {
+        tx: Some(Radio::Radio1),
+        rx: Some((Radio::Radio1, RxMode::Stereo)),
+        ..Default::default()
+    };
+    current.aux.insert(1, 3);
+
+    let mut target = SwitchState {
+        tx: Some(Radio::Radio2),
+        rx: Some((Radio::Radio1, RxMode::Stereo)),
+        ..Default::default()
+    };
+    target.aux.insert(1, 3);
+    target.aux.insert(2, 7);
+
+    assert_eq!(
+        SwitchState::diff(&current, &target),
+        [
+            Command::Tx(Radio::Radio2),
+            Command::Aux { port: 2, value: 7 }
+        ]
+    );
+    assert!(SwitchState::diff(&target, &target).is_empty());
+    // Unset target fields are left alone.
+    assert!(SwitchState::diff(&current, &SwitchState::default()).is_empty());
+    assert_eq!(SwitchState::diff(&SwitchState::default(), &target).len(), 4);
+}
+
+#[tokio::test]
+async fn apply_state_sends_only_needed_commands() {
+    use otrsp::state::SwitchState;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_tx(Radio::Radio1).await.unwrap();
+    device.set_aux(1, 3).await.unwrap();
+
+    let mut target = SwitchState {
+        tx: Some(Radio::Radio1),
+        rx: Some((Radio::Radio2, RxMode::Mono)),
+        ..Default::default()
+    };
+    target.aux.insert(1, 4);
+    let sent = device.apply_state(&target).await.unwrap();
+
+    assert_eq!(sent.len(), 2);
+    assert_eq!(&mock.written_data()[..], b"TX1\rAUX13\rRX2\rAUX14\r");
+    assert_eq!(device.state().aux.get(&1), Some(&4));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn set_state_sends_the_difference_in_one_write() {
+    use otrsp::state::SwitchState;
+    use otrsp::tap::WireDirection;
+    use std::sync::{Arc, Mutex};
+
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let log = sent.clone();
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .on_wire(move |dir, bytes| {
+            if dir == WireDirection::Sent {
+                log.lock().unwrap().push(bytes.to_vec());
+            }
+        })
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio1).await.unwrap();
+
+    let mut saved = SwitchState {
+        tx: Some(Radio::Radio2),
+        rx: Some((Radio::Radio2, RxMode::Stereo)),
+        ..Default::default()
+    };
+    saved.aux.insert(2, 7);
+    let commands = device.set_state(&saved).await.unwrap();
+    assert_eq!(commands.len(), 3);
+    assert_eq!(sent.lock().unwrap().last().unwrap(), b"TX2\rRX2S\rAUX27\r");
+    assert_eq!(device.state().aux.get(&2), Some(&7));
+
+    // Already there: nothing to send.
+    assert!(device.set_state(&saved).await.unwrap().is_empty());
+    assert_eq!(sent.lock().unwrap().len(), 2);
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn toggle_and_swap_flip_to_the_other_radio() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_tx(Radio::Radio1).await.unwrap();
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    assert_eq!(device.other_radio().await.unwrap(), Radio::Radio2);
+    assert_eq!(device.toggle_tx().await.unwrap(), Radio::Radio2);
+    assert_eq!(device.swap().await.unwrap(), Radio::Radio1);
+    assert_eq!(device.state().rx, Some((Radio::Radio1, RxMode::Stereo)));
+
+    assert_eq!(&mock.written_data()[..], b"TX1\rRX1S\rTX2\rTX1\rRX1S\r");
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn rx_follows_tx_when_enabled() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .rx_follows_tx(RxFollow::Mono)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"TX2\rRX2\r");
+    device.close().await.unwrap();
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .rx_follows_tx(RxFollow::KeepMode)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    device.set_tx(Radio::Radio2).await.unwrap();
+    device.swap().await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"RX1S\rTX2\rRX2S\rTX1\rRX1S\r");
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn rx_follows_tx_skipped_when_restoring_state() {
+    use otrsp::state::SwitchState;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .rx_follows_tx(RxFollow::KeepMode)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio1).await.unwrap();
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    let sent = mock.written_data().len();
+
+    let target = SwitchState {
+        tx: Some(Radio::Radio2),
+        rx: Some((Radio::Radio1, RxMode::Stereo)),
+        ..Default::default()
+    };
+    device.apply_state(&target).await.unwrap();
+    assert_eq!(&mock.written_data()[sent..], b"TX2\r");
+    assert_eq!(device.state().rx, Some((Radio::Radio1, RxMode::Stereo)));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn switch_impls_for_pointer_types() {
+    use std::sync::Arc;
+
+    async fn select_radio2(switch: impl So2rSwitch) {
+        switch.set_tx(Radio::Radio2).await.unwrap();
+    }
+
+    let mock = MockPort::new();
+    let device = Arc::new(
+        OtrspBuilder::new("/dev/mock")
+            .query_name(false)
+            .build_with_port(mock.clone())
+            .await
+            .unwrap(),
+    );
+
+    select_radio2(&*device).await;
+    select_radio2(device.clone()).await;
+    let boxed: Box<dyn So2rSwitch> = Box::new(device.clone());
+    select_radio2(boxed).await;
+
+    assert_eq!(&mock.written_data()[..], b"TX2\rTX2\rTX2\r");
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn offline_queue_replays_after_glitch() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .offline_queue(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_tx(Radio::Radio1).await.unwrap();
+
+    mock.close();
+    device.set_tx(Radio::Radio2).await.unwrap();
+    device.set_aux(1, 5).await.unwrap();
+    device.set_aux(1, 6).await.unwrap();
+    let backlog = device.offline_backlog();
+    assert_eq!(backlog.tx, Some(Radio::Radio2));
+    assert_eq!(backlog.aux.get(&1), Some(&6));
+    assert_eq!(device.state().tx, Some(Radio::Radio1));
+
+    // Link recovers: the net offline result goes out before the new command.
+    mock.reopen();
+    device.set_rx(Radio::Radio1, RxMode::Mono).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"TX1\rTX2\rAUX16\rRX1\r");
+    assert_eq!(device.offline_backlog(), Default::default());
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn offline_queue_replays_on_reconnect() {
+    use otrsp::transport::{BoxedTransport, Connector};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    struct Replug {
+        mock: MockPort,
+        plugged: Arc<AtomicBool>,
+    }
+
+    #[async_trait::async_trait]
+    impl Connector for Replug {
+        async fn connect(&self) -> otrsp::Result<BoxedTransport> {
+            if !self.plugged.load(Ordering::SeqCst) {
+                return Err(Error::Transport("device not found".into()));
+            }
+            self.mock.reopen();
+            Ok(Box::new(self.mock.clone()))
+        }
+    }
+
+    let mock = MockPort::new();
+    let plugged = Arc::new(AtomicBool::new(true));
+    let connector = Replug {
+        mock: mock.clone(),
+        plugged: plugged.clone(),
+    };
+    let device = OtrspBuilder::from_connector("usb", connector)
+        .query_name(false)
+        .emit_connected(false)
+        .reconnect(true)
+        .reconnect_delay(Duration::from_millis(20))
+        .offline_queue(true)
+        .build()
+        .await
+        .unwrap();
+    let mut watch = device.watch_state();
+    device.set_tx(Radio::Radio1).await.unwrap();
+
+    plugged.store(false, Ordering::SeqCst);
+    mock.close();
+    watch.wait_for(|s| !s.connected).await.unwrap();
+
+    // The write waits for the link and times out; it is kept for replay.
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert_eq!(device.offline_backlog().tx, Some(Radio::Radio2));
+    assert_eq!(device.state().tx, Some(Radio::Radio1));
+
+    // Plugging back in replays the backlog without another command.
+    plugged.store(true, Ordering::SeqCst);
+    tokio::time::timeout(
+        Duration::from_secs(2),
+        watch.wait_for(|s| s.tx == Some(Radio::Radio2)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(device.offline_backlog(), Default::default());
+    assert!(mock.written_data().ends_with(b"TX2\r"));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn offline_queue_disabled_reports_errors() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    mock.close();
+    assert!(device.set_tx(Radio::Radio2).await.is_err());
+    assert_eq!(device.offline_backlog(), Default::default());
+}
+
+#[tokio::test]
+async fn device_input_events() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    mock.queue_read(b"NAMESO2Rduino\rEVENT1\rPTT0\r");
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .negotiate(true)
+        .emit_connected(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    let mut rx = device.subscribe();
+
+    mock.queue_read(b"$FS1\r$FS0\r$PTT2\r$PTT0\r");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let events: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+    assert_eq!(
+        events,
+        [
+            SwitchEvent::FootswitchChanged { pressed: true },
+            SwitchEvent::FootswitchChanged { pressed: false },
+            SwitchEvent::TxChanged {
+                radio: Radio::Radio2,
+                origin: Origin::Device
+            },
+            SwitchEvent::PttChanged {
+                radio: Some(Radio::Radio2)
+            },
+            SwitchEvent::PttChanged { radio: None },
+        ]
+    );
+    assert!(events[0].is_input_event());
+    assert_eq!(events[3].radio(), Some(Radio::Radio2));
+    assert_eq!(device.state().tx, Some(Radio::Radio2));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn query_raw_returns_response_line() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    mock.queue_read(b"FWV2.1\r");
+    assert_eq!(device.query_raw("?FW").await.unwrap(), "FWV2.1");
+    assert_eq!(&mock.written_data()[..], b"?FW\r");
+
+    assert!(matches!(
+        device.query_raw("?FW\rTX2").await,
+        Err(Error::InvalidParameter(_))
+    ));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn late_answer_to_raw_query_is_dropped() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    assert!(device.query_raw("?FW").await.unwrap_err().is_timeout());
+    // The answer turns up while the link is idle, after the query gave up.
+    mock.queue_read(b"FWV2.1\r");
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    mock.expect(b"?SN\r").respond(b"SN1234\r");
+    assert_eq!(device.query_raw("?SN").await.unwrap(), "SN1234");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn firmware_version_queried_during_build() {
+    let mock = MockPort::new();
+    mock.queue_read(b"NAMESO2Rduino\rVERSION1.4\r");
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_version(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    assert_eq!(device.info().firmware.as_deref(), Some("1.4"));
+    assert_eq!(&mock.written_data()[..], b"?NAME\r?VERSION\r");
+
+    mock.queue_read(b"VERSION1.5\r");
+    assert_eq!(device.device_version().await.unwrap(), "1.5");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn firmware_unknown_without_version_query() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    assert_eq!(device.info().firmware, None);
+    assert!(mock.written_data().is_empty());
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn echo_mode_skips_echoed_commands() {
+    let mock = MockPort::new();
+    mock.queue_read(b"?NAME\rNAMEHomebrew\r");
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .echo_mode(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    assert_eq!(device.info().name, "Homebrew");
+
+    // The echo of a plain write is still buffered when the next query runs.
+    device.set_tx(Radio::Radio2).await.unwrap();
+    mock.queue_read(b"TX2\r?AUX1\rAUX13\r");
+    assert_eq!(device.query_aux(1).await.unwrap(), 3);
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn query_tx_reads_back_focus() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .emit_connected(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    let mut rx = device.subscribe();
+
+    mock.queue_read(b"TX2\r");
+    assert_eq!(device.query_tx().await.unwrap(), Radio::Radio2);
+    assert_eq!(&mock.written_data()[..], b"?TX\r");
+    assert_eq!(device.state().tx, Some(Radio::Radio2));
+    assert_eq!(
+        rx.try_recv().unwrap(),
+        SwitchEvent::TxChanged {
+            radio: Radio::Radio2,
+            origin: Origin::Device
+        }
+    );
+
+    mock.queue_read(b"TXX\r");
+    assert!(matches!(device.query_tx().await, Err(Error::Protocol(_))));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn query_rx_reads_back_routing() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    mock.queue_read(b"RX2S\r");
+    assert_eq!(
+        device.query_rx().await.unwrap(),
+        (Radio::Radio2, RxMode::Stereo)
+    );
+    assert_eq!(&mock.written_data()[..], b"?RX\r");
+    assert_eq!(device.state().rx, Some((Radio::Radio2, RxMode::Stereo)));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn enable_events_controls_reporting() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .emit_connected(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    let mut rx = device.subscribe();
+
+    device.enable_events(true).await.unwrap();
+    assert!(device.state().events);
+    mock.queue_read(b"$TX2\r");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        rx.try_recv().unwrap(),
+        SwitchEvent::TxChanged {
+            radio: Radio::Radio2,
+            origin: Origin::Device
+        }
+    );
+
+    device.enable_events(false).await.unwrap();
+    assert!(!device.state().events);
+    mock.queue_read(b"$TX1\r");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(rx.try_recv().is_err());
+
+    assert_eq!(&mock.written_data()[..], b"EVENT1\rEVENT0\r");
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn keying_routing_commands() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .emit_connected(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    let mut rx = device.subscribe();
+
+    device.set_keying(Radio::Radio2).await.unwrap();
+    assert_eq!(device.state().keying, Some(Radio::Radio2));
+    assert_eq!(
+        rx.try_recv().unwrap(),
+        SwitchEvent::KeyingChanged {
+            radio: Radio::Radio2,
+            origin: Origin::Host
+        }
+    );
+
+    mock.queue_read(b"CR1\r");
+    assert_eq!(device.query_keying().await.unwrap(), Radio::Radio1);
+    assert_eq!(&mock.written_data()[..], b"CR2\r?CR\r");
+    assert_eq!(
+        rx.try_recv().unwrap(),
+        SwitchEvent::KeyingChanged {
+            radio: Radio::Radio1,
+            origin: Origin::Device
+        }
+    );
+
+    device.enable_events(true).await.unwrap();
+    mock.queue_read(b"$CR2\r");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        rx.try_recv().unwrap(),
+        SwitchEvent::KeyingChanged {
+            radio: Radio::Radio2,
+            origin: Origin::Device
+        }
+    );
+    assert_eq!(device.state().keying, Some(Radio::Radio2));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn permissive_mode_accepts_sloppy_responses() {
+    let mock = MockPort::new();
+    mock.queue_read(b"name Arduino SO2R \r");
+    let device = OtrspBuilder::new("/dev/mock")
+        .permissive(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    assert_eq!(device.info().name, "Arduino SO2R");
+
+    mock.queue_read(b"aux14  \r");
+    assert_eq!(device.query_aux(1).await.unwrap(), 4);
+    mock.queue_read(b"rx2s\r");
+    assert_eq!(
+        device.query_rx().await.unwrap(),
+        (Radio::Radio2, RxMode::Stereo)
+    );
+
+    device.close().await.unwrap();
+
+    // Without it the lowercase answer is rejected.
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    mock.queue_read(b"aux14\r");
+    assert!(matches!(device.query_aux(1).await, Err(Error::Protocol(_))));
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn strict_mode_rejects_deviating_responses() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .strict(true)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    mock.queue_read(b"AUX14\r");
+    assert_eq!(device.query_aux(1).await.unwrap(), 4);
+
+    mock.queue_read(b"AUX14\r\n");
+    let err = device.query_aux(1).await.unwrap_err();
+    assert!(err.to_string().contains("missing CR terminator"), "{err}");
+
+    mock.queue_read(b"AUX14 \r");
+    assert!(matches!(device.query_aux(1).await, Err(Error::Protocol(_))));
+    device.close().await.unwrap();
+
+    // The same CRLF answer is fine without strict mode.
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .strict(true)
+        .strict(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    mock.queue_read(b"AUX14\r\n");
+    assert_eq!(device.query_aux(1).await.unwrap(), 4);
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn wide_aux_values_gated_by_capabilities() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    let result = device.set_aux_wide(1, 300).await;
+    assert!(matches!(result, Err(Error::InvalidParameter(_))));
+    device.set_aux_wide(1, 200).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"AUX1200\r");
+    device.close().await.unwrap();
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .capabilities(SwitchCapabilities {
+            aux_value_max: 1023,
+            ..Default::default()
+        })
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_aux_wide(2, 1000).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"AUX21000\r");
+    assert_eq!(device.state().aux.get(&2), Some(&1000));
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn set_band_writes_bcd_code() {
+    use otrsp::Band;
+    use otrsp::protocol::BcdMap;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .bcd_map(BcdMap::yaesu().with(Band::M60, 11))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_band(Radio::Radio1, Band::M20).await.unwrap();
+    device.set_band(Radio::Radio2, Band::M160).await.unwrap();
+    device.set_band(Radio::Radio1, Band::M60).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"AUX15\rAUX21\rAUX111\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn set_band_uses_the_radios_decoder() {
+    use otrsp::Band;
+    use otrsp::protocol::BcdMap;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .capabilities(SwitchCapabilities {
+            aux_ports: 3,
+            ..Default::default()
+        })
+        .band_decoder(Radio::Radio2, 3, BcdMap::yaesu().with(Band::M20, 9))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_band(Radio::Radio1, Band::M20).await.unwrap();
+    device.set_band(Radio::Radio2, Band::M20).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"AUX15\rAUX39\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn vendor_extensions() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.register_command("led", |args| Ok(format!("LED{args}\r").into_bytes()));
+    device.register_command("temp", |_| Ok(b"?TEMP\r".to_vec()));
+    device.register_parser("TEMP", |line| {
+        std::str::from_utf8(&line[4..])
+            .ok()
+            .and_then(|v| v.parse::<i16>().ok())
+            .ok_or_else(|| Error::Protocol("bad TEMP answer".into()))
+    });
+
+    device.vendor_command("led", "1").await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"LED1\r");
+
+    mock.queue_read(b"TEMP-12\r");
+    let temp: i16 = device.vendor_query("temp", "").await.unwrap();
+    assert_eq!(temp, -12);
+
+    mock.queue_read(b"TEMP21\r");
+    let wrong: Result<String, _> = device.vendor_query("temp", "").await;
+    assert!(matches!(wrong, Err(Error::Protocol(_))));
+
+    assert!(matches!(
+        device.vendor_command("fan", "").await,
+        Err(Error::Unsupported(_))
+    ));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn send_batch_single_write() {
+    use otrsp::protocol::Command;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    let mut events = device.subscribe();
+    let _ = events.try_recv(); // Connected
+
+    device
+        .send_batch(&[
+            Command::Tx(Radio::Radio2),
+            Command::Rx(Radio::Radio2, RxMode::Stereo),
+            Command::Aux { port: 2, value: 5 },
+        ])
+        .await
+        .unwrap();
+    assert_eq!(&mock.written_data()[..], b"TX2\rRX2S\rAUX25\r");
+
+    let state = device.state();
+    assert_eq!(state.tx, Some(Radio::Radio2));
+    assert_eq!(state.rx, Some((Radio::Radio2, RxMode::Stereo)));
+    assert_eq!(state.aux.get(&2), Some(&5));
+    assert!(matches!(
+        events.try_recv().unwrap(),
+        SwitchEvent::TxChanged {
+            radio: Radio::Radio2,
+            ..
+        }
+    ));
+
+    // Nothing is sent if any command is invalid.
+    for bad in [Command::QueryTx, Command::Aux { port: 9, value: 1 }] {
+        let result = device.send_batch(&[Command::Tx(Radio::Radio1), bad]).await;
+        assert!(matches!(result, Err(Error::InvalidParameter(_))));
+    }
+    assert_eq!(&mock.written_data()[..], b"TX2\rRX2S\rAUX25\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn skip_banner_before_answer() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    mock.queue_read(b"SO2R box booting\r\nfirmware ok\r\nNAMESO2RDUINO\r");
+    let device = OtrspBuilder::new("/dev/mock")
+        .skip_unexpected_lines(2, Duration::from_millis(500))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    assert_eq!(device.info().name, "SO2RDUINO");
+
+    // Past the line budget the next line is taken as the answer.
+    mock.queue_read(b"debug 1\rdebug 2\rdebug 3\rAUX14\r");
+    assert!(matches!(device.query_aux(1).await, Err(Error::Protocol(_))));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn name_query_retried_after_reset() {
+    let mock = MockPort::new();
+    // Device still booting: the first ?NAME goes unanswered.
+    let late = mock.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(1300)).await;
+        late.queue_read(b"NAMESO2RDUINO\r");
+    });
+
+    let device = OtrspBuilder::new("/dev/mock")
+        .settle_delay(std::time::Duration::from_millis(20))
+        .name_retries(2)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(device.info().name, "SO2RDUINO");
+    assert_eq!(&mock.written_data()[..], b"?NAME\r?NAME\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn ping_measures_round_trip() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    mock.queue_read(b"NAMESO2RDUINO\r");
+    let rtt = device.ping().await.unwrap();
+    assert!(rtt < std::time::Duration::from_secs(1));
+    assert_eq!(&mock.written_data()[..], b"?NAME\r");
+
+    assert!(matches!(device.ping().await, Err(Error::Timeout)));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn unknown_command_reply_is_unsupported_command() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    mock.queue_read(b"?\r");
+    let err = device.query_raw("?FW").await.unwrap_err();
+    assert!(err.is_unsupported());
+    assert!(matches!(err, Error::UnsupportedCommand { ref command } if command == "?FW"));
+
+    mock.queue_read(b"?\r");
+    assert!(matches!(
+        device.query_keying().await,
+        Err(Error::UnsupportedCommand { .. })
+    ));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn transcript_records_exchanges() {
+    let path = std::env::temp_dir().join(format!("otrsp-transcript-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mock = MockPort::new();
+    mock.queue_read(b"NAMESO2RDUINO\r");
+    let device = OtrspBuilder::new("/dev/mock")
+        .transcript(2)
+        .transcript_file(&path)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert!(device.query_aux(1).await.is_err());
+
+    // Only the two most recent exchanges are kept in memory.
+    let entries = device.transcript().unwrap().entries();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].command, b"TX2\r");
+    assert_eq!(entries[0].response, None);
+    assert_eq!(entries[1].command, b"?AUX1\r");
+    assert!(entries[1].error.is_some());
+
+    // The file is written in the background.
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let lines: Vec<String> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert!(
+        lines[0].contains(r#""command":"?NAME\u000d","response":"NAMESO2RDUINO\u000d""#),
+        "{}",
+        lines[0]
+    );
+    assert!(lines[2].contains(r#""error":"timeout waiting for response""#));
+
+    device.transcript().unwrap().clear();
+    assert!(device.transcript().unwrap().entries().is_empty());
+
+    device.close().await.unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn per_port_aux_limits() {
+    use otrsp::AuxLimit;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .capabilities(
+            SwitchCapabilities::default()
+                .with_aux_limit(1, AuxLimit::max(10))
+                .with_aux_limit(2, AuxLimit::allowed([0, 1, 2, 4, 8])),
+        )
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    for (port, value) in [(1, 11), (2, 3)] {
+        let result = device.set_aux(port, value).await;
+        assert!(matches!(result, Err(Error::InvalidParameter(_))), "{port}");
+    }
+    assert!(mock.written_data().is_empty());
+
+    device.set_aux(1, 10).await.unwrap();
+    device.set_aux(2, 8).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"AUX110\rAUX28\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn tcp_transport() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let bridge = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 6];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"?NAME\r");
+        socket.write_all(b"NAMESO2RDUINO\r").await.unwrap();
+        let mut buf = [0u8; 4];
+        socket.read_exact(&mut buf).await.unwrap();
+        buf
+    });
+
+    let device = OtrspBuilder::new_tcp("127.0.0.1", port)
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(device.info().name, "SO2RDUINO");
+    assert_eq!(
+        device.info().port.as_deref(),
+        Some(&*format!("127.0.0.1:{port}"))
+    );
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert_eq!(&bridge.await.unwrap(), b"TX2\r");
+
+    device.close().await.unwrap();
+
+    let result = OtrspBuilder::new_tcp("127.0.0.1", port).build().await;
+    assert!(matches!(result, Err(Error::Transport(_))));
+}
+
+#[test]
+fn list_ports_puts_usb_first() {
+    let ports = otrsp::transport::list_ports().unwrap();
+    let first_other = ports.iter().position(|p| !p.is_usb());
+    if let Some(i) = first_other {
+        assert!(ports[i..].iter().all(|p| !p.is_usb()));
+    }
+    assert!(
+        ports
+            .iter()
+            .all(|p| p.is_usb() == p.pid.is_some() && !p.name.is_empty())
+    );
+}
+
+#[test]
+fn port_filter_matches_and_ranks() {
+    use otrsp::discovery::PortFilter;
+    use otrsp::transport::PortInfo;
+
+    let port = |name: &str, id: Option<(u16, u16)>, serial: Option<&str>| PortInfo {
+        name: name.to_string(),
+        vid: id.map(|(vid, _)| vid),
+        pid: id.map(|(_, pid)| pid),
+        manufacturer: None,
+        product: None,
+        serial_number: serial.map(str::to_string),
+        bluetooth: false,
+    };
+    let ports = vec![
+        port("/dev/ttyUSB0", Some((0x0403, 0x6001)), Some("A1")),
+        port("/dev/ttyUSB1", Some((0x1A86, 0x7523)), None),
+        port("/dev/ttyUSB2", Some((0x0403, 0x6001)), Some("B2")),
+        port("/dev/ttyACM0", Some((0x1234, 0x5678)), Some("B2")),
+        port("/dev/ttyS0", None, None),
+    ];
+    let names = |ports: Vec<PortInfo>| ports.into_iter().map(|p| p.name).collect::<Vec<_>>();
+
+    assert_eq!(PortFilter::new().apply(ports.clone()).len(), 5);
+    assert_eq!(
+        names(PortFilter::known_adapters().apply(ports.clone())),
+        ["/dev/ttyUSB0", "/dev/ttyUSB1", "/dev/ttyUSB2"]
+    );
+    let filter = PortFilter::new()
+        .usb_id(0x0403, 0x6001)
+        .prefer_serial("B2")
+        .prefer_serial("A1");
+    assert_eq!(names(filter.apply(ports)), ["/dev/ttyUSB2", "/dev/ttyUSB0"]);
+
+    let builder = OtrspBuilder::new("/dev/null").port_filter(filter);
+    assert!(builder.discover().unwrap().iter().all(|p| p.is_usb()));
+}
+
+#[tokio::test]
+async fn detect_reports_scan() {
+    use otrsp::discovery::{DetectEvent, PortFilter};
+
+    // No port carries this ID, so the scan finds nothing.
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let result = OtrspBuilder::new("")
+        .port_filter(PortFilter::new().usb_id(0xFFFF, 0xFFFF))
+        .detect_progress(tx)
+        .detect()
+        .await;
+    assert!(matches!(result, Err(Error::Transport(_))));
+    assert_eq!(
+        rx.recv().await,
+        Some(DetectEvent::Scanning { ports: vec![] })
+    );
+    assert_eq!(rx.recv().await, None);
+}
+
+#[tokio::test]
+async fn wire_tap_sees_raw_bytes() {
+    use otrsp::tap::WireDirection;
+    use std::sync::{Arc, Mutex};
+
+    let wire = Arc::new(Mutex::new(Vec::new()));
+    let log = wire.clone();
+    let mock = MockPort::new();
+    mock.expect(b"?NAME\r").respond(b"NAMESO2RDUINO\r");
+    let device = OtrspBuilder::new("/dev/mock")
+        .on_wire(move |dir, bytes| log.lock().unwrap().push((dir, bytes.to_vec())))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio2).await.unwrap();
+
+    let wire = wire.lock().unwrap().clone();
+    let joined = |want: WireDirection| {
+        wire.iter()
+            .filter(|(dir, _)| *dir == want)
+            .flat_map(|(_, bytes)| bytes.clone())
+            .collect::<Vec<u8>>()
+    };
+    assert_eq!(joined(WireDirection::Sent), b"?NAME\rTX2\r");
+    assert_eq!(joined(WireDirection::Received), b"NAMESO2RDUINO\r");
+    assert_eq!(wire[0].0, WireDirection::Sent);
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn mock_expectations_answer_scripted_requests() {
+    let mock = MockPort::new();
+    mock.expect(b"?NAME\r").respond(b"NAMESO2RDUINO\r");
+    // Unordered: ?AUX2 may be asked before ?AUX1.
+    mock.expect(b"?AUX1\r").respond(b"AUX14\r");
+    mock.expect(b"?AUX2\r").respond(b"AUX27\r");
+    mock.expect(b"TX2\r");
+    let device = OtrspBuilder::new("/dev/mock")
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(device.query_aux(2).await.unwrap(), 7);
+    assert_eq!(device.query_aux(1).await.unwrap(), 4);
+    device.set_tx(Radio::Radio2).await.unwrap();
+    mock.verify();
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn mock_ordered_expectations_reject_reordering() {
+    let mock = MockPort::new();
+    mock.set_ordered(true);
+    mock.expect(b"?NAME\r").respond(b"NAMESO2RDUINO\r");
+    mock.expect(b"TX1\r");
+    mock.expect(b"TX2\r");
+    let device = OtrspBuilder::new("/dev/mock")
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio2).await.unwrap();
+    device.set_tx(Radio::Radio1).await.unwrap();
+    device.close().await.unwrap();
+
+    let verified = std::panic::catch_unwind(|| mock.verify());
+    let message = *verified.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains(r#"unmet ["TX2\\r"]"#), "{message}");
+    assert!(
+        message.contains(r#"unexpected writes ["TX2\\r"]"#),
+        "{message}"
+    );
+}
+
+#[tokio::test]
+async fn mock_latency_and_chunking_delay_answers() {
+    use std::time::{Duration, Instant};
+
+    let mock = MockPort::new();
+    mock.set_chunking(1, Duration::from_millis(10));
+    mock.expect(b"?NAME\r").respond(b"NAMESO2RDUINO\r");
+    let started = Instant::now();
+    let device = OtrspBuilder::new("/dev/mock")
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    // 14 bytes one every 10ms: the line is read in pieces.
+    assert!(started.elapsed() >= Duration::from_millis(130));
+    assert_eq!(device.info().name, "SO2RDUINO");
+
+    mock.set_chunking(usize::MAX, Duration::ZERO);
+    mock.set_latency(Duration::from_millis(100));
+    mock.expect(b"?AUX1\r").respond(b"AUX14\r");
+    let started = Instant::now();
+    assert_eq!(device.query_aux(1).await.unwrap(), 4);
+    assert!(started.elapsed() >= Duration::from_millis(100));
+    mock.verify();
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn in_memory_pair_with_emulator() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (port, switch) = otrsp::transport::pair();
+    // A minimal emulator: answer ?NAME, acknowledge nothing else.
+    let emulator = tokio::spawn(async move {
+        let (read, mut write) = tokio::io::split(switch);
+        let mut lines = BufReader::new(read);
+        let mut received = Vec::new();
+        loop {
+            let mut line = Vec::new();
+            if lines.read_until(b'\r', &mut line).await.unwrap() == 0 {
+                break received;
+            }
+            if line == b"?NAME\r" {
+                write.write_all(b"NAMEEMULATOR\r").await.unwrap();
+            }
+            received.push(line);
+        }
+    });
+
+    let device = OtrspBuilder::new("emulator")
+        .build_with_port(port)
+        .await
+        .unwrap();
+    assert_eq!(device.info().name, "EMULATOR");
+    device.set_tx(Radio::Radio2).await.unwrap();
+    device.close().await.unwrap();
+    drop(device);
+
+    let received = emulator.await.unwrap();
+    assert_eq!(received, [b"?NAME\r".to_vec(), b"TX2\r".to_vec()]);
+}
+
+#[tokio::test]
+async fn reconnecting_port_survives_unplug() {
+    use otrsp::transport::{BoxedTransport, Connector};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    /// A USB adapter that can be pulled and plugged back in.
+    struct Replug {
+        mock: MockPort,
+        plugged: Arc<AtomicBool>,
+    }
+
+    #[async_trait::async_trait]
+    impl Connector for Replug {
+        async fn connect(&self) -> otrsp::Result<BoxedTransport> {
+            if !self.plugged.load(Ordering::SeqCst) {
+                return Err(Error::Transport("device not found".into()));
+            }
+            self.mock.reopen();
+            Ok(Box::new(self.mock.clone()))
+        }
+    }
+
+    let mock = MockPort::new();
+    mock.queue_read(b"NAMESO2RDUINO\r");
+    let plugged = Arc::new(AtomicBool::new(true));
+    let connector = Replug {
+        mock: mock.clone(),
+        plugged: plugged.clone(),
+    };
+    let device = OtrspBuilder::from_connector("usb", connector)
+        .reconnect(true)
+        .reconnect_delay(Duration::from_millis(20))
+        .build()
+        .await
+        .unwrap();
+    let mut events = device.subscribe_connection();
+
+    plugged.store(false, Ordering::SeqCst);
+    mock.close();
+    let replug = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        plugged.store(true, Ordering::SeqCst);
+    });
+    // The write fails, waits out the unplug, and goes to the new link.
+    device.set_tx(Radio::Radio2).await.unwrap();
+    replug.await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"?NAME\rTX2\r");
+
+    for expected in [
+        SwitchEvent::Connected,
+        SwitchEvent::Reconnecting,
+        SwitchEvent::Reconnected,
+    ] {
+        let event = tokio::time::timeout(Duration::from_secs(1), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event, expected);
+    }
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn stats_count_link_activity() {
+    let mock = MockPort::new();
+    mock.queue_read(b"NAMESO2RDUINO\r");
+    let device = OtrspBuilder::new("/dev/mock")
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio2).await.unwrap();
+    mock.queue_read(b"AUX14\r");
+    assert_eq!(device.query_aux(1).await.unwrap(), 4);
+    // No answer: times out.
+    assert!(device.query_aux(2).await.is_err());
+
+    let stats = device.stats();
+    assert_eq!(stats.bytes_sent, b"?NAME\rTX2\r?AUX1\r?AUX2\r".len() as u64);
+    assert_eq!(stats.bytes_received, b"NAMESO2RDUINO\rAUX14\r".len() as u64);
+    assert_eq!(stats.commands, 4);
+    assert_eq!(stats.queries, 3);
+    assert_eq!(stats.errors, 1);
+    assert_eq!(stats.timeouts, 1);
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn build_from_connector() {
+    use otrsp::transport::{BoxedTransport, Connector};
+
+    struct Unplugged;
+
+    #[async_trait::async_trait]
+    impl Connector for Unplugged {
+        async fn connect(&self) -> otrsp::Result<BoxedTransport> {
+            Err(Error::Transport("switch unplugged".into()))
+        }
+    }
+
+    let result = OtrspBuilder::from_connector("usb-hid", Unplugged)
+        .build()
+        .await;
+    assert!(matches!(result, Err(Error::Transport(_))));
+
+    let mock = MockPort::new();
+    mock.queue_read(b"NAMESO2RDUINO\r");
+    let device = OtrspBuilder::from_connector("mock", mock.clone())
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(device.info().name, "SO2RDUINO");
+    assert_eq!(device.info().port.as_deref(), Some("mock"));
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"?NAME\rTX2\r");
+
+    device.close().await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn pty_transport() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let pty = otrsp::transport::open_pty().unwrap();
+    assert!(pty.path.starts_with("/dev/"));
+    let mut switch = pty.switch;
+    let simulator = tokio::spawn(async move {
+        let mut buf = [0u8; 6];
+        switch.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"?NAME\r");
+        switch.write_all(b"NAMESO2RDUINO\r").await.unwrap();
+        let mut buf = [0u8; 4];
+        switch.read_exact(&mut buf).await.unwrap();
+        buf
+    });
+
+    let device = OtrspBuilder::new(&pty.path)
+        .build_with_port(pty.port)
+        .await
+        .unwrap();
+    assert_eq!(device.info().name, "SO2RDUINO");
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert_eq!(&simulator.await.unwrap(), b"TX2\r");
+
+    device.close().await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn rfcomm_builder_reconnects_serial_link() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // A pseudo-terminal stands in for /dev/rfcomm0.
+    let pty = otrsp::transport::open_pty().unwrap();
+    let mut switch = pty.switch;
+    let answer = tokio::spawn(async move {
+        let mut buf = [0u8; 6];
+        switch.read_exact(&mut buf).await.unwrap();
+        switch.write_all(b"NAMESO2RDUINO\r").await.unwrap();
+        switch
+    });
+    let device = OtrspBuilder::new_rfcomm(&pty.path)
+        .reconnect_delay(std::time::Duration::from_millis(20))
+        .reconnect_attempts(1)
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(device.info().name, "SO2RDUINO");
+    let mut events = device.subscribe_connection();
+    assert_eq!(events.recv().await.unwrap(), SwitchEvent::Connected);
+
+    // Closing the far end hangs up the link; the next write notices.
+    drop(answer.await.unwrap());
+    let event = tokio::time::timeout(std::time::Duration::from_secs(2), async {
+        tokio::select! {
+            _ = device.set_tx(Radio::Radio2) => None,
+            event = events.recv() => event.ok(),
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(event, Some(SwitchEvent::Reconnecting));
+
+    // The far end is gone for good, so the one reconnect attempt fails and
+    // the stalled write gives up.
+    assert!(device.set_tx(Radio::Radio1).await.is_err());
+    device.close().await.unwrap();
+    drop(pty.port);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn serial_reconnect_reapplies_configure_serial() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    let pty = otrsp::transport::open_pty().unwrap();
+    let opens = Arc::new(AtomicUsize::new(0));
+    let counter = opens.clone();
+    let device = OtrspBuilder::new(&pty.path)
+        .query_name(false)
+        .reconnect(true)
+        .reconnect_delay(Duration::from_millis(20))
+        .keepalive(Duration::from_millis(50))
+        .query_timeout(Duration::from_millis(50))
+        .configure_serial(move |serial| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            serial
+        })
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(opens.load(Ordering::Relaxed), 1);
+
+    // The far end never answers the keepalive, so the link is reopened.
+    let mut events = device.subscribe_connection();
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while events.recv().await.unwrap() != SwitchEvent::Reconnected {}
+    })
+    .await
+    .unwrap();
+    assert_eq!(opens.load(Ordering::Relaxed), 2);
+
+    device.close().await.unwrap();
+    drop(pty);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn exclusive_open_reports_port_in_use() {
+    let pty = otrsp::transport::open_pty().unwrap();
+    let open = |exclusive| {
+        OtrspBuilder::new(&pty.path)
+            .query_name(false)
+            .exclusive(exclusive)
+            .build()
+    };
+
+    let first = open(true).await.unwrap();
+    match open(true).await {
+        Err(Error::Transport(msg)) => assert!(msg.contains("in use"), "{msg}"),
+        Err(e) => panic!("expected port-in-use error, got {e:?}"),
+        Ok(_) => panic!("second exclusive open succeeded"),
+    }
+    first.close().await.unwrap();
+
+    let first = open(false).await.unwrap();
+    let second = open(false).await.unwrap();
+    first.close().await.unwrap();
+    second.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn rfc2217_transport_configures_far_end() {
+    use otrsp::rfc2217::Rfc2217Settings;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut received = Vec::new();
+        while !received.ends_with(b"?NAME\r") {
+            let mut buf = [0u8; 64];
+            let n = socket.read(&mut buf).await.unwrap();
+            assert!(n > 0);
+            received.extend_from_slice(&buf[..n]);
+        }
+        // Acknowledge the options and the baud rate, then answer.
+        socket
+            .write_all(&[255, 253, 44, 255, 251, 3])
+            .await
+            .unwrap();
+        socket
+            .write_all(&[255, 250, 44, 101, 0, 0, 0x4B, 0, 255, 240])
+            .await
+            .unwrap();
+        socket.write_all(b"NAMESO2RDUINO\r").await.unwrap();
+        let mut buf = [0u8; 4];
+        socket.read_exact(&mut buf).await.unwrap();
+        (received, buf)
+    });
+
+    let device = OtrspBuilder::new_rfc2217("127.0.0.1", port)
+        .rfc2217_settings(Rfc2217Settings {
+            baud_rate: 19200,
+            dtr: true,
+            rts: false,
+        })
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(device.info().name, "SO2RDUINO");
+    assert_eq!(device.info().baud_rate, Some(19200));
+    device.set_tx(Radio::Radio2).await.unwrap();
+
+    let (setup, tx) = server.await.unwrap();
+    assert_eq!(&tx, b"TX2\r");
+    // IAC WILL COM-PORT-OPTION, then SET-BAUDRATE 19200 and SET-CONTROL DTR on.
+    let contains = |needle: &[u8]| setup.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(&[255, 251, 44]));
+    assert!(contains(&[255, 250, 44, 1, 0, 0, 0x4B, 0, 255, 240]));
+    assert!(contains(&[255, 250, 44, 5, 8, 255, 240]));
+    assert!(contains(&[255, 250, 44, 5, 12, 255, 240]));
+
+    device.close().await.unwrap();
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn tls_transport_verifies_server() {
+    use otrsp::transport::rustls;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // A private CA and a server certificate it signed for "localhost".
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let server_key = KeyPair::generate().unwrap();
+    let server_cert = CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .signed_by(&server_key, &ca, &ca_key)
+        .unwrap();
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let server_config = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![server_cert.der().clone()],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(server_key.serialize_der())),
+        )
+        .unwrap();
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+
+    // Answers ?NAME on every connection that completes the handshake.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(mut tls) = acceptor.accept(socket).await else {
+                    return;
+                };
+                let mut buf = [0u8; 6];
+                tls.read_exact(&mut buf).await.unwrap();
+                tls.write_all(b"NAMESO2RDUINO\r").await.unwrap();
+                tls.flush().await.unwrap();
+                // Hold the link open until the client hangs up.
+                let _ = tls.read(&mut buf).await;
+            });
+        }
+    });
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(ca.der().clone()).unwrap();
+    let trusting = Arc::new(
+        rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    );
+
+    // Trusted CA and matching name: the link comes up.
+    let device = OtrspBuilder::new_tls("localhost", port)
+        .tls_config(trusting.clone())
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(device.info().name, "SO2RDUINO");
+    device.close().await.unwrap();
+
+    // Trusted CA, but the certificate is not valid for the address.
+    let result = OtrspBuilder::new_tls("127.0.0.1", port)
+        .tls_config(trusting)
+        .build()
+        .await;
+    match result {
+        Err(Error::Transport(msg)) => assert!(msg.contains("TLS handshake"), "{msg}"),
+        Err(e) => panic!("expected handshake failure, got {e:?}"),
+        Ok(_) => panic!("certificate for the wrong name accepted"),
+    }
+
+    // The Mozilla roots do not include the private CA.
+    let result = OtrspBuilder::new_tls("localhost", port).build().await;
+    assert!(matches!(result, Err(Error::Transport(_))));
+
+    let result = OtrspBuilder::new_tls("not a host", port).build().await;
+    assert!(matches!(result, Err(Error::InvalidParameter(_))));
+}
+
+#[tokio::test]
+async fn full_queue_fails_fast() {
+    use otrsp::queue::QueuePolicy;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = Arc::new(
+        OtrspBuilder::new("/dev/mock")
+            .query_name(false)
+            .queue_capacity(1)
+            .queue_policy(QueuePolicy::FailFast)
+            .build_with_port(mock.clone())
+            .await
+            .unwrap(),
+    );
+
+    // An unanswered query holds the IO task; one more command fits in the queue.
+    let d = device.clone();
+    let query = tokio::spawn(async move { d.query_aux(1).await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let d = device.clone();
+    let queued = tokio::spawn(async move { d.set_tx(Radio::Radio2).await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    assert!(device.set_aux(1, 3).await.unwrap_err().is_busy());
+    assert!(query.await.unwrap().unwrap_err().is_timeout());
+    queued.await.unwrap().unwrap();
+    assert_eq!(&mock.written_data()[..], b"?AUX1\rTX2\r");
+}
+
+#[tokio::test]
+async fn full_queue_drops_oldest_rx_update() {
+    use otrsp::queue::QueuePolicy;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = Arc::new(
+        OtrspBuilder::new("/dev/mock")
+            .query_name(false)
+            .queue_capacity(2)
+            .queue_policy(QueuePolicy::DropOldest)
+            .build_with_port(mock.clone())
+            .await
+            .unwrap(),
+    );
+
+    let d = device.clone();
+    let query = tokio::spawn(async move { d.query_aux(1).await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let mut queued = Vec::new();
+    for (radio, mode) in [(Radio::Radio1, RxMode::Mono), (Radio::Radio2, RxMode::Mono)] {
+        let d = device.clone();
+        queued.push(tokio::spawn(async move { d.set_rx(radio, mode).await }));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // The queue is full; the newest routing replaces the oldest queued one.
+    device.set_rx(Radio::Radio1, RxMode::Stereo).await.unwrap();
+    assert!(query.await.unwrap().unwrap_err().is_timeout());
+    for task in queued {
+        task.await.unwrap().unwrap();
+    }
+    assert_eq!(&mock.written_data()[..], b"?AUX1\rRX2\rRX1S\r");
+    assert_eq!(device.state().rx, Some((Radio::Radio1, RxMode::Stereo)));
+}
+
+#[tokio::test]
+async fn transient_write_errors_are_retried() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .write_retry(3, Duration::from_millis(5))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    mock.fail_writes(2, std::io::ErrorKind::WouldBlock);
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"TX2\r");
+
+    // Out of attempts.
+    mock.fail_writes(3, std::io::ErrorKind::WouldBlock);
+    assert!(device.set_tx(Radio::Radio1).await.is_err());
+
+    // Hard errors are not retried.
+    mock.fail_writes(1, std::io::ErrorKind::BrokenPipe);
+    assert!(device.set_tx(Radio::Radio1).await.is_err());
+    device.set_tx(Radio::Radio1).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"TX2\rTX1\r");
+}
+
+#[tokio::test]
+async fn write_retry_resumes_after_partial_write() {
+    use otrsp::protocol::Command;
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .write_retry(3, Duration::from_millis(5))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    // The link takes "TX2" and then times out; the retry sends the rest.
+    mock.break_write(3, std::io::ErrorKind::TimedOut);
+    device
+        .send_batch(&[
+            Command::Tx(Radio::Radio2),
+            Command::Rx(Radio::Radio2, RxMode::Mono),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(&mock.written_data()[..], b"TX2\rRX2\r");
+}
+
+#[tokio::test(start_paused = true)]
+async fn write_retries_outlast_the_reply_timeout() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .write_retry(4, Duration::from_secs(2))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    // Three failures cost 6s of retries, more than the 5s reply timeout.
+    mock.fail_writes(3, std::io::ErrorKind::WouldBlock);
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert_eq!(device.state().tx, Some(Radio::Radio2));
+}
+
+#[tokio::test]
+async fn keepalive_detects_silent_device() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .emit_connected(false)
+        .keepalive(Duration::from_millis(50))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    let mut events = device.subscribe_connection();
+
+    mock.expect(b"?NAME\r").respond(b"NAMESO2RDUINO\r");
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    assert_eq!(&mock.written_data()[..], b"?NAME\r");
+    assert!(events.try_recv().is_err());
+
+    // The next probe goes unanswered.
+    let event = tokio::time::timeout(Duration::from_secs(2), events.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event, SwitchEvent::Disconnected);
+    assert_eq!(&mock.written_data()[..], b"?NAME\r?NAME\r");
+}
+
+#[tokio::test]
+async fn unanswered_keepalive_reconnects() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::from_connector("mock", mock.clone())
+        .query_name(false)
+        .emit_connected(false)
+        .reconnect(true)
+        .reconnect_delay(Duration::from_millis(20))
+        .keepalive(Duration::from_millis(50))
+        .build()
+        .await
+        .unwrap();
+    let mut events = device.subscribe_connection();
+
+    for expected in [SwitchEvent::Reconnecting, SwitchEvent::Reconnected] {
+        let event = tokio::time::timeout(Duration::from_secs(2), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event, expected);
+    }
+    device.set_tx(Radio::Radio2).await.unwrap();
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn metrics_time_each_exchange() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    mock.set_latency(Duration::from_millis(30));
+    mock.expect(b"?NAME\r").respond(b"NAMESO2RDUINO\r");
+    let device = OtrspBuilder::new("/dev/mock")
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert!(device.query_aux(1).await.unwrap_err().is_timeout());
+
+    let metrics = device.metrics();
+    assert_eq!(metrics.succeeded, 2);
+    assert_eq!(metrics.failed, 1);
+    assert_eq!(metrics.writes.count, 1);
+    assert_eq!(metrics.queries.count, 1);
+    let name = metrics.queries.last.unwrap();
+    assert!(name >= Duration::from_millis(30), "{name:?}");
+    assert_eq!(metrics.queries.mean(), Some(name));
+    assert_eq!(metrics.queries.min, metrics.queries.max);
+}
+
+#[tokio::test]
+async fn query_timeout_is_configurable() {
+    use std::time::{Duration, Instant};
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .query_timeout(Duration::from_millis(100))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    let started = Instant::now();
+    assert!(device.query_aux(1).await.unwrap_err().is_timeout());
+    assert!(started.elapsed() < Duration::from_millis(500));
+
+    // A slow link answers within a longer timeout.
+    mock.set_latency(Duration::from_millis(50));
+    mock.expect(b"?AUX1\r").respond(b"AUX14\r");
+    assert_eq!(device.query_aux(1).await.unwrap(), 4);
+}
+
+#[tokio::test]
+async fn close_sends_writes_queued_behind_it() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    // These requests are queued after the shutdown request.
+    let (closed, tx, aux, query) = tokio::join!(
+        device.close(),
+        device.set_tx(Radio::Radio1),
+        device.set_aux(1, 0),
+        device.query_aux(2),
+    );
+    closed.unwrap();
+    tx.unwrap();
+    aux.unwrap();
+    assert!(matches!(query, Err(Error::NotConnected)));
+    assert_eq!(&mock.written_data()[..], b"TX1\rAUX10\r");
+}
+
+#[tokio::test]
+async fn queued_writes_go_out_in_one_write() {
+    use otrsp::tap::WireDirection;
+    use std::sync::{Arc, Mutex};
+
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let log = sent.clone();
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .query_timeout(std::time::Duration::from_millis(100))
+        .on_wire(move |dir, bytes| {
+            if dir == WireDirection::Sent {
+                log.lock().unwrap().push(bytes.to_vec());
+            }
+        })
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    // The unanswered query holds the queue while the writes pile up behind it.
+    let (query, tx, aux, rx) = tokio::join!(
+        device.query_aux(1),
+        device.set_tx(Radio::Radio1),
+        device.set_aux(1, 3),
+        device.set_rx(Radio::Radio2, RxMode::Stereo),
+    );
+    assert!(query.unwrap_err().is_timeout());
+    tx.unwrap();
+    aux.unwrap();
+    rx.unwrap();
+
+    let sent = sent.lock().unwrap().clone();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0], b"?AUX1\r");
+    assert_eq!(sent[1], b"TX1\rAUX13\rRX2S\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn stale_routing_updates_are_dropped() {
+    use otrsp::tap::WireDirection;
+    use std::sync::{Arc, Mutex};
+
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let log = sent.clone();
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .query_timeout(std::time::Duration::from_millis(100))
+        .routing_deadline(std::time::Duration::from_millis(20))
+        .on_wire(move |dir, bytes| {
+            if dir == WireDirection::Sent {
+                log.lock().unwrap().push(bytes.to_vec());
+            }
+        })
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    // Both updates wait behind the unanswered query; only the newer is sent.
+    let (query, first, second) = tokio::join!(
+        device.query_aux(1),
+        device.set_rx(Radio::Radio1, RxMode::Stereo),
+        device.set_rx(Radio::Radio2, RxMode::Stereo),
+    );
+    assert!(query.unwrap_err().is_timeout());
+    first.unwrap();
+    second.unwrap();
+
+    // A late update with nothing newer behind it still goes out.
+    let (query, last) = tokio::join!(
+        device.query_aux(1),
+        device.set_rx(Radio::Radio1, RxMode::Mono),
+    );
+    assert!(query.unwrap_err().is_timeout());
+    last.unwrap();
+
+    let sent = sent.lock().unwrap().concat();
+    assert_eq!(sent, b"?AUX1\rRX2S\r?AUX1\rRX1\r");
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn pending_commands_shows_a_stalled_link() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .query_timeout(Duration::from_millis(200))
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    assert_eq!(device.pending_commands().count, 0);
+    assert_eq!(device.pending_commands().oldest, None);
+
+    let check = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        device.pending_commands()
+    };
+    let (query, tx, pending) =
+        tokio::join!(device.query_aux(1), device.set_tx(Radio::Radio2), check);
+    assert!(query.unwrap_err().is_timeout());
+    tx.unwrap();
+    assert_eq!(pending.count, 2);
+    assert!(pending.oldest.unwrap() >= Duration::from_millis(50));
+    assert_eq!(device.pending_commands().count, 0);
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn scheduled_command_is_sent_when_due() {
+    use otrsp::protocol::Command;
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    let mut events = device.subscribe();
+
+    // Pulse AUX 1 and schedule its release.
+    device.set_aux(1, 1).await.unwrap();
+    let release = Command::Aux { port: 1, value: 0 };
+    device
+        .send_after(release, Duration::from_millis(100))
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"AUX11\rTX2\r");
+    assert_eq!(device.state().aux.get(&1), Some(&1));
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(&mock.written_data()[..], b"AUX11\rTX2\rAUX10\r");
+    assert_eq!(device.state().aux.get(&1), Some(&0));
+    let mut released = false;
+    while let Ok(event) = events.try_recv() {
+        released |= matches!(
+            event,
+            SwitchEvent::AuxChanged {
+                port: 1,
+                value: 0,
+                ..
+            }
+        );
+    }
+    assert!(released);
+
+    let query = device
+        .send_after(Command::QueryAux(1), Duration::from_millis(10))
+        .await;
+    assert!(matches!(query, Err(Error::InvalidParameter(_))));
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn leased_port_is_handed_back_on_drop() {
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    // Talk to the bootloader directly.
+    let mut lease = device.lease_port().await.unwrap();
+    lease.write_all(b"\x30\x20").await.unwrap();
+    mock.queue_read(b"\x14\x10");
+    let mut sync = [0u8; 2];
+    lease.read_exact(&mut sync).await.unwrap();
+    assert_eq!(sync, [0x14, 0x10]);
+
+    // A command sent meanwhile waits for the lease to drop.
+    let release = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(&mock.written_data()[..], b"\x30\x20");
+        drop(lease);
+    };
+    let (tx, ()) = tokio::join!(device.set_tx(Radio::Radio1), release);
+    tx.unwrap();
+    assert_eq!(&mock.written_data()[..], b"\x30\x20TX1\r");
+
+    mock.expect(b"?AUX1\r").respond(b"AUX13\r");
+    assert_eq!(device.query_aux(1).await.unwrap(), 3);
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn hardware_reset_needs_a_dtr_line() {
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+
+    let result = device.reset_hardware().await;
+    assert!(matches!(result, Err(Error::Unsupported(_))));
+    assert!(mock.written_data().is_empty());
+
+    device.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn watch_state_follows_changes_and_link_status() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    let mut watch = device.watch_state();
+    assert!(watch.borrow().connected);
+    assert_eq!(watch.borrow().tx, None);
+
+    device.set_tx(Radio::Radio2).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(1), watch.changed())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(watch.borrow_and_update().tx, Some(Radio::Radio2));
+
+    mock.close();
+    let _ = device.set_tx(Radio::Radio1).await;
+    tokio::time::timeout(Duration::from_secs(1), watch.wait_for(|s| !s.connected))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!device.state().connected);
+}
+
+/// Log output kept in memory, for checking what was traced.
+#[derive(Clone, Default)]
+struct LogCapture(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogCapture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn requests_are_traced_under_their_id() {
+    let log = LogCapture::default();
+    let writer = log.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let mock = MockPort::new();
+    mock.expect(b"?AUX1\r").respond(b"AUX14\r");
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .build_with_port(mock.clone())
+        .await
+        .unwrap();
+    device.set_tx(Radio::Radio1).await.unwrap();
+    assert_eq!(device.query_aux(1).await.unwrap(), 4);
+    device.close().await.unwrap();
+
+    let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+    let traced = |id: &str, what: &str| {
+        log.lines().any(|line| {
+            line.contains(&format!("request{{{id}}}")) && line.contains(&format!(": {what}"))
+        })
+    };
+    assert!(traced("id=1 command=TX1", "submitted"), "{log}");
+    assert!(traced("id=1 command=TX1", "written"), "{log}");
+    assert!(traced("id=1 command=TX1", "completed"), "{log}");
+    assert!(traced("id=2 command=?AUX1", "submitted"), "{log}");
+    assert!(
+        traced("id=2 command=?AUX1", "written, awaiting answer"),
+        "{log}"
+    );
+    assert!(traced("id=2 command=?AUX1", "answered"), "{log}");
+}
+
+/// A port with a bug: writing `TX2` panics inside the IO task.
+struct PanicOnTx2(MockPort);
+
+impl tokio::io::AsyncRead for PanicOnTx2 {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl tokio::io::AsyncWrite for PanicOnTx2 {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        assert!(!buf.starts_with(b"TX2"), "bug on TX2");
+        std::pin::Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[tokio::test]
+async fn io_task_panic_is_reported() {
+    use std::time::Duration;
+
+    let mock = MockPort::new();
+    let device = OtrspBuilder::new("/dev/mock")
+        .query_name(false)
+        .emit_connected(false)
+        .build_with_port(PanicOnTx2(mock.clone()))
+        .await
+        .unwrap();
+    let mut events = device.subscribe_connection();
+
+    assert!(matches!(
+        device.set_tx(Radio::Radio2).await,
+        Err(Error::NotConnected)
+    ));
+    for expected in [SwitchEvent::IoTaskFailed, SwitchEvent::Disconnected] {
+        let event = tokio::time::timeout(Duration::from_secs(1), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event, expected);
+    }
+    assert!(matches!(
+        device.set_tx(Radio::Radio1).await,
+        Err(Error::NotConnected)
+    ));
+}
+
+#[tokio::test]
+async fn io_task_restarts_after_panic() {
+    use otrsp::transport::{BoxedTransport, Connector};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    /// Hands out the buggy port first, then a working one.
+    struct Flaky {
+        mock: MockPort,
+        used: AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl Connector for Flaky {
+        async fn connect(&self) -> otrsp::Result<BoxedTransport> {
+            if self.used.swap(true, Ordering::SeqCst) {
+                Ok(Box::new(self.mock.clone()))
+            } else {
+                Ok(Box::new(PanicOnTx2(self.mock.clone())))
+            }
+        }
+    }
+
+    let mock = MockPort::new();
+    let connector = Flaky {
+        mock: mock.clone(),
+        used: AtomicBool::new(false),
+    };
+    let device = OtrspBuilder::from_connector("flaky", connector)
+        .query_name(false)
+        .emit_connected(false)
+        .reconnect(true)
+        .build()
+        .await
+        .unwrap();
+    let mut events = device.subscribe_connection();
+
+    assert!(device.set_tx(Radio::Radio2).await.is_err());
+    for expected in [SwitchEvent::IoTaskFailed, SwitchEvent::Reconnected] {
+        let event = tokio::time::timeout(Duration::from_secs(1), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event, expected);
+    }
+    device.set_tx(Radio::Radio2).await.unwrap();
+    assert_eq!(&mock.written_data()[..], b"TX2\r");
+
+    device.close().await.unwrap();
+}
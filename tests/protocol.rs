@@ -13,22 +13,22 @@ fn encode_tx_radio2() {
 
 #[test]
 fn encode_rx_all_modes() {
-    assert_eq!(protocol::encode_rx(Radio::Radio1, RxMode::Mono), b"RX1\r");
-    assert_eq!(protocol::encode_rx(Radio::Radio2, RxMode::Mono), b"RX2\r");
+    assert_eq!(protocol::encode_rx(Radio::Radio1, RxMode::Mono).unwrap(), b"RX1\r");
+    assert_eq!(protocol::encode_rx(Radio::Radio2, RxMode::Mono).unwrap(), b"RX2\r");
     assert_eq!(
-        protocol::encode_rx(Radio::Radio1, RxMode::Stereo),
+        protocol::encode_rx(Radio::Radio1, RxMode::Stereo).unwrap(),
         b"RX1S\r"
     );
     assert_eq!(
-        protocol::encode_rx(Radio::Radio2, RxMode::Stereo),
+        protocol::encode_rx(Radio::Radio2, RxMode::Stereo).unwrap(),
         b"RX2S\r"
     );
     assert_eq!(
-        protocol::encode_rx(Radio::Radio1, RxMode::ReverseStereo),
+        protocol::encode_rx(Radio::Radio1, RxMode::ReverseStereo).unwrap(),
         b"RX1R\r"
     );
     assert_eq!(
-        protocol::encode_rx(Radio::Radio2, RxMode::ReverseStereo),
+        protocol::encode_rx(Radio::Radio2, RxMode::ReverseStereo).unwrap(),
         b"RX2R\r"
     );
 }
@@ -66,9 +66,16 @@ fn encode_query_aux_invalid() {
 
 #[test]
 fn encode_raw_appends_cr() {
-    assert_eq!(protocol::encode_raw("HELLO"), b"HELLO\r");
-    assert_eq!(protocol::encode_raw("TX1"), b"TX1\r");
-    assert_eq!(protocol::encode_raw(""), b"\r");
+    assert_eq!(protocol::encode_raw("HELLO").unwrap(), b"HELLO\r");
+    assert_eq!(protocol::encode_raw("TX1").unwrap(), b"TX1\r");
+    assert_eq!(protocol::encode_raw("").unwrap(), b"\r");
+}
+
+#[test]
+fn encode_raw_rejects_embedded_terminators() {
+    assert!(protocol::encode_raw("TX1\rTX2").is_err());
+    assert!(protocol::encode_raw("RX1\n").is_err());
+    assert!(protocol::encode_raw(&"X".repeat(protocol::MAX_COMMAND_LEN + 1)).is_err());
 }
 
 #[test]